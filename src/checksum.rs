@@ -0,0 +1,66 @@
+// Checksums stored in on-disk formats.
+
+const MASK_DELTA: u32 = 0xa282_ead8;
+
+/// CRC32C of 'data'.
+#[inline(always)]
+pub fn crc32c(data: &[u8]) -> u32 {
+    crc32c::crc32c(data)
+}
+
+/// CRC32C of the concatenation of the data 'crc' was computed over and
+/// 'data'.
+#[inline(always)]
+pub fn crc32c_extend(crc: u32, data: &[u8]) -> u32 {
+    crc32c::crc32c_append(crc, data)
+}
+
+/// Returns a masked representation of 'crc'. Computing the CRC of a
+/// string that contains embedded CRCs is problematic, so CRCs are masked
+/// before they are stored. Same as LevelDB.
+#[inline(always)]
+pub fn mask_crc(crc: u32) -> u32 {
+    // Rotate right by 15 bits and add a constant.
+    crc.rotate_right(15).wrapping_add(MASK_DELTA)
+}
+
+/// Returns the CRC whose masked representation is 'masked_crc'.
+#[inline(always)]
+pub fn unmask_crc(masked_crc: u32) -> u32 {
+    masked_crc.wrapping_sub(MASK_DELTA).rotate_left(15)
+}
+
+/// xxHash64 of 'data' with seed 0.
+#[inline(always)]
+pub fn xxhash64(data: &[u8]) -> u64 {
+    xxhash_rust::xxh64::xxh64(data, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::checksum::{crc32c, crc32c_extend, mask_crc, unmask_crc, xxhash64};
+
+    #[test]
+    fn test_crc32c() {
+        // From rfc3720 section B.4.
+        assert_eq!(crc32c(&[0; 32]), 0x8a91_36aa);
+        assert_eq!(crc32c(&[0xff; 32]), 0x62a8_ab43);
+        assert_eq!(crc32c(b"hello world"),
+            crc32c_extend(crc32c(b"hello "), b"world"));
+    }
+
+    #[test]
+    fn test_mask() {
+        let crc = crc32c(b"foo");
+        assert_ne!(crc, mask_crc(crc));
+        assert_ne!(crc, mask_crc(mask_crc(crc)));
+        assert_eq!(crc, unmask_crc(mask_crc(crc)));
+        assert_eq!(crc, unmask_crc(unmask_crc(mask_crc(mask_crc(crc)))));
+    }
+
+    #[test]
+    fn test_xxhash64() {
+        assert_eq!(xxhash64(b""), 0xef46_db37_51d8_e999);
+        assert_ne!(xxhash64(b"foo"), xxhash64(b"bar"));
+    }
+}
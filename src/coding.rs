@@ -0,0 +1,54 @@
+// Helpers to encode and decode the integers used in on-disk formats.
+// Variable length integers use leb128 encoding.
+
+/// Appends the leb128 encoding of 'value' to 'dst'.
+pub fn put_varint(dst: &mut Vec<u8>, value: u64) {
+    leb128::write::unsigned(dst, value).unwrap();
+}
+
+/// Number of bytes needed for the leb128 encoding of 'value'.
+pub fn varint_length(mut value: usize) -> usize {
+    let mut len = 1;
+    while value >= 0x80 {
+        value >>= 7;
+        len += 1;
+    }
+    return len;
+}
+
+/// Decodes a leb128 integer from the start of 'input' and advances it
+/// past the integer. Returns None if 'input' is truncated or the encoding
+/// overflows 64 bits.
+pub fn get_varint(input: &mut &[u8]) -> Option<u64> {
+    leb128::read::unsigned(input).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coding::{get_varint, put_varint, varint_length};
+
+    #[test]
+    fn test_varint_round_trip() {
+        let values = [0, 1, 127, 128, 300, u32::MAX as u64, u64::MAX];
+        let mut buf = vec!();
+        for value in values {
+            let len = buf.len();
+            put_varint(&mut buf, value);
+            assert_eq!(buf.len() - len, varint_length(value as usize));
+        }
+        let mut input = &buf[..];
+        for value in values {
+            assert_eq!(get_varint(&mut input), Some(value));
+        }
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn test_malformed_varint() {
+        // Continuation bit set on the last byte.
+        assert_eq!(get_varint(&mut &[0x81u8, 0x81][..]), None);
+        assert_eq!(get_varint(&mut &[][..]), None);
+        // More than 64 bits.
+        assert_eq!(get_varint(&mut &[0xffu8; 11][..]), None);
+    }
+}
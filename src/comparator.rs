@@ -0,0 +1,159 @@
+use std::cmp::Ordering;
+use crate::sst::lsm_error::DataStoreError;
+
+/// Total order over keys used across the memtable and the SST layer.
+/// The name of the comparator is persisted along with the data, so a
+/// store written with one comparator cannot be opened with another.
+/// Implementations must be thread safe as they are shared by readers
+/// and writers.
+pub trait Comparator: Send + Sync {
+    /// Three way comparison of 'a' and 'b'.
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering;
+
+    /// Name of the comparator. Changing the ordering of an existing
+    /// comparator requires changing its name.
+    fn name(&self) -> &str;
+
+    /// If 'start' < 'limit', changes 'start' to a short key in
+    /// ['start', 'limit'). Used to shorten keys stored in index blocks.
+    /// Leaving 'start' unchanged is a correct implementation.
+    fn find_shortest_separator(&self, start: &mut Vec<u8>, limit: &[u8]);
+
+    /// Changes 'key' to a short key >= 'key'.
+    /// Leaving 'key' unchanged is a correct implementation.
+    fn find_short_successor(&self, key: &mut Vec<u8>);
+}
+
+/// Orders keys lexicographically by their bytes.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BytewiseComparator;
+
+impl Comparator for BytewiseComparator {
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        a.cmp(b)
+    }
+
+    fn name(&self) -> &str {
+        "lsm.BytewiseComparator"
+    }
+
+    fn find_shortest_separator(&self, start: &mut Vec<u8>, limit: &[u8]) {
+        let min_length = start.len().min(limit.len());
+        let diff_index = start.iter().zip(limit.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        if diff_index >= min_length {
+            // One key is a prefix of the other, so it cannot be shortened.
+            return;
+        }
+        let diff_byte = start[diff_index];
+        if diff_byte < 0xff && diff_byte + 1 < limit[diff_index] {
+            start[diff_index] += 1;
+            start.truncate(diff_index + 1);
+            debug_assert!(self.compare(start, limit) == Ordering::Less);
+        }
+    }
+
+    fn find_short_successor(&self, key: &mut Vec<u8>) {
+        // Find the first byte that can be incremented and drop the rest.
+        if let Some(i) = key.iter().position(|byte| *byte != 0xff) {
+            key[i] += 1;
+            key.truncate(i + 1);
+        }
+        // 'key' is a run of 0xff bytes. Leave it alone.
+    }
+}
+
+/// Fails unless 'comparator' is the one named 'persisted_name', which is
+/// the name recorded when the data was written.
+pub fn check_comparator_name(comparator: &dyn Comparator,
+    persisted_name: &str) -> Result<(), DataStoreError> {
+    if comparator.name() != persisted_name {
+        return Err(DataStoreError::InvalidHeader {
+            expected: comparator.name().to_string(),
+            found: persisted_name.to_string(),
+        });
+    }
+    return Ok(());
+}
+
+/// Orders keys bytewise descending. Lets tests check that the comparator
+/// of the options is honored.
+#[cfg(test)]
+pub(crate) struct ReverseComparator;
+
+#[cfg(test)]
+impl Comparator for ReverseComparator {
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        b.cmp(a)
+    }
+
+    fn name(&self) -> &str {
+        "test.ReverseComparator"
+    }
+
+    fn find_shortest_separator(&self, _start: &mut Vec<u8>, _limit: &[u8]) {}
+
+    fn find_short_successor(&self, _key: &mut Vec<u8>) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+    use crate::comparator::{check_comparator_name, BytewiseComparator,
+        Comparator};
+    use crate::sst::lsm_error::DataStoreError;
+
+    fn separator(start: &[u8], limit: &[u8]) -> Vec<u8> {
+        let mut start = start.to_vec();
+        BytewiseComparator.find_shortest_separator(&mut start, limit);
+        return start;
+    }
+
+    fn successor(key: &[u8]) -> Vec<u8> {
+        let mut key = key.to_vec();
+        BytewiseComparator.find_short_successor(&mut key);
+        return key;
+    }
+
+    #[test]
+    fn test_bytewise_compare() {
+        let c = BytewiseComparator;
+        assert_eq!(c.compare(b"abc", b"abd"), Ordering::Less);
+        assert_eq!(c.compare(b"abc", b"ab"), Ordering::Greater);
+        assert_eq!(c.compare(b"", b""), Ordering::Equal);
+        assert_eq!(c.compare(&[0xff], &[0x01, 0x02]), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_find_shortest_separator() {
+        assert_eq!(separator(b"abcdefgh", b"abzzz"), b"abd");
+        // Differing bytes are adjacent, so nothing can be shortened.
+        assert_eq!(separator(b"abc1xyz", b"abc2"), b"abc1xyz");
+        // Prefix of limit.
+        assert_eq!(separator(b"abc", b"abcdef"), b"abc");
+        assert_eq!(separator(&[0x01, 0xff, 0x10], &[0x01, 0xff, 0x20]),
+            vec!(0x01, 0xff, 0x11));
+    }
+
+    #[test]
+    fn test_find_short_successor() {
+        assert_eq!(successor(b"abcd"), b"b");
+        assert_eq!(successor(&[0xff, 0xff, 0x10, 0x20]), vec!(0xff, 0xff, 0x11));
+        assert_eq!(successor(&[0xff, 0xff]), vec!(0xff, 0xff));
+        assert_eq!(successor(b""), b"");
+    }
+
+    #[test]
+    fn test_check_comparator_name() {
+        assert!(check_comparator_name(&BytewiseComparator,
+            "lsm.BytewiseComparator").is_ok());
+        match check_comparator_name(&BytewiseComparator, "other.Comparator") {
+            Err(DataStoreError::InvalidHeader { expected, found }) => {
+                assert_eq!(expected, "lsm.BytewiseComparator");
+                assert_eq!(found, "other.Comparator");
+            }
+            _ => panic!("Expected InvalidHeader error"),
+        }
+    }
+}
@@ -0,0 +1,41 @@
+use std::sync::Arc;
+use crate::db::filename::{table_file_name, temp_file_name};
+use crate::filesystem::Path;
+use crate::iterator::InternalIterator;
+use crate::sst::lsm_error::DataStoreError;
+use crate::sst::options::TableOptions;
+use crate::sst::table_builder::TableBuilder;
+
+/// Writes the entries of 'iter' and the range deletions of
+/// 'range_del_iter' to the table numbered 'number' in 'dbname'. The table
+/// is written to a temporary file which is synced and then renamed, so a
+/// table file is always complete.
+pub fn build_table(dbname: &Arc<dyn Path>, options: &TableOptions,
+    iter: &mut dyn InternalIterator, range_del_iter: &mut dyn InternalIterator,
+    number: u64) -> Result<(), DataStoreError> {
+    let temp_path = dbname.join(&temp_file_name(number));
+    let mut builder = TableBuilder::new(options.clone(), temp_path.as_ref())?;
+    iter.seek_to_first()?;
+    while iter.valid() {
+        builder.add(iter.key(), iter.value())?;
+        iter.next()?;
+    }
+    range_del_iter.seek_to_first()?;
+    while range_del_iter.valid() {
+        builder.add_range_deletion(range_del_iter.key(), range_del_iter.value())?;
+        range_del_iter.next()?;
+    }
+    return finish_table(dbname, &mut builder, number);
+}
+
+/// Finishes 'builder', writing the temporary file of the table numbered
+/// 'number', then syncs the file and renames it to the name of the table.
+pub fn finish_table(dbname: &Arc<dyn Path>, builder: &mut TableBuilder, number: u64)
+    -> Result<(), DataStoreError> {
+    builder.finish()?;
+    let temp_path = dbname.join(&temp_file_name(number));
+    let fs = dbname.get_file_system();
+    fs.sync(temp_path.as_ref())?;
+    fs.rename(temp_path.as_ref(), dbname.join(&table_file_name(number)).as_ref())?;
+    return Ok(());
+}
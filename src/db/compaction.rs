@@ -0,0 +1,161 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::sync::Arc;
+use crate::comparator::Comparator;
+use crate::db::version_edit::{FileMetaData, VersionEdit};
+use crate::db::version_set::{Version, NUM_LEVELS};
+use crate::memtable::internal_key::{InternalKey, InternalKeyComparator};
+
+/// Tables of a level taking part in a compaction.
+#[derive(Clone, Debug)]
+pub struct CompactionInputs {
+    pub level: usize,
+    pub files: Vec<Arc<FileMetaData>>,
+}
+
+/// Compaction picked by a CompactionPicker: the tables merged, and the
+/// level the result is written to.
+pub struct Compaction {
+    /// Version the inputs were picked from. Keeps them from being deleted
+    /// while they are read.
+    input_version_: Arc<Version>,
+    inputs_: Vec<CompactionInputs>,
+    output_level_: usize,
+    max_output_file_size_: u64,
+    /// Whether no table outside the inputs holds older entries of their
+    /// keys, so that deletions can be dropped.
+    bottommost_: bool,
+    /// Whether the inputs are deleted without writing any output.
+    deletion_: bool,
+}
+
+impl Compaction {
+    /// Compaction of 'inputs' into 'output_level'. Inputs with no files are
+    /// dropped.
+    pub fn new(input_version: Arc<Version>, inputs: Vec<CompactionInputs>,
+        output_level: usize, max_output_file_size: u64,
+        comparator: &InternalKeyComparator) -> Compaction {
+        let inputs: Vec<CompactionInputs> = inputs.into_iter()
+            .filter(|inputs| !inputs.files.is_empty())
+            .collect();
+        let mut compaction = Compaction {
+            input_version_: input_version,
+            inputs_: inputs,
+            output_level_: output_level,
+            max_output_file_size_: max_output_file_size,
+            bottommost_: false,
+            deletion_: false,
+        };
+        compaction.bottommost_ = compaction.compute_bottommost(comparator);
+        return compaction;
+    }
+
+    /// Compaction deleting the tables of 'inputs', their entries being
+    /// dropped.
+    pub fn deletion(input_version: Arc<Version>, inputs: CompactionInputs) -> Compaction {
+        Compaction {
+            input_version_: input_version,
+            output_level_: inputs.level,
+            inputs_: vec!(inputs),
+            max_output_file_size_: 0,
+            bottommost_: false,
+            deletion_: true,
+        }
+    }
+
+    /// Tables of the output level and of the levels below it which are not
+    /// inputs may hold older entries. Level 0 tables outside of the inputs
+    /// are only older when the output level is 0.
+    fn compute_bottommost(&self, comparator: &InternalKeyComparator) -> bool {
+        let (smallest, largest) = match self.key_range(comparator) {
+            Some(range) => range,
+            None => return true,
+        };
+        let user_comparator = comparator.user_comparator();
+        let inputs: HashSet<u64> = self.inputs_.iter()
+            .flat_map(|inputs| inputs.files.iter().map(|file| file.number))
+            .collect();
+        return (self.output_level_..NUM_LEVELS).all(|level| self.input_version_
+            .overlapping_files(level, smallest.user_key(), largest.user_key(),
+                user_comparator.as_ref())
+            .iter()
+            .all(|file| inputs.contains(&file.number)));
+    }
+
+    /// Smallest and largest internal keys of the inputs.
+    pub fn key_range(&self, comparator: &InternalKeyComparator)
+        -> Option<(InternalKey, InternalKey)> {
+        return key_range(self.inputs_.iter().flat_map(|inputs| inputs.files.iter()),
+            comparator);
+    }
+
+    #[inline(always)]
+    pub fn input_version(&self) -> &Arc<Version> {
+        &self.input_version_
+    }
+
+    #[inline(always)]
+    pub fn inputs(&self) -> &[CompactionInputs] {
+        &self.inputs_
+    }
+
+    pub fn num_input_files(&self) -> usize {
+        self.inputs_.iter().map(|inputs| inputs.files.len()).sum()
+    }
+
+    #[inline(always)]
+    pub fn output_level(&self) -> usize {
+        self.output_level_
+    }
+
+    #[inline(always)]
+    pub fn max_output_file_size(&self) -> u64 {
+        self.max_output_file_size_
+    }
+
+    #[inline(always)]
+    pub fn is_bottommost(&self) -> bool {
+        self.bottommost_
+    }
+
+    #[inline(always)]
+    pub fn is_deletion(&self) -> bool {
+        self.deletion_
+    }
+
+    /// Records the deletion of the inputs in 'edit'.
+    pub fn add_input_deletions(&self, edit: &mut VersionEdit) {
+        for inputs in self.inputs_.iter() {
+            for file in inputs.files.iter() {
+                edit.delete_file(inputs.level, file.number);
+            }
+        }
+    }
+}
+
+/// Smallest and largest internal keys of 'files', or None if there are no
+/// files.
+pub fn key_range<'a, I>(files: I, comparator: &InternalKeyComparator)
+    -> Option<(InternalKey, InternalKey)>
+    where I: IntoIterator<Item = &'a Arc<FileMetaData>> {
+    let mut range: Option<(InternalKey, InternalKey)> = None;
+    for file in files {
+        range = Some(match range {
+            None => (file.smallest.clone(), file.largest.clone()),
+            Some((smallest, largest)) => (
+                if comparator.compare(file.smallest.encode(), smallest.encode())
+                    == Ordering::Less {
+                    file.smallest.clone()
+                } else {
+                    smallest
+                },
+                if comparator.compare(file.largest.encode(), largest.encode())
+                    == Ordering::Greater {
+                    file.largest.clone()
+                } else {
+                    largest
+                }),
+        });
+    }
+    return range;
+}
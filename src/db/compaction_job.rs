@@ -0,0 +1,264 @@
+use std::cmp::Ordering;
+use std::sync::Arc;
+use crate::comparator::Comparator;
+use crate::db::builder::finish_table;
+use crate::db::compaction::Compaction;
+use crate::db::filename::temp_file_name;
+use crate::db::table_cache::TableCache;
+use crate::db::version_edit::FileMetaData;
+use crate::filesystem::Path;
+use crate::iterator::{InternalIterator, MergingIterator};
+use crate::memtable::internal_key::{append_internal_key, parse_internal_key, InternalKeyComparator,
+    ValueType};
+use crate::memtable::range_tombstone::{FragmentedRangeTombstones, RangeTombstoneCursor};
+use crate::merge_operator::MergeOperator;
+use crate::sst::lsm_error::DataStoreError;
+use crate::sst::options::{ReadOptions, TableOptions};
+use crate::sst::table::Table;
+use crate::sst::table_builder::TableBuilder;
+
+/// Runs a Compaction: merges the entries of its inputs and writes the
+/// entries still needed to new tables, cut at the target file size.
+/// Dropped are the entries shadowed by a newer entry of the same key and
+/// the entries covered by a newer range deletion. Merge operands are
+/// combined with the value they apply to. When the compaction is
+/// bottommost, deletions and range deletions are dropped too.
+pub struct CompactionJob<'a> {
+    compaction_: &'a Compaction,
+    dbname_: &'a Arc<dyn Path>,
+    table_options_: TableOptions,
+    comparator_: &'a InternalKeyComparator,
+    merge_operator_: Option<&'a Arc<dyn MergeOperator>>,
+    table_cache_: &'a TableCache,
+}
+
+impl<'a> CompactionJob<'a> {
+    /// 'table_options' are the options of the tables of the DB.
+    pub fn new(compaction: &'a Compaction, dbname: &'a Arc<dyn Path>,
+        table_options: &TableOptions, comparator: &'a InternalKeyComparator,
+        merge_operator: Option<&'a Arc<dyn MergeOperator>>, table_cache: &'a TableCache)
+        -> CompactionJob<'a> {
+        let table_options = if compaction.is_bottommost() {
+            table_options.for_bottommost_level()
+        } else {
+            table_options.clone()
+        };
+        CompactionJob {
+            compaction_: compaction,
+            dbname_: dbname,
+            table_options_: table_options,
+            comparator_: comparator,
+            merge_operator_: merge_operator,
+            table_cache_: table_cache,
+        }
+    }
+
+    /// Writes the output tables, numbered by 'new_file_number', and
+    /// returns them.
+    pub fn run(&self, new_file_number: &mut dyn FnMut() -> u64)
+        -> Result<Vec<FileMetaData>, DataStoreError> {
+        let mut tables: Vec<Arc<Table>> = vec!();
+        for inputs in self.compaction_.inputs() {
+            for file in inputs.files.iter() {
+                tables.push(self.table_cache_.get(file.number)?);
+            }
+        }
+        let fragments = FragmentedRangeTombstones::new(
+            self.comparator_.user_comparator().clone(),
+            tables.iter().flat_map(|table| table.range_deletions().iter()
+                .map(|(begin, end)| (&begin[..], &end[..]))))?;
+        // The outputs are as old as the oldest entries they hold.
+        let creation_time = self.compaction_.inputs().iter()
+            .flat_map(|inputs| inputs.files.iter().map(|file| file.creation_time))
+            .filter(|creation_time| *creation_time > 0)
+            .min();
+        let read_options = ReadOptions::default();
+        let children = tables.iter()
+            .map(|table| Box::new(table.iter(&read_options)) as Box<dyn InternalIterator>)
+            .collect();
+        let mut input = CompactionIterator {
+            input_: MergingIterator::new(Arc::new(self.comparator_.clone()), children),
+            user_comparator_: self.comparator_.user_comparator().as_ref(),
+            merge_operator_: self.merge_operator_,
+            bottommost_: self.compaction_.is_bottommost(),
+            tombstones_: fragments.cursor(),
+            user_key_: vec!(),
+            entries_: vec!(),
+        };
+        input.input_.seek_to_first()?;
+        let mut has_key = input.next_key()?;
+        let keep_tombstones = !self.compaction_.is_bottommost() && !fragments.is_empty();
+
+        let mut outputs = vec!();
+        // Each output holds the user keys from 'lower', inclusive, to the
+        // first user key of the next output. A user key is never split
+        // across outputs.
+        let mut lower: Option<Vec<u8>> = None;
+        while has_key || (outputs.is_empty() && keep_tombstones) {
+            let number = new_file_number();
+            let temp_path = self.dbname_.join(&temp_file_name(number));
+            let mut builder = TableBuilder::new(self.table_options_.clone(),
+                temp_path.as_ref())?;
+            if let Some(creation_time) = creation_time {
+                builder.set_creation_time(creation_time);
+            }
+            while has_key {
+                for (key, value) in input.entries_.iter() {
+                    builder.add(key, value)?;
+                }
+                has_key = input.next_key()?;
+                if builder.file_size() >= self.compaction_.max_output_file_size() {
+                    break;
+                }
+            }
+            let upper = if has_key {
+                Some(input.user_key_.clone())
+            } else {
+                None
+            };
+            if keep_tombstones {
+                add_tombstones(&mut builder, &fragments, lower.as_deref(), upper.as_deref())?;
+            }
+            finish_table(self.dbname_, &mut builder, number)?;
+            lower = upper;
+            let table = self.table_cache_.get(number)?;
+            outputs.push(FileMetaData::from_table(number, &table, self.comparator_)?);
+        }
+        return Ok(outputs);
+    }
+
+}
+
+/// Adds the parts of 'fragments' from 'lower' to 'upper' to 'builder', a
+/// range deletion per fragment and sequence number, in key order. Missing
+/// bounds are unbounded.
+fn add_tombstones(builder: &mut TableBuilder, fragments: &FragmentedRangeTombstones,
+    lower: Option<&[u8]>, upper: Option<&[u8]>) -> Result<(), DataStoreError> {
+    for (begin, end, sequences) in fragments.clipped(lower, upper) {
+        for sequence in sequences {
+            let mut key = vec!();
+            append_internal_key(&mut key, begin, *sequence, ValueType::RangeDeletion);
+            builder.add_range_deletion(&key, end)?;
+        }
+    }
+    return Ok(());
+}
+
+fn malformed_key() -> DataStoreError {
+    DataStoreError::MalformedBlock {
+        offset: 0,
+        reason: "malformed internal key".to_string(),
+    }
+}
+
+/// Reads the merged inputs of a compaction one user key at a time, and
+/// computes the entries of the key to be written.
+struct CompactionIterator<'a> {
+    input_: MergingIterator<'a>,
+    user_comparator_: &'a dyn Comparator,
+    merge_operator_: Option<&'a Arc<dyn MergeOperator>>,
+    bottommost_: bool,
+    /// Range deletions of the inputs, looked up as the user keys increase.
+    tombstones_: RangeTombstoneCursor<'a>,
+    /// Current user key.
+    user_key_: Vec<u8>,
+    /// Entries of the current user key to be written, newest first.
+    entries_: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl CompactionIterator<'_> {
+    /// Moves to the next user key with entries to be written. Returns
+    /// false at the end of the inputs.
+    fn next_key(&mut self) -> Result<bool, DataStoreError> {
+        while self.input_.valid() {
+            self.read_key()?;
+            if !self.entries_.is_empty() {
+                return Ok(true);
+            }
+        }
+        return Ok(false);
+    }
+
+    /// Consumes the entries of the next user key, newest first, and sets
+    /// the entries to be written.
+    fn read_key(&mut self) -> Result<(), DataStoreError> {
+        let parsed = parse_internal_key(self.input_.key()).ok_or_else(malformed_key)?;
+        self.user_key_ = parsed.user_key.to_vec();
+        self.entries_.clear();
+        let tombstone = self.tombstones_.max_covering_tombstone_sequence(&self.user_key_);
+        // Merge operands not yet combined, newest first.
+        let mut operands: Vec<(Vec<u8>, Vec<u8>)> = vec!();
+        // Set once older entries of the key are not needed.
+        let mut done = false;
+        while self.input_.valid() {
+            let parsed = parse_internal_key(self.input_.key()).ok_or_else(malformed_key)?;
+            if self.user_comparator_.compare(parsed.user_key, &self.user_key_)
+                != Ordering::Equal {
+                break;
+            }
+            let (sequence, value_type) = (parsed.sequence, parsed.value_type);
+            if !done && tombstone.is_some_and(|tombstone| sequence < tombstone) {
+                // Deleted, like all older entries.
+                self.merge(&mut operands, None);
+                done = true;
+            }
+            if !done {
+                let key = self.input_.key().to_vec();
+                let value = self.input_.value().to_vec();
+                match value_type {
+                    ValueType::Value => {
+                        if !self.merge(&mut operands, Some(&value)) {
+                            self.entries_.push((key, value));
+                        }
+                        done = true;
+                    }
+                    ValueType::Deletion => {
+                        // Needed to hide older entries of other tables,
+                        // unless the compaction is bottommost.
+                        if !self.merge(&mut operands, None) && !self.bottommost_ {
+                            self.entries_.push((key, value));
+                        }
+                        done = true;
+                    }
+                    ValueType::Merge => operands.push((key, value)),
+                    ValueType::RangeDeletion => {}
+                }
+            }
+            self.input_.next()?;
+        }
+        if !done {
+            if self.bottommost_ {
+                // The key has no older entries.
+                self.merge(&mut operands, None);
+            } else {
+                self.entries_.append(&mut operands);
+            }
+        }
+        return Ok(());
+    }
+
+    /// Writes the merge 'operands' of the key, which apply to 'base'. With
+    /// a merge operator, they are combined with 'base' into a value and
+    /// true is returned: the entry of 'base' is not needed. Otherwise they
+    /// are written as they are.
+    fn merge(&mut self, operands: &mut Vec<(Vec<u8>, Vec<u8>)>, base: Option<&[u8]>) -> bool {
+        if operands.is_empty() {
+            return false;
+        }
+        let merge_operator = match self.merge_operator_ {
+            Some(merge_operator) => merge_operator,
+            None => {
+                self.entries_.append(operands);
+                return false;
+            }
+        };
+        let sequence = parse_internal_key(&operands[0].0).unwrap().sequence;
+        let values: Vec<&[u8]> = operands.iter().rev().map(|(_, value)| &value[..]).collect();
+        let value = merge_operator.full_merge(&self.user_key_, base, &values);
+        let mut key = vec!();
+        append_internal_key(&mut key, &self.user_key_, sequence, ValueType::Value);
+        self.entries_.push((key, value));
+        operands.clear();
+        return true;
+    }
+}
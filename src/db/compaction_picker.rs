@@ -0,0 +1,365 @@
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::db::compaction::{key_range, Compaction, CompactionInputs};
+use crate::db::options::{CompactionOptionsFifo, CompactionOptionsUniversal, CompactionStyle,
+    Options};
+use crate::db::version_edit::FileMetaData;
+use crate::db::version_set::{Version, NUM_LEVELS};
+use crate::memtable::internal_key::InternalKeyComparator;
+
+/// Decides when and what to compact. Every compaction style has its own
+/// picker.
+pub trait CompactionPicker: Send + Sync {
+    /// Whether 'version' should be compacted.
+    fn needs_compaction(&self, version: &Version) -> bool;
+
+    /// Picks the next compaction of 'version', if it needs one.
+    fn pick_compaction(&self, version: &Arc<Version>) -> Option<Compaction>;
+}
+
+/// Leveled compaction. Each level from 1 on holds non overlapping tables,
+/// and has a target size growing by 'max_bytes_for_level_multiplier' from
+/// level to level. Level 0 is compacted into level 1 once it has
+/// 'level0_file_num_compaction_trigger' tables, a level above its target
+/// size into the next level.
+pub struct LevelCompactionPicker {
+    comparator_: InternalKeyComparator,
+    level0_file_num_compaction_trigger_: usize,
+    max_bytes_for_level_base_: u64,
+    max_bytes_for_level_multiplier_: f64,
+    target_file_size_base_: u64,
+}
+
+impl LevelCompactionPicker {
+    pub fn new(options: &Options) -> LevelCompactionPicker {
+        LevelCompactionPicker {
+            comparator_: InternalKeyComparator::new(options.comparator.clone()),
+            level0_file_num_compaction_trigger_: options.level0_file_num_compaction_trigger,
+            max_bytes_for_level_base_: options.max_bytes_for_level_base,
+            max_bytes_for_level_multiplier_: options.max_bytes_for_level_multiplier,
+            target_file_size_base_: options.target_file_size_base,
+        }
+    }
+
+    /// Target size of 'level', from level 1 on.
+    pub fn max_bytes_for_level(&self, level: usize) -> f64 {
+        assert!(level >= 1, "Level 0 has no target size");
+        return self.max_bytes_for_level_base_ as f64
+            * self.max_bytes_for_level_multiplier_.powi(level as i32 - 1);
+    }
+
+    /// Level with the highest compaction score, and its score. A level
+    /// needs a compaction when its score is at least 1. The last level is
+    /// never compacted.
+    pub fn compaction_score(&self, version: &Version) -> (usize, f64) {
+        let mut best = (0, version.num_files(0) as f64
+            / self.level0_file_num_compaction_trigger_.max(1) as f64);
+        for level in 1..NUM_LEVELS - 1 {
+            let score = version.level_size(level) as f64 / self.max_bytes_for_level(level);
+            if score > best.1 {
+                best = (level, score);
+            }
+        }
+        return best;
+    }
+}
+
+impl CompactionPicker for LevelCompactionPicker {
+    fn needs_compaction(&self, version: &Version) -> bool {
+        self.compaction_score(version).1 >= 1.0
+    }
+
+    fn pick_compaction(&self, version: &Arc<Version>) -> Option<Compaction> {
+        let (level, score) = self.compaction_score(version);
+        if score < 1.0 {
+            return None;
+        }
+        let user_comparator = self.comparator_.user_comparator();
+        let files = if level == 0 {
+            // Level 0 tables may overlap, they are all compacted at once.
+            version.files(0).to_vec()
+        } else {
+            // The table overlapping the least with the next level, relative
+            // to its size, costs the least to compact.
+            let overlap_ratio = |file: &FileMetaData| {
+                let overlap: u64 = version.overlapping_files(level + 1, file.smallest.user_key(),
+                    file.largest.user_key(), user_comparator.as_ref())
+                    .iter().map(|file| file.file_size).sum();
+                overlap as f64 / file.file_size.max(1) as f64
+            };
+            let file = version.files(level).iter()
+                .min_by(|a, b| overlap_ratio(a).total_cmp(&overlap_ratio(b)))?;
+            vec!(file.clone())
+        };
+        let (smallest, largest) = key_range(files.iter(), &self.comparator_)?;
+        let next_files = version.overlapping_files(level + 1, smallest.user_key(),
+            largest.user_key(), user_comparator.as_ref());
+        return Some(Compaction::new(version.clone(), vec!(
+            CompactionInputs { level, files },
+            CompactionInputs { level: level + 1, files: next_files },
+        ), level + 1, self.target_file_size_base_, &self.comparator_));
+    }
+}
+
+/// Tables read as one: a level 0 table, or all the tables of a level from
+/// level 1 on.
+struct SortedRun {
+    level: usize,
+    files: Vec<Arc<FileMetaData>>,
+    size: u64,
+}
+
+/// Sorted runs of 'version', newest first.
+fn sorted_runs(version: &Version) -> Vec<SortedRun> {
+    let mut runs: Vec<SortedRun> = version.files(0).iter()
+        .map(|file| SortedRun {
+            level: 0,
+            files: vec!(file.clone()),
+            size: file.file_size,
+        })
+        .collect();
+    for level in 1..NUM_LEVELS {
+        if version.num_files(level) > 0 {
+            runs.push(SortedRun {
+                level,
+                files: version.files(level).to_vec(),
+                size: version.level_size(level),
+            });
+        }
+    }
+    return runs;
+}
+
+/// Universal compaction. Every level 0 table and every non empty level
+/// from level 1 on is a sorted run, levels holding older entries than the
+/// levels above them. Once there are 'level0_file_num_compaction_trigger'
+/// runs, consecutive runs are merged into one:
+/// - all runs, when the runs other than the oldest one take more than
+///   'max_size_amplification_percent' of its size;
+/// - otherwise the newest runs of similar sizes, each run being at most
+///   'size_ratio' percent larger than the runs before it together;
+/// - otherwise the newest runs, enough of them to get below the trigger,
+///   if there are at least 'min_merge_width' runs.
+pub struct UniversalCompactionPicker {
+    comparator_: InternalKeyComparator,
+    level0_file_num_compaction_trigger_: usize,
+    target_file_size_base_: u64,
+    options_: CompactionOptionsUniversal,
+}
+
+impl UniversalCompactionPicker {
+    pub fn new(options: &Options) -> UniversalCompactionPicker {
+        UniversalCompactionPicker {
+            comparator_: InternalKeyComparator::new(options.comparator.clone()),
+            level0_file_num_compaction_trigger_: options.level0_file_num_compaction_trigger,
+            target_file_size_base_: options.target_file_size_base,
+            options_: options.compaction_options_universal,
+        }
+    }
+
+    /// Number of runs to merge, all of them, if the space amplification is
+    /// too high.
+    fn pick_size_amplification(&self, runs: &[SortedRun]) -> Option<usize> {
+        let (oldest, newer) = runs.split_last()?;
+        let newer_size: u64 = newer.iter().map(|run| run.size).sum();
+        if newer.is_empty() || newer_size.saturating_mul(100)
+            < self.options_.max_size_amplification_percent.saturating_mul(oldest.size) {
+            return None;
+        }
+        return Some(runs.len());
+    }
+
+    /// First and end index of the newest runs of similar sizes, if there
+    /// are at least 'min_merge_width' of them.
+    fn pick_size_ratio(&self, runs: &[SortedRun]) -> Option<(usize, usize)> {
+        let max_merge_width = self.options_.max_merge_width.max(2);
+        for start in 0..runs.len() {
+            let mut size = runs[start].size;
+            let mut end = start + 1;
+            while end < runs.len() && end - start < max_merge_width
+                && runs[end].size.saturating_mul(100)
+                    <= size.saturating_mul(100 + self.options_.size_ratio) {
+                size += runs[end].size;
+                end += 1;
+            }
+            if end - start >= self.options_.min_merge_width.max(2) {
+                return Some((start, end));
+            }
+        }
+        return None;
+    }
+
+    /// First and end index of the newest runs, enough of them to get below
+    /// the trigger, within the merge widths. There must be at least
+    /// 'min_merge_width' runs.
+    fn pick_below_trigger(&self, runs: &[SortedRun]) -> Option<(usize, usize)> {
+        let min_merge_width = self.options_.min_merge_width.max(2);
+        if runs.len() < min_merge_width {
+            return None;
+        }
+        let num_runs = (runs.len() + 1).saturating_sub(self.level0_file_num_compaction_trigger_)
+            .max(min_merge_width)
+            .min(self.options_.max_merge_width.max(2))
+            .min(runs.len());
+        return Some((0, num_runs));
+    }
+
+    /// Level the merge of 'runs[start..end]' is written to: the level of
+    /// the oldest run merged if it is a level from 1 on, otherwise the
+    /// lowest level above the older runs.
+    fn output_level(runs: &[SortedRun], end: usize) -> usize {
+        let oldest = &runs[end - 1];
+        if oldest.level > 0 {
+            return oldest.level;
+        }
+        return match runs.get(end) {
+            Some(older) if older.level == 0 => 0,
+            Some(older) => older.level - 1,
+            None => NUM_LEVELS - 1,
+        };
+    }
+}
+
+impl CompactionPicker for UniversalCompactionPicker {
+    fn needs_compaction(&self, version: &Version) -> bool {
+        let num_runs = version.num_files(0)
+            + (1..NUM_LEVELS).filter(|level| version.num_files(*level) > 0).count();
+        if num_runs < self.level0_file_num_compaction_trigger_.max(2) {
+            return false;
+        }
+        // Only all the runs can be merged when there are fewer than
+        // 'min_merge_width' of them.
+        return num_runs >= self.options_.min_merge_width
+            || self.pick_size_amplification(&sorted_runs(version)).is_some();
+    }
+
+    fn pick_compaction(&self, version: &Arc<Version>) -> Option<Compaction> {
+        if !self.needs_compaction(version) {
+            return None;
+        }
+        let runs = sorted_runs(version);
+        let (start, end) = self.pick_size_amplification(&runs)
+            .map(|end| (0, end))
+            .or_else(|| self.pick_size_ratio(&runs))
+            .or_else(|| self.pick_below_trigger(&runs))?;
+        let mut inputs: Vec<CompactionInputs> = vec!();
+        for run in runs[start..end].iter() {
+            match inputs.last_mut() {
+                Some(last) if last.level == run.level => last.files.extend(run.files.clone()),
+                _ => inputs.push(CompactionInputs {
+                    level: run.level,
+                    files: run.files.clone(),
+                }),
+            }
+        }
+        let output_level = Self::output_level(&runs, end);
+        // A level 0 table is a sorted run of its own, so it is not cut.
+        let max_output_file_size = if output_level == 0 {
+            u64::MAX
+        } else {
+            self.target_file_size_base_
+        };
+        return Some(Compaction::new(version.clone(), inputs, output_level,
+            max_output_file_size, &self.comparator_));
+    }
+}
+
+/// FIFO compaction. All tables are kept in level 0, newest first: a DB
+/// with tables in other levels is not opened with it. The oldest tables
+/// are deleted, whole, once they are older than 'ttl' or the tables take
+/// more than 'max_table_files_size'. With 'allow_compaction', the newest
+/// small tables are merged into one.
+pub struct FifoCompactionPicker {
+    comparator_: InternalKeyComparator,
+    level0_file_num_compaction_trigger_: usize,
+    target_file_size_base_: u64,
+    options_: CompactionOptionsFifo,
+}
+
+impl FifoCompactionPicker {
+    pub fn new(options: &Options) -> FifoCompactionPicker {
+        FifoCompactionPicker {
+            comparator_: InternalKeyComparator::new(options.comparator.clone()),
+            level0_file_num_compaction_trigger_: options.level0_file_num_compaction_trigger,
+            target_file_size_base_: options.target_file_size_base,
+            options_: options.compaction_options_fifo,
+        }
+    }
+
+    /// Number of the oldest tables to delete, 0 if none.
+    fn num_files_to_delete(&self, version: &Version) -> usize {
+        let files = version.files(0);
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs()).unwrap_or(0);
+        let expired = |file: &FileMetaData| self.options_.ttl > 0 && file.creation_time > 0
+            && file.creation_time.saturating_add(self.options_.ttl) < now;
+        let num_expired = files.iter().rev().take_while(|file| expired(file)).count();
+        let mut size = version.level_size(0);
+        let mut num_files = 0;
+        for file in files.iter().rev() {
+            if num_files >= num_expired && size <= self.options_.max_table_files_size {
+                break;
+            }
+            size -= file.file_size;
+            num_files += 1;
+        }
+        return num_files;
+    }
+
+    /// Number of the newest tables to merge, 0 if none. Small tables are
+    /// only merged once they add up to the target file size, so that the
+    /// merged table is not merged again.
+    fn num_files_to_merge(&self, version: &Version) -> usize {
+        if !self.options_.allow_compaction {
+            return 0;
+        }
+        let min_files = self.level0_file_num_compaction_trigger_.max(2);
+        let mut size = 0;
+        let mut num_files = 0;
+        for file in version.files(0) {
+            if file.file_size >= self.target_file_size_base_ {
+                break;
+            }
+            size += file.file_size;
+            num_files += 1;
+            if num_files >= min_files && size >= self.target_file_size_base_ {
+                return num_files;
+            }
+        }
+        return 0;
+    }
+}
+
+impl CompactionPicker for FifoCompactionPicker {
+    fn needs_compaction(&self, version: &Version) -> bool {
+        self.num_files_to_delete(version) > 0 || self.num_files_to_merge(version) > 0
+    }
+
+    fn pick_compaction(&self, version: &Arc<Version>) -> Option<Compaction> {
+        let files = version.files(0);
+        let num_files = self.num_files_to_delete(version);
+        if num_files > 0 {
+            return Some(Compaction::deletion(version.clone(), CompactionInputs {
+                level: 0,
+                files: files[files.len() - num_files..].to_vec(),
+            }));
+        }
+        let num_files = self.num_files_to_merge(version);
+        if num_files > 0 {
+            return Some(Compaction::new(version.clone(), vec!(CompactionInputs {
+                level: 0,
+                files: files[..num_files].to_vec(),
+            }), 0, u64::MAX, &self.comparator_));
+        }
+        return None;
+    }
+}
+
+/// Returns the picker of the compaction style of 'options'.
+pub fn new_compaction_picker(options: &Options) -> Box<dyn CompactionPicker> {
+    match options.compaction_style {
+        CompactionStyle::Level => Box::new(LevelCompactionPicker::new(options)),
+        CompactionStyle::Universal => Box::new(UniversalCompactionPicker::new(options)),
+        CompactionStyle::Fifo => Box::new(FifoCompactionPicker::new(options)),
+    }
+}
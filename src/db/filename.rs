@@ -0,0 +1,121 @@
+use std::sync::Arc;
+use crate::filesystem::Path;
+use crate::sst::lsm_error::DataStoreError;
+
+const MANIFEST_PREFIX: &str = "MANIFEST-";
+const CURRENT_FILE_NAME: &str = "CURRENT";
+
+/// Type of the files in a DB directory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileType {
+    /// Write ahead log, named "NNNNNN.log".
+    Log,
+    /// Sorted table, named "NNNNNN.sst".
+    Table,
+    /// File being written, named "NNNNNN.dbtmp", renamed once complete.
+    Temp,
+    /// Log of the VersionEdits of the DB, named "MANIFEST-NNNNNN".
+    Manifest,
+    /// Name of the current MANIFEST, in a file named "CURRENT".
+    Current,
+}
+
+/// Name of the file of type 'file_type' numbered 'number'. The number of
+/// the CURRENT file is ignored.
+pub fn file_name(number: u64, file_type: FileType) -> String {
+    match file_type {
+        FileType::Log => format!("{:06}.log", number),
+        FileType::Table => format!("{:06}.sst", number),
+        FileType::Temp => format!("{:06}.dbtmp", number),
+        FileType::Manifest => format!("{}{:06}", MANIFEST_PREFIX, number),
+        FileType::Current => CURRENT_FILE_NAME.to_string(),
+    }
+}
+
+#[inline(always)]
+pub fn log_file_name(number: u64) -> String {
+    file_name(number, FileType::Log)
+}
+
+#[inline(always)]
+pub fn table_file_name(number: u64) -> String {
+    file_name(number, FileType::Table)
+}
+
+#[inline(always)]
+pub fn temp_file_name(number: u64) -> String {
+    file_name(number, FileType::Temp)
+}
+
+#[inline(always)]
+pub fn manifest_file_name(number: u64) -> String {
+    file_name(number, FileType::Manifest)
+}
+
+#[inline(always)]
+pub fn current_file_name() -> String {
+    file_name(0, FileType::Current)
+}
+
+/// Returns the number and type of the file named 'name', or None if it
+/// is not a file of the DB. The number of the CURRENT file is 0.
+pub fn parse_file_name(name: &str) -> Option<(u64, FileType)> {
+    if name == CURRENT_FILE_NAME {
+        return Some((0, FileType::Current));
+    }
+    if let Some(number) = name.strip_prefix(MANIFEST_PREFIX) {
+        return Some((parse_number(number)?, FileType::Manifest));
+    }
+    let (number, extension) = name.split_once('.')?;
+    let number = parse_number(number)?;
+    return match extension {
+        "log" => Some((number, FileType::Log)),
+        "sst" => Some((number, FileType::Table)),
+        "dbtmp" => Some((number, FileType::Temp)),
+        _ => None,
+    };
+}
+
+fn parse_number(number: &str) -> Option<u64> {
+    if number.is_empty() || !number.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    return number.parse().ok();
+}
+
+/// Points the CURRENT file of 'dbname' at the MANIFEST numbered
+/// 'manifest_number'. CURRENT is replaced by a rename, so it is never
+/// seen partially written.
+pub fn set_current_file(dbname: &Arc<dyn Path>, manifest_number: u64)
+    -> Result<(), DataStoreError> {
+    let fs = dbname.get_file_system();
+    let temp_path = dbname.join(&temp_file_name(manifest_number));
+    if fs.exists(temp_path.as_ref()) {
+        fs.remove(temp_path.as_ref())?;
+    }
+    fs.create(temp_path.as_ref())?;
+    fs.append(temp_path.as_ref(),
+        format!("{}\n", manifest_file_name(manifest_number)).as_bytes())?;
+    fs.sync(temp_path.as_ref())?;
+    fs.rename(temp_path.as_ref(), dbname.join(&current_file_name()).as_ref())?;
+    return Ok(());
+}
+
+/// Returns the number of the MANIFEST named in the CURRENT file of
+/// 'dbname'.
+pub fn read_current_file(dbname: &Arc<dyn Path>) -> Result<u64, DataStoreError> {
+    let fs = dbname.get_file_system();
+    let path = dbname.join(&current_file_name());
+    let mut contents = vec![0; fs.file_size(path.as_ref())? as usize];
+    let len = fs.seek_read(path.as_ref(), 0, &mut contents)?;
+    let corruption = || DataStoreError::Corruption {
+        file: current_file_name(),
+        offset: 0,
+        reason: "CURRENT does not name a MANIFEST".to_string(),
+    };
+    let name = std::str::from_utf8(&contents[..len]).map_err(|_| corruption())?;
+    return match name.strip_suffix('\n').and_then(parse_file_name) {
+        Some((number, FileType::Manifest)) => Ok(number),
+        _ => Err(corruption()),
+    };
+}
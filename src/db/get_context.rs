@@ -0,0 +1,134 @@
+use std::cmp::Ordering;
+use std::sync::Arc;
+use crate::comparator::Comparator;
+use crate::iterator::InternalIterator;
+use crate::memtable::MemTable;
+use crate::memtable::internal_key::{parse_internal_key, LookupKey, SequenceNumber,
+    ValueType};
+use crate::merge_operator::MergeOperator;
+use crate::sst::lsm_error::DataStoreError;
+use crate::sst::options::ReadOptions;
+use crate::sst::table::Table;
+
+/// State of the lookup of a user key, fed with the sources of the DB
+/// newest first: the MemTable, the immutable MemTable and the tables.
+/// Merge operands are collected until the value they apply to, or a
+/// deletion, is found.
+pub struct GetContext<'a> {
+    user_comparator_: &'a Arc<dyn Comparator>,
+    lookup_key_: LookupKey,
+    sequence_: SequenceNumber,
+    /// Merge operands found so far, newest first.
+    operands_: Vec<Vec<u8>>,
+    /// Set once the lookup is complete: the value found, or None if the
+    /// key is deleted.
+    base_: Option<Option<Vec<u8>>>,
+}
+
+impl<'a> GetContext<'a> {
+    /// Looks up 'user_key' as of 'sequence'.
+    pub fn new(user_comparator: &'a Arc<dyn Comparator>, user_key: &[u8],
+        sequence: SequenceNumber) -> GetContext<'a> {
+        GetContext {
+            user_comparator_: user_comparator,
+            lookup_key_: LookupKey::new(user_key, sequence),
+            sequence_: sequence,
+            operands_: vec!(),
+            base_: None,
+        }
+    }
+
+    #[inline(always)]
+    pub fn user_key(&self) -> &[u8] {
+        self.lookup_key_.user_key()
+    }
+
+    /// Internal key of the lookup, sorting before all the entries of the
+    /// user key visible to it.
+    #[inline(always)]
+    pub fn internal_key(&self) -> &[u8] {
+        self.lookup_key_.internal_key()
+    }
+
+    /// Whether older sources need not be searched.
+    #[inline(always)]
+    pub fn is_done(&self) -> bool {
+        self.base_.is_some()
+    }
+
+    pub fn search_memtable(&mut self, mem: &MemTable) -> Result<(), DataStoreError> {
+        let tombstone = mem.max_covering_tombstone_sequence(self.lookup_key_.user_key(),
+            self.sequence_);
+        return self.search(Some(&mut mem.iter()), tombstone);
+    }
+
+    pub fn search_table(&mut self, table: &Table, read_options: &ReadOptions)
+        -> Result<(), DataStoreError> {
+        let tombstone = table.range_tombstones(self.user_comparator_)?
+            .max_covering_tombstone_sequence(self.lookup_key_.user_key(), self.sequence_);
+        if !table.key_may_match(self.lookup_key_.internal_key())? {
+            // The range deletions of the table may still delete the key.
+            return self.search(None, tombstone);
+        }
+        return self.search(Some(&mut table.iter(read_options)), tombstone);
+    }
+
+    /// Feeds the entries of the user key from 'iter', newest first. Entries
+    /// older than 'tombstone', the sequence number of a range deletion of
+    /// the same source covering the key, are deleted, and so are the
+    /// entries of all older sources.
+    fn search(&mut self, iter: Option<&mut dyn InternalIterator>,
+        tombstone: Option<SequenceNumber>) -> Result<(), DataStoreError> {
+        debug_assert!(!self.is_done());
+        if let Some(iter) = iter {
+            iter.seek(self.lookup_key_.internal_key())?;
+            while iter.valid() {
+                let parsed = parse_internal_key(iter.key()).ok_or_else(||
+                    DataStoreError::MalformedBlock {
+                        offset: 0,
+                        reason: "malformed internal key".to_string(),
+                    })?;
+                if self.user_comparator_.compare(parsed.user_key,
+                    self.lookup_key_.user_key()) != Ordering::Equal {
+                    break;
+                }
+                if tombstone.is_some_and(|tombstone| parsed.sequence < tombstone) {
+                    break;
+                }
+                match parsed.value_type {
+                    ValueType::Value => {
+                        self.base_ = Some(Some(iter.value().to_vec()));
+                        return Ok(());
+                    }
+                    ValueType::Deletion => {
+                        self.base_ = Some(None);
+                        return Ok(());
+                    }
+                    ValueType::Merge => self.operands_.push(iter.value().to_vec()),
+                    ValueType::RangeDeletion => {}
+                }
+                iter.next()?;
+            }
+        }
+        if tombstone.is_some() {
+            self.base_ = Some(None);
+        }
+        return Ok(());
+    }
+
+    /// Returns the value of the key, or None if it has no value. Merge
+    /// operands are combined by 'merge_operator'.
+    pub fn finish(self, merge_operator: Option<&Arc<dyn MergeOperator>>)
+        -> Result<Option<Vec<u8>>, DataStoreError> {
+        let base = self.base_.unwrap_or(None);
+        if self.operands_.is_empty() {
+            return Ok(base);
+        }
+        let merge_operator = merge_operator.ok_or_else(|| DataStoreError::NotSupported(
+            "Merge requires a merge operator".to_string()))?;
+        let operands: Vec<&[u8]> = self.operands_.iter().rev()
+            .map(|operand| &operand[..]).collect();
+        return Ok(Some(merge_operator.full_merge(self.lookup_key_.user_key(),
+            base.as_deref(), &operands)));
+    }
+}
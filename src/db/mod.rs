@@ -0,0 +1,618 @@
+pub mod builder;
+pub mod compaction;
+pub mod compaction_job;
+pub mod compaction_picker;
+pub mod filename;
+pub mod get_context;
+pub mod options;
+pub mod table_cache;
+mod tests;
+pub mod version_edit;
+pub mod version_set;
+
+use std::collections::HashSet;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+use crate::db::builder::build_table;
+use crate::db::compaction_job::CompactionJob;
+use crate::db::compaction_picker::{new_compaction_picker, CompactionPicker};
+use crate::db::filename::{current_file_name, log_file_name, parse_file_name,
+    table_file_name, FileType};
+use crate::db::get_context::GetContext;
+use crate::db::options::{CompactionStyle, Options, WriteOptions};
+use crate::db::table_cache::TableCache;
+use crate::db::version_edit::{FileMetaData, VersionEdit};
+use crate::db::version_set::{VersionSet, NUM_LEVELS};
+use crate::filesystem::Path;
+use crate::memtable::MemTable;
+use crate::memtable::internal_key::{InternalFilterPolicy, InternalKeyComparator,
+    SequenceNumber};
+use crate::sst::lsm_error::DataStoreError;
+use crate::sst::options::{ReadOptions, TableOptions};
+use crate::wal::WalRecoveryMode;
+use crate::wal::reader::{Reader, Reporter};
+use crate::wal::writer::Writer;
+use crate::write_batch::WriteBatch;
+
+/// Longest time between two checks for tables older than the TTL of FIFO
+/// compaction.
+const MAX_TTL_CHECK_PERIOD: Duration = Duration::from_secs(600);
+
+/// Key value store. Writes are recorded in a write ahead log and applied
+/// to the active MemTable. Once full, the MemTable becomes immutable and
+/// is flushed by a background thread to a level 0 table, while writes go
+/// to a new MemTable and log. The same thread compacts the tables, as
+/// picked by the CompactionPicker of the compaction style. The tables of
+/// the DB are tracked by its VersionSet. On open, the tables are recovered from the MANIFEST and the
+/// write ahead logs left by the previous instance are replayed.
+/// DB is thread safe: writes are serialized, reads do not block writes.
+pub struct DB {
+    inner_: Arc<DBInner>,
+}
+
+/// Part of a DB shared with its background work.
+struct DBInner {
+    dbname_: Arc<dyn Path>,
+    options_: Options,
+    internal_comparator_: InternalKeyComparator,
+    /// Options of the tables, keyed by internal keys.
+    table_options_: TableOptions,
+    table_cache_: TableCache,
+    compaction_picker_: Box<dyn CompactionPicker>,
+    state_: Mutex<DBState>,
+    /// Signalled when background work or a MANIFEST write completes, and
+    /// when the DB is dropped.
+    background_done_: Condvar,
+}
+
+/// State of a DB, guarded by its mutex.
+struct DBState {
+    mem: Arc<MemTable>,
+    /// MemTable being flushed, if any.
+    imm: Option<Arc<MemTable>>,
+    log: Writer,
+    /// Number of the log of 'mem'. Older logs only hold writes of 'imm'.
+    log_number: u64,
+    versions: VersionSet,
+    /// Numbers of the tables being built, not yet in a Version.
+    pending_outputs: HashSet<u64>,
+    /// Whether a background thread is flushing or compacting.
+    bg_scheduled: bool,
+    /// Error of a background flush or compaction, or of a write to the log.
+    /// Writes fail once it is set, and no more background work is
+    /// scheduled.
+    bg_error: Option<String>,
+    /// Set when the DB is dropped. No more background work is scheduled.
+    shutting_down: bool,
+}
+
+impl DB {
+    /// Opens the DB in the directory 'dbname', recovering the writes of
+    /// the previous instance.
+    pub fn open(dbname: Arc<dyn Path>, options: Options) -> Result<DB, DataStoreError> {
+        let fs = dbname.get_file_system();
+        let db_name = dbname.to_str().unwrap_or("").to_string();
+        if !fs.exists(dbname.join(&current_file_name()).as_ref()) {
+            if !options.create_if_missing {
+                return Err(DataStoreError::InvalidArgument(
+                    format!("{} does not exist (create_if_missing is false)", db_name)));
+            }
+            fs.create_dir_all(dbname.as_ref())?;
+            VersionSet::create(&dbname, options.comparator.as_ref(),
+                options.merge_operator.as_deref())?;
+        } else if options.error_if_exists {
+            return Err(DataStoreError::InvalidArgument(
+                format!("{} exists (error_if_exists is true)", db_name)));
+        }
+
+        let internal_comparator = InternalKeyComparator::new(options.comparator.clone());
+        let mut versions = VersionSet::new(dbname.clone(), internal_comparator.clone(),
+            options.merge_operator.as_deref());
+        versions.recover()?;
+        // Logs written since the last flush recorded in the MANIFEST.
+        let mut logs = vec!();
+        let mut tables = HashSet::new();
+        for name in fs.children(dbname.as_ref())? {
+            match parse_file_name(&name) {
+                Some((number, FileType::Log)) if number >= versions.log_number() => {
+                    versions.mark_file_number_used(number);
+                    logs.push(number);
+                }
+                Some((number, FileType::Table)) => {
+                    tables.insert(number);
+                }
+                _ => {}
+            }
+        }
+        if options.compaction_style == CompactionStyle::Fifo
+            && (1..NUM_LEVELS).any(|level| versions.current().num_files(level) > 0) {
+            return Err(DataStoreError::InvalidArgument(
+                "FIFO compaction needs all the tables in level 0".to_string()));
+        }
+        if let Some(number) = versions.live_files().iter().find(|number|
+            !tables.contains(number)) {
+            return Err(DataStoreError::Corruption {
+                file: table_file_name(*number),
+                offset: 0,
+                reason: "table of the MANIFEST is missing".to_string(),
+            });
+        }
+
+        let table_options = TableOptions {
+            comparator: Arc::new(internal_comparator.clone()),
+            internal_keys: true,
+            filter_policy: options.table_options.filter_policy.as_ref().map(|policy|
+                Arc::new(InternalFilterPolicy::new(policy.clone())) as _),
+            ..options.table_options.clone()
+        };
+        let log_number = versions.new_file_number();
+        let inner = Arc::new(DBInner {
+            table_cache_: TableCache::new(dbname.clone(), table_options.clone()),
+            dbname_: dbname.clone(),
+            internal_comparator_: internal_comparator.clone(),
+            table_options_: table_options,
+            compaction_picker_: new_compaction_picker(&options),
+            state_: Mutex::new(DBState {
+                mem: Arc::new(new_memtable(&options, &internal_comparator)),
+                imm: None,
+                // Writes after recovery go to a new log.
+                log: Writer::new(dbname.join(&log_file_name(log_number)))?,
+                log_number,
+                versions,
+                pending_outputs: HashSet::new(),
+                bg_scheduled: false,
+                bg_error: None,
+                shutting_down: false,
+            }),
+            background_done_: Condvar::new(),
+            options_: options,
+        });
+        inner.recover(logs)?;
+        inner.maybe_schedule_background_work(&mut inner.state_.lock().unwrap());
+        if inner.options_.compaction_style == CompactionStyle::Fifo
+            && inner.options_.compaction_options_fifo.ttl > 0 {
+            let inner = inner.clone();
+            std::thread::spawn(move || inner.check_ttl_periodically());
+        }
+        return Ok(DB { inner_: inner });
+    }
+
+    pub fn put(&self, key: &[u8], value: &[u8]) -> Result<(), DataStoreError> {
+        let mut batch = WriteBatch::new();
+        batch.put(key, value);
+        return self.write(batch);
+    }
+
+    pub fn delete(&self, key: &[u8]) -> Result<(), DataStoreError> {
+        let mut batch = WriteBatch::new();
+        batch.delete(key);
+        return self.write(batch);
+    }
+
+    /// Applies the updates of 'batch' atomically.
+    pub fn write(&self, batch: WriteBatch) -> Result<(), DataStoreError> {
+        return self.write_with_options(&WriteOptions::default(), batch);
+    }
+
+    pub fn write_with_options(&self, options: &WriteOptions, mut batch: WriteBatch)
+        -> Result<(), DataStoreError> {
+        if batch.count() == 0 {
+            return Ok(());
+        }
+        let inner = &self.inner_;
+        let mut state = inner.make_room_for_write(inner.state_.lock().unwrap())?;
+        // Reject the batch before it reaches the log, or it would fail
+        // again on recovery.
+        batch.validate(&state.mem)?;
+        let last_sequence = state.versions.last_sequence();
+        batch.set_sequence(last_sequence + 1);
+        let mut result = state.log.add_record(batch.data());
+        if result.is_ok() && options.sync {
+            result = state.log.sync();
+        }
+        if let Err(error) = result {
+            // The log may end with a partial record, which following records
+            // must not be appended after.
+            state.bg_error = Some(error.to_string());
+            inner.background_done_.notify_all();
+            return Err(error);
+        }
+        batch.insert_into(&state.mem)?;
+        state.versions.set_last_sequence(last_sequence + batch.count() as u64);
+        return Ok(());
+    }
+
+    /// Returns the value of 'key', or None if it has no value. The
+    /// MemTables and tables are searched newest first.
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DataStoreError> {
+        let inner = &self.inner_;
+        let (mem, imm, version, sequence) = {
+            let state = inner.state_.lock().unwrap();
+            (state.mem.clone(), state.imm.clone(), state.versions.current(),
+                state.versions.last_sequence())
+        };
+        let comparator = &inner.internal_comparator_;
+        let mut context = GetContext::new(comparator.user_comparator(), key, sequence);
+        context.search_memtable(&mem)?;
+        if let Some(imm) = imm.as_ref() {
+            if !context.is_done() {
+                context.search_memtable(imm)?;
+            }
+        }
+        if !context.is_done() {
+            version.get(&mut context, comparator, &inner.table_cache_,
+                &ReadOptions::default())?;
+        }
+        return context.finish(inner.options_.merge_operator.as_ref());
+    }
+
+    /// Flushes the MemTable to a level 0 table, and waits for the flush to
+    /// complete.
+    pub fn flush(&self) -> Result<(), DataStoreError> {
+        let inner = &self.inner_;
+        let mut state = inner.wait_for_flush(inner.state_.lock().unwrap())?;
+        if !state.mem.is_empty() {
+            inner.switch_memtable(&mut state)?;
+            drop(inner.wait_for_flush(state)?);
+        }
+        return Ok(());
+    }
+
+    /// Waits until no flush or compaction is running or needed.
+    pub fn wait_for_compact(&self) -> Result<(), DataStoreError> {
+        let inner = &self.inner_;
+        let mut state = inner.state_.lock().unwrap();
+        inner.maybe_schedule_background_work(&mut state);
+        while state.bg_scheduled && state.bg_error.is_none() {
+            state = inner.background_done_.wait(state).unwrap();
+        }
+        if let Some(error) = state.bg_error.as_ref() {
+            return Err(DataStoreError::BackgroundError(error.clone()));
+        }
+        return Ok(());
+    }
+
+    /// Number of tables in 'level'.
+    pub fn num_files_at_level(&self, level: usize) -> usize {
+        self.inner_.state_.lock().unwrap().versions.current().num_files(level)
+    }
+
+    #[inline(always)]
+    pub fn dbname(&self) -> &Arc<dyn Path> {
+        &self.inner_.dbname_
+    }
+
+    #[inline(always)]
+    pub fn options(&self) -> &Options {
+        &self.inner_.options_
+    }
+
+    #[inline(always)]
+    pub fn internal_comparator(&self) -> &InternalKeyComparator {
+        &self.inner_.internal_comparator_
+    }
+}
+
+impl Drop for DB {
+    fn drop(&mut self) {
+        // Let the background work in progress complete. A MemTable being
+        // flushed would otherwise be replayed from its log on the next
+        // open.
+        let inner = &self.inner_;
+        let mut state = inner.state_.lock().unwrap();
+        state.shutting_down = true;
+        inner.background_done_.notify_all();
+        while state.bg_scheduled {
+            state = inner.background_done_.wait(state).unwrap();
+        }
+    }
+}
+
+impl DBInner {
+    /// Replays the logs numbered 'logs'. The writes replayed are flushed
+    /// to a level 0 table, recorded in the MANIFEST along with the new log,
+    /// so the logs replayed become obsolete.
+    fn recover(&self, mut logs: Vec<u64>) -> Result<(), DataStoreError> {
+        let mut state = self.state_.lock().unwrap();
+        logs.sort_unstable();
+        for number in logs {
+            let (sequence, corrupted) = replay_log(self.dbname_.join(&log_file_name(number)),
+                &self.options_, &state.mem)?;
+            if sequence > state.versions.last_sequence() {
+                state.versions.set_last_sequence(sequence);
+            }
+            if corrupted
+                && self.options_.wal_recovery_mode == WalRecoveryMode::PointInTimeRecovery {
+                // Writes in later logs are after the point in time recovered.
+                // They are numbered before the new log, so the edit below
+                // makes them obsolete and they are deleted before new writes
+                // reuse their sequence numbers.
+                break;
+            }
+        }
+        let mut edit = VersionEdit::new();
+        if !state.mem.is_empty() {
+            let mem = std::mem::replace(&mut state.mem,
+                Arc::new(new_memtable(&self.options_, &self.internal_comparator_)));
+            let number = state.versions.new_file_number();
+            edit.add_file(0, self.write_level0_table(&mem, number)?);
+        }
+        edit.set_log_number(state.log_number);
+        let (mut state, result) = self.log_and_apply(state, &mut edit);
+        result?;
+        state.log.sync()?;
+        return self.delete_obsolete_files(&mut state);
+    }
+
+    /// Waits until the MemTable has room for a write. A full MemTable is
+    /// made immutable and a flush of it is scheduled, once the flush of
+    /// the previous one completes.
+    fn make_room_for_write<'a>(self: &'a Arc<Self>, mut state: MutexGuard<'a, DBState>)
+        -> Result<MutexGuard<'a, DBState>, DataStoreError> {
+        loop {
+            if let Some(error) = state.bg_error.as_ref() {
+                return Err(DataStoreError::BackgroundError(error.clone()));
+            }
+            if state.mem.approximate_memory_usage() < self.options_.write_buffer_size
+                || state.mem.is_empty() {
+                return Ok(state);
+            }
+            if state.imm.is_some() {
+                state = self.background_done_.wait(state).unwrap();
+                continue;
+            }
+            self.switch_memtable(&mut state)?;
+        }
+    }
+
+    /// Waits until no MemTable is being flushed.
+    fn wait_for_flush<'a>(&self, mut state: MutexGuard<'a, DBState>)
+        -> Result<MutexGuard<'a, DBState>, DataStoreError> {
+        while state.imm.is_some() && state.bg_error.is_none() {
+            state = self.background_done_.wait(state).unwrap();
+        }
+        if let Some(error) = state.bg_error.as_ref() {
+            return Err(DataStoreError::BackgroundError(error.clone()));
+        }
+        return Ok(state);
+    }
+
+    /// Makes the MemTable immutable and schedules its flush. Following
+    /// writes go to a new MemTable and a new log. There must be no
+    /// immutable MemTable.
+    fn switch_memtable(self: &Arc<Self>, state: &mut DBState) -> Result<(), DataStoreError> {
+        assert!(state.imm.is_none(), "A MemTable is already being flushed");
+        let number = state.versions.new_file_number();
+        state.log = Writer::new(self.dbname_.join(&log_file_name(number)))?;
+        state.log_number = number;
+        let mem = Arc::new(new_memtable(&self.options_, &self.internal_comparator_));
+        state.imm = Some(std::mem::replace(&mut state.mem, mem));
+        self.maybe_schedule_background_work(state);
+        return Ok(());
+    }
+
+    /// Starts a background thread if the immutable MemTable is to be
+    /// flushed or the current Version needs a compaction. A single thread
+    /// runs at a time.
+    fn maybe_schedule_background_work(self: &Arc<Self>, state: &mut DBState) {
+        if state.bg_scheduled || state.shutting_down || state.bg_error.is_some() {
+            return;
+        }
+        if state.imm.is_none()
+            && !self.compaction_picker_.needs_compaction(&state.versions.current()) {
+            return;
+        }
+        state.bg_scheduled = true;
+        let inner = self.clone();
+        std::thread::spawn(move || inner.background_call());
+    }
+
+    /// Checks for tables older than the TTL of FIFO compaction every TTL,
+    /// up to every MAX_TTL_CHECK_PERIOD, until the DB is dropped. Tables
+    /// expire even when no MemTable is flushed.
+    fn check_ttl_periodically(self: &Arc<Self>) {
+        let ttl = Duration::from_secs(self.options_.compaction_options_fifo.ttl);
+        let period = ttl.min(MAX_TTL_CHECK_PERIOD);
+        let mut state = self.state_.lock().unwrap();
+        while !state.shutting_down {
+            state = self.background_done_.wait_timeout(state, period).unwrap().0;
+            self.maybe_schedule_background_work(&mut state);
+        }
+    }
+
+    fn background_call(self: &Arc<Self>) {
+        let state = self.state_.lock().unwrap();
+        // A flush comes first, writes may be waiting for it.
+        let (mut state, result) = if state.imm.is_some() {
+            self.flush_imm(state)
+        } else {
+            self.background_compaction(state)
+        };
+        // Same lock hold as the result was installed in, so neither another
+        // background thread nor Drop sees the work done before it is over.
+        state.bg_scheduled = false;
+        if let Err(error) = result {
+            state.bg_error = Some(error.to_string());
+        }
+        // The flush or compaction may call for another compaction.
+        self.maybe_schedule_background_work(&mut state);
+        self.background_done_.notify_all();
+    }
+
+    /// Runs the compaction picked for the current Version, and installs
+    /// its outputs in place of its inputs. 'state' is unlocked while the
+    /// outputs are built, and returned locked along with the result.
+    fn background_compaction<'a>(&'a self, state: MutexGuard<'a, DBState>)
+        -> (MutexGuard<'a, DBState>, Result<(), DataStoreError>) {
+        let compaction = match self.compaction_picker_.pick_compaction(
+            &state.versions.current()) {
+            Some(compaction) => compaction,
+            None => return (state, Ok(())),
+        };
+        if compaction.is_deletion() {
+            let mut edit = VersionEdit::new();
+            compaction.add_input_deletions(&mut edit);
+            let (mut state, result) = self.log_and_apply(state, &mut edit);
+            drop(compaction);
+            let result = result.and_then(|_| self.delete_obsolete_files(&mut state));
+            return (state, result);
+        }
+        drop(state);
+        let mut numbers = vec!();
+        let job = CompactionJob::new(&compaction, &self.dbname_, &self.table_options_,
+            &self.internal_comparator_, self.options_.merge_operator.as_ref(),
+            &self.table_cache_);
+        let result = job.run(&mut || {
+            let mut state = self.state_.lock().unwrap();
+            let number = state.versions.new_file_number();
+            state.pending_outputs.insert(number);
+            numbers.push(number);
+            number
+        });
+        let mut state = self.state_.lock().unwrap();
+        let result = match result {
+            Ok(files) => {
+                let mut edit = VersionEdit::new();
+                compaction.add_input_deletions(&mut edit);
+                for file in files {
+                    edit.add_file(compaction.output_level(), file);
+                }
+                let result;
+                (state, result) = self.log_and_apply(state, &mut edit);
+                result
+            }
+            Err(error) => Err(error),
+        };
+        // Outputs stay pending until they are in the current Version.
+        for number in numbers {
+            state.pending_outputs.remove(&number);
+        }
+        drop(compaction);
+        let result = result.and_then(|_| self.delete_obsolete_files(&mut state));
+        return (state, result);
+    }
+
+    /// Writes the immutable MemTable to a level 0 table and records it in
+    /// the MANIFEST, making the log of the MemTable obsolete. 'state' is
+    /// unlocked while the table is built, and returned locked along with
+    /// the result.
+    fn flush_imm<'a>(&'a self, mut state: MutexGuard<'a, DBState>)
+        -> (MutexGuard<'a, DBState>, Result<(), DataStoreError>) {
+        let imm = state.imm.clone().expect("No MemTable to flush");
+        let number = state.versions.new_file_number();
+        state.pending_outputs.insert(number);
+        let log_number = state.log_number;
+        drop(state);
+        let result = self.write_level0_table(&imm, number);
+        let mut state = self.state_.lock().unwrap();
+        let result = match result {
+            Ok(file) => {
+                let mut edit = VersionEdit::new();
+                edit.add_file(0, file);
+                edit.set_log_number(log_number);
+                let result;
+                (state, result) = self.log_and_apply(state, &mut edit);
+                result
+            }
+            Err(error) => Err(error),
+        };
+        state.pending_outputs.remove(&number);
+        let result = result.and_then(|_| {
+            state.imm = None;
+            return self.delete_obsolete_files(&mut state);
+        });
+        return (state, result);
+    }
+
+    /// Records 'edit' in the MANIFEST and makes the resulting Version
+    /// current, as VersionSet::log_and_apply. 'state' is unlocked while the
+    /// MANIFEST is written and synced, and returned locked along with the
+    /// result. Edits are recorded one at a time.
+    fn log_and_apply<'a>(&'a self, mut state: MutexGuard<'a, DBState>,
+        edit: &mut VersionEdit) -> (MutexGuard<'a, DBState>, Result<(), DataStoreError>) {
+        while state.versions.is_writing_manifest() {
+            state = self.background_done_.wait(state).unwrap();
+        }
+        let mut write = state.versions.prepare_edit(edit);
+        drop(state);
+        let result = write.write();
+        let mut state = self.state_.lock().unwrap();
+        let result = state.versions.finish_edit(write, result);
+        self.background_done_.notify_all();
+        return (state, result);
+    }
+
+    /// Builds the table numbered 'number' from the entries of 'mem', which
+    /// must not be empty.
+    fn write_level0_table(&self, mem: &MemTable, number: u64)
+        -> Result<FileMetaData, DataStoreError> {
+        build_table(&self.dbname_, &self.table_options_, &mut mem.iter(),
+            &mut mem.range_del_iter(), number)?;
+        let table = self.table_cache_.get(number)?;
+        return FileMetaData::from_table(number, &table, &self.internal_comparator_);
+    }
+
+    /// Deletes the files no longer needed: logs older than the log number
+    /// of the MANIFEST, tables of no Version in use, old MANIFESTs, and
+    /// temporary files left by failed flushes.
+    fn delete_obsolete_files(&self, state: &mut DBState) -> Result<(), DataStoreError> {
+        let live = state.versions.live_files();
+        let fs = self.dbname_.get_file_system();
+        for name in fs.children(self.dbname_.as_ref())? {
+            let keep = match parse_file_name(&name) {
+                Some((number, FileType::Log)) => number >= state.versions.log_number(),
+                Some((number, FileType::Table)) => live.contains(&number)
+                    || state.pending_outputs.contains(&number),
+                Some((number, FileType::Temp)) => state.pending_outputs.contains(&number),
+                Some((number, FileType::Manifest)) =>
+                    number >= state.versions.manifest_file_number(),
+                Some((_, FileType::Current)) | None => true,
+            };
+            if !keep {
+                if let Some((number, FileType::Table)) = parse_file_name(&name) {
+                    self.table_cache_.evict(number);
+                }
+                fs.remove(self.dbname_.join(&name).as_ref())?;
+            }
+        }
+        return Ok(());
+    }
+}
+
+fn new_memtable(options: &Options, comparator: &InternalKeyComparator) -> MemTable {
+    match options.merge_operator.as_ref() {
+        Some(merge_operator) => MemTable::with_merge_operator(comparator.clone(),
+            merge_operator.clone()),
+        None => MemTable::new(comparator.clone()),
+    }
+}
+
+/// Records whether corrupted data was dropped while replaying a log.
+struct LogReporter {
+    corrupted: bool,
+}
+
+impl Reporter for LogReporter {
+    fn corruption(&mut self, _bytes: usize, _reason: &str) {
+        self.corrupted = true;
+    }
+}
+
+/// Applies the write batches recorded in the log at 'path' to 'mem'.
+/// Returns the last sequence number found and whether corrupted records
+/// were dropped.
+fn replay_log(path: Arc<dyn Path>, options: &Options, mem: &MemTable)
+    -> Result<(SequenceNumber, bool), DataStoreError> {
+    let mut reporter = LogReporter { corrupted: false };
+    let mut reader = Reader::new(path, Some(&mut reporter), true,
+        options.wal_recovery_mode);
+    let mut last_sequence = 0;
+    while let Some(record) = reader.read_record()? {
+        let batch = WriteBatch::from_data(record)?;
+        batch.insert_into(mem)?;
+        if batch.count() > 0 {
+            last_sequence = batch.sequence() + batch.count() as u64 - 1;
+        }
+    }
+    return Ok((last_sequence, reporter.corrupted));
+}
@@ -0,0 +1,148 @@
+use std::sync::Arc;
+use crate::comparator::{BytewiseComparator, Comparator};
+use crate::merge_operator::MergeOperator;
+use crate::sst::options::TableOptions;
+use crate::wal::WalRecoveryMode;
+
+pub const DEFAULT_WRITE_BUFFER_SIZE: usize = 4 * 1024 * 1024;
+pub const DEFAULT_MAX_BYTES_FOR_LEVEL_BASE: u64 = 10 * 1024 * 1024;
+pub const DEFAULT_TARGET_FILE_SIZE_BASE: u64 = 2 * 1024 * 1024;
+
+/// How tables are compacted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CompactionStyle {
+    /// Each level from 1 on is a single sorted run, with a target size
+    /// growing from level to level. Low space amplification, at the cost
+    /// of rewriting data once per level.
+    #[default]
+    Level,
+    /// Every level 0 table and every level from 1 on is a sorted run, and
+    /// runs of similar sizes are merged. Low write amplification, at the
+    /// cost of more space and reads.
+    Universal,
+    /// All tables are kept in level 0, and the oldest ones are deleted once
+    /// they take too much space or have expired. Meant for data which is
+    /// never updated, such as caches and time series.
+    Fifo,
+}
+
+/// Options of the universal compaction style.
+#[derive(Clone, Copy, Debug)]
+pub struct CompactionOptionsUniversal {
+    /// Percentage by which the size of the next older sorted run may exceed
+    /// the size of the runs picked so far for it to be merged with them.
+    pub size_ratio: u64,
+    /// Minimum number of sorted runs merged by a compaction.
+    pub min_merge_width: usize,
+    /// Maximum number of sorted runs merged by a compaction.
+    pub max_merge_width: usize,
+    /// Percentage of the size of the oldest sorted run which the other runs
+    /// may take, above which all runs are merged.
+    pub max_size_amplification_percent: u64,
+}
+
+/// Options of the FIFO compaction style.
+#[derive(Clone, Copy, Debug)]
+pub struct CompactionOptionsFifo {
+    /// Total size of the tables above which the oldest tables are deleted.
+    pub max_table_files_size: u64,
+    /// Age in seconds, from their creation time, above which tables are
+    /// deleted. 0 keeps tables regardless of their age. Checked when
+    /// MemTables are flushed, when the DB is opened, and every 'ttl'
+    /// seconds, up to every 10 minutes.
+    pub ttl: u64,
+    /// Merges the newest level 0 tables smaller than
+    /// 'target_file_size_base' once there are
+    /// 'level0_file_num_compaction_trigger' of them and they add up to
+    /// 'target_file_size_base'. The merged table is not small, so it is
+    /// not merged again. Older tables are never rewritten.
+    pub allow_compaction: bool,
+}
+
+impl Default for CompactionOptionsFifo {
+    fn default() -> Self {
+        CompactionOptionsFifo {
+            max_table_files_size: 1024 * 1024 * 1024,
+            ttl: 0,
+            allow_compaction: false,
+        }
+    }
+}
+
+impl Default for CompactionOptionsUniversal {
+    fn default() -> Self {
+        CompactionOptionsUniversal {
+            size_ratio: 1,
+            min_merge_width: 2,
+            max_merge_width: usize::MAX,
+            max_size_amplification_percent: 200,
+        }
+    }
+}
+
+/// Options of a DB, set when it is opened.
+#[derive(Clone)]
+pub struct Options {
+    /// Orders the user keys. Must stay the same for the life of the DB.
+    pub comparator: Arc<dyn Comparator>,
+    /// Combines merge operands. Merges fail without it.
+    pub merge_operator: Option<Arc<dyn MergeOperator>>,
+    /// Creates the DB if it is missing.
+    pub create_if_missing: bool,
+    /// Fails if the DB already exists.
+    pub error_if_exists: bool,
+    /// Handling of corrupted WAL records when the DB is recovered.
+    pub wal_recovery_mode: WalRecoveryMode,
+    /// Approximate size of the MemTable, in bytes, at which it is frozen
+    /// and flushed to a level 0 table.
+    pub write_buffer_size: usize,
+    /// Options of the tables. Their comparator and 'internal_keys' are
+    /// ignored. Tables are keyed by internal keys ordered by 'comparator'.
+    /// The filter policy applies to the user keys.
+    pub table_options: TableOptions,
+    /// Number of level 0 tables from which they are compacted to level 1.
+    pub level0_file_num_compaction_trigger: usize,
+    /// Total size of the tables of level 1 above which level 1 is
+    /// compacted to level 2.
+    pub max_bytes_for_level_base: u64,
+    /// Ratio of the target size of a level to the one of the previous
+    /// level, from level 2 on.
+    pub max_bytes_for_level_multiplier: f64,
+    /// Size above which a table written by a compaction is cut.
+    pub target_file_size_base: u64,
+    pub compaction_style: CompactionStyle,
+    /// Options of CompactionStyle::Universal. Compactions start once there
+    /// are 'level0_file_num_compaction_trigger' sorted runs.
+    pub compaction_options_universal: CompactionOptionsUniversal,
+    /// Options of CompactionStyle::Fifo.
+    pub compaction_options_fifo: CompactionOptionsFifo,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            comparator: Arc::new(BytewiseComparator),
+            merge_operator: None,
+            create_if_missing: false,
+            error_if_exists: false,
+            wal_recovery_mode: WalRecoveryMode::default(),
+            write_buffer_size: DEFAULT_WRITE_BUFFER_SIZE,
+            table_options: TableOptions::default(),
+            level0_file_num_compaction_trigger: 4,
+            max_bytes_for_level_base: DEFAULT_MAX_BYTES_FOR_LEVEL_BASE,
+            max_bytes_for_level_multiplier: 10.0,
+            target_file_size_base: DEFAULT_TARGET_FILE_SIZE_BASE,
+            compaction_style: CompactionStyle::default(),
+            compaction_options_universal: CompactionOptionsUniversal::default(),
+            compaction_options_fifo: CompactionOptionsFifo::default(),
+        }
+    }
+}
+
+/// Options of a write.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WriteOptions {
+    /// Syncs the WAL before the write returns. Otherwise a crash of the
+    /// machine, but not of the process, may lose recent writes.
+    pub sync: bool,
+}
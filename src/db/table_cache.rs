@@ -0,0 +1,42 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use crate::db::filename::table_file_name;
+use crate::filesystem::Path;
+use crate::sst::lsm_error::DataStoreError;
+use crate::sst::options::TableOptions;
+use crate::sst::table::Table;
+
+/// Keeps the tables of a DB open, so that their index, filter and
+/// properties are read only once.
+pub struct TableCache {
+    dbname_: Arc<dyn Path>,
+    options_: TableOptions,
+    tables_: Mutex<HashMap<u64, Arc<Table>>>,
+}
+
+impl TableCache {
+    pub fn new(dbname: Arc<dyn Path>, options: TableOptions) -> TableCache {
+        TableCache {
+            dbname_: dbname,
+            options_: options,
+            tables_: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the table numbered 'number', opening it if needed.
+    pub fn get(&self, number: u64) -> Result<Arc<Table>, DataStoreError> {
+        if let Some(table) = self.tables_.lock().unwrap().get(&number) {
+            return Ok(table.clone());
+        }
+        // Open without holding the lock. Concurrent opens of the same
+        // table are harmless, the first one is kept.
+        let table = Arc::new(Table::open(self.options_.clone(),
+            self.dbname_.join(&table_file_name(number)))?);
+        return Ok(self.tables_.lock().unwrap().entry(number).or_insert(table).clone());
+    }
+
+    /// Drops the table numbered 'number', once its file is deleted.
+    pub fn evict(&self, number: u64) {
+        self.tables_.lock().unwrap().remove(&number);
+    }
+}
@@ -0,0 +1,1128 @@
+#[cfg(test)]
+mod filename_test {
+    use crate::db::filename::{current_file_name, log_file_name, manifest_file_name,
+        parse_file_name, table_file_name, temp_file_name, FileType};
+
+    #[test]
+    fn test_file_names() {
+        assert_eq!(log_file_name(7), "000007.log");
+        assert_eq!(table_file_name(1234567), "1234567.sst");
+        assert_eq!(parse_file_name("000007.log"), Some((7, FileType::Log)));
+        assert_eq!(parse_file_name("1234567.sst"), Some((1234567, FileType::Table)));
+        assert_eq!(parse_file_name(&temp_file_name(3)), Some((3, FileType::Temp)));
+        assert_eq!(manifest_file_name(5), "MANIFEST-000005");
+        assert_eq!(parse_file_name("MANIFEST-000005"), Some((5, FileType::Manifest)));
+        assert_eq!(parse_file_name(&current_file_name()), Some((0, FileType::Current)));
+        for name in ["", "foo", ".log", "12.", "12.ldb", "-1.log", "1a.sst",
+            "99999999999999999999.log", "MANIFEST-", "MANIFEST-1a", "CURRENT.1"] {
+            assert_eq!(parse_file_name(name), None, "{}", name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod version_edit_test {
+    use std::sync::Arc;
+    use tempfile::TempDir;
+    use crate::comparator::BytewiseComparator;
+    use crate::db::version_edit::{FileMetaData, VersionEdit};
+    use crate::filesystem::{LocalPath, Path};
+    use crate::memtable::internal_key::{InternalKey, InternalKeyComparator, ValueType};
+    use crate::sst::lsm_error::DataStoreError;
+    use crate::sst::options::TableOptions;
+    use crate::sst::table::Table;
+    use crate::sst::table_builder::TableBuilder;
+
+    pub fn file(number: u64, smallest: &[u8], largest: &[u8], sequence: u64) -> FileMetaData {
+        FileMetaData {
+            number,
+            file_size: 1000 * number,
+            smallest: InternalKey::new(smallest, sequence, ValueType::Value),
+            largest: InternalKey::new(largest, sequence + 10, ValueType::Deletion),
+            smallest_sequence: sequence,
+            largest_sequence: sequence + 10,
+            creation_time: 1_700_000_000 + number,
+        }
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let mut edit = VersionEdit::new();
+        assert_eq!(VersionEdit::decode(&edit.encode()).unwrap(), edit);
+        edit.set_comparator_name("lsm.BytewiseComparator");
+        edit.set_merge_operator_name("lsm.StringAppendOperator");
+        edit.set_log_number(7);
+        edit.set_next_file_number(12);
+        edit.set_last_sequence(1 << 40);
+        edit.delete_file(0, 3);
+        edit.delete_file(2, 4);
+        edit.add_file(0, file(10, b"a", b"m", 100));
+        edit.add_file(1, file(11, b"", b"zzz", 0));
+        let decoded = VersionEdit::decode(&edit.encode()).unwrap();
+        assert_eq!(decoded, edit);
+        assert_eq!(decoded.log_number(), Some(7));
+        assert_eq!(decoded.merge_operator_name(), Some("lsm.StringAppendOperator"));
+        assert_eq!(decoded.new_files()[1].1.smallest.user_key(), b"");
+
+        let encoded = edit.encode();
+        for len in [1, encoded.len() - 1] {
+            assert!(matches!(VersionEdit::decode(&encoded[..len]),
+                Err(DataStoreError::MalformedVersionEdit(_))));
+        }
+        assert!(matches!(VersionEdit::decode(&[99, 1]),
+            Err(DataStoreError::MalformedVersionEdit(_))));
+    }
+
+    #[test]
+    fn test_file_creation_time() {
+        // Tables without a creation time are recorded as NEW_FILE fields
+        // only, and read back with a creation time of 0.
+        let mut edit = VersionEdit::new();
+        edit.add_file(0, FileMetaData {
+            creation_time: 0,
+            ..file(10, b"a", b"m", 100)
+        });
+        let encoded = edit.encode();
+        let mut timed = edit.clone();
+        timed.add_file(0, file(11, b"a", b"m", 100));
+        assert!(timed.encode().starts_with(&encoded));
+        assert_eq!(VersionEdit::decode(&encoded).unwrap(), edit);
+        assert_eq!(VersionEdit::decode(&timed.encode()).unwrap(), timed);
+        // The creation time must follow the table it applies to.
+        let mut encoded = encoded.clone();
+        encoded.extend_from_slice(&[8, 11, 1]);
+        assert!(matches!(VersionEdit::decode(&encoded),
+            Err(DataStoreError::MalformedVersionEdit(_))));
+    }
+
+    #[test]
+    fn test_from_empty_table() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path: Arc<dyn Path> = Arc::new(
+            LocalPath::from_std_path(tmp_dir.path().join("000001.sst").as_path()));
+        let comparator = InternalKeyComparator::new(Arc::new(BytewiseComparator));
+        let options = TableOptions {
+            comparator: Arc::new(comparator.clone()),
+            internal_keys: true,
+            ..TableOptions::default()
+        };
+        TableBuilder::new(options.clone(), path.as_ref()).unwrap().finish().unwrap();
+        let table = Table::open(options, path).unwrap();
+        assert!(matches!(FileMetaData::from_table(1, &table, &comparator),
+            Err(DataStoreError::Corruption { .. })));
+    }
+}
+
+#[cfg(test)]
+mod version_set_test {
+    use std::sync::Arc;
+    use tempfile::TempDir;
+    use crate::comparator::{BytewiseComparator, ReverseComparator};
+    use crate::db::filename::{manifest_file_name, read_current_file};
+    use crate::db::tests::version_edit_test::file;
+    use crate::db::version_edit::VersionEdit;
+    use crate::db::version_set::VersionSet;
+    use crate::filesystem::{LocalPath, Path};
+    use crate::memtable::internal_key::InternalKeyComparator;
+    use crate::merge_operator::{MergeOperator, StringAppendOperator};
+    use crate::sst::lsm_error::DataStoreError;
+
+    fn open(path: &Arc<dyn Path>) -> Result<VersionSet, DataStoreError> {
+        let mut versions = VersionSet::new(path.clone(),
+            InternalKeyComparator::new(Arc::new(BytewiseComparator)), None);
+        versions.recover()?;
+        return Ok(versions);
+    }
+
+    #[test]
+    fn test_log_and_apply_and_recover() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path: Arc<dyn Path> = Arc::new(LocalPath::from_std_path(tmp_dir.path()));
+        VersionSet::create(&path, &BytewiseComparator, None).unwrap();
+        {
+            let mut versions = open(&path).unwrap();
+            assert_eq!(versions.current().num_files(0), 0);
+            let numbers: Vec<u64> = (0..4).map(|_| versions.new_file_number()).collect();
+            let mut edit = VersionEdit::new();
+            edit.add_file(0, file(numbers[0], b"a", b"k", 10));
+            edit.add_file(0, file(numbers[1], b"c", b"z", 30));
+            edit.add_file(1, file(numbers[2], b"m", b"p", 1));
+            edit.add_file(1, file(numbers[3], b"b", b"d", 2));
+            edit.set_log_number(numbers[3]);
+            versions.set_last_sequence(40);
+            versions.log_and_apply(&mut edit).unwrap();
+            // A new MANIFEST was started.
+            assert_eq!(read_current_file(&path).unwrap(), versions.manifest_file_number());
+            assert!(versions.manifest_file_number() > 1);
+
+            let old = versions.current();
+            let mut edit = VersionEdit::new();
+            edit.delete_file(0, numbers[0]);
+            versions.log_and_apply(&mut edit).unwrap();
+            // Tables of Versions in use are live.
+            assert!(versions.live_files().contains(&numbers[0]));
+            drop(old);
+            assert!(!versions.live_files().contains(&numbers[0]));
+        }
+        let mut versions = open(&path).unwrap();
+        let current = versions.current();
+        assert_eq!(current.files(0).iter().map(|file| file.number).collect::<Vec<_>>(),
+            vec![3]);
+        // Levels other than 0 are in key order.
+        assert_eq!(current.files(1).iter().map(|file| file.number).collect::<Vec<_>>(),
+            vec![5, 4]);
+        assert_eq!(current.files(1)[0].as_ref(), &file(5, b"b", b"d", 2));
+        assert_eq!(current.level_size(1), 9000);
+        assert_eq!(versions.log_number(), 5);
+        assert_eq!(versions.last_sequence(), 40);
+        assert!(versions.new_file_number() > read_current_file(&path).unwrap());
+        // The MANIFEST is replaced on the next edit.
+        let manifest_number = read_current_file(&path).unwrap();
+        versions.log_and_apply(&mut VersionEdit::new()).unwrap();
+        assert!(versions.manifest_file_number() > manifest_number);
+        assert_eq!(open(&path).unwrap().current().num_files(1), 2);
+    }
+
+    #[test]
+    fn test_manifest_write_outside_of_the_set() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path: Arc<dyn Path> = Arc::new(LocalPath::from_std_path(tmp_dir.path()));
+        VersionSet::create(&path, &BytewiseComparator, None).unwrap();
+        let mut versions = open(&path).unwrap();
+        let number = versions.new_file_number();
+        let mut edit = VersionEdit::new();
+        edit.add_file(0, file(number, b"a", b"k", 10));
+        let mut write = versions.prepare_edit(&mut edit);
+        assert!(versions.is_writing_manifest());
+        // The set is usable during the write, the edit is not applied yet.
+        versions.set_last_sequence(20);
+        assert!(versions.new_file_number() > number);
+        assert_eq!(versions.current().num_files(0), 0);
+        let result = write.write();
+        versions.finish_edit(write, result).unwrap();
+        assert!(!versions.is_writing_manifest());
+        assert_eq!(versions.current().num_files(0), 1);
+        assert_eq!(read_current_file(&path).unwrap(), versions.manifest_file_number());
+        assert_eq!(open(&path).unwrap().current().num_files(0), 1);
+    }
+
+    #[test]
+    fn test_recover_checks_comparator() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path: Arc<dyn Path> = Arc::new(LocalPath::from_std_path(tmp_dir.path()));
+        VersionSet::create(&path, &ReverseComparator, None).unwrap();
+        assert!(matches!(open(&path), Err(DataStoreError::InvalidArgument(_))));
+        let mut versions = VersionSet::new(path.clone(),
+            InternalKeyComparator::new(Arc::new(ReverseComparator)), None);
+        versions.recover().unwrap();
+        assert!(path.get_file_system().exists(path.join(&manifest_file_name(1)).as_ref()));
+    }
+
+    #[test]
+    fn test_recover_checks_merge_operator() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path: Arc<dyn Path> = Arc::new(LocalPath::from_std_path(tmp_dir.path()));
+        let comparator = InternalKeyComparator::new(Arc::new(BytewiseComparator));
+        let append = StringAppendOperator::new(b',');
+        VersionSet::create(&path, &BytewiseComparator, None).unwrap();
+        // A merge operator can be added to a DB written without one.
+        let mut versions = VersionSet::new(path.clone(), comparator.clone(), Some(&append));
+        versions.recover().unwrap();
+        versions.log_and_apply(&mut VersionEdit::new()).unwrap();
+        drop(versions);
+
+        // It is recorded in the new MANIFEST, and required from now on.
+        assert!(matches!(open(&path), Err(DataStoreError::InvalidArgument(_))));
+        let mut versions = VersionSet::new(path.clone(), comparator.clone(),
+            Some(&RenamedOperator));
+        assert!(matches!(versions.recover(), Err(DataStoreError::InvalidArgument(_))));
+        let mut versions = VersionSet::new(path.clone(), comparator, Some(&append));
+        versions.recover().unwrap();
+    }
+
+    struct RenamedOperator;
+
+    impl MergeOperator for RenamedOperator {
+        fn full_merge(&self, _key: &[u8], _existing: Option<&[u8]>, _operands: &[&[u8]])
+            -> Vec<u8> {
+            return vec!();
+        }
+
+        fn name(&self) -> &str {
+            "test.RenamedOperator"
+        }
+    }
+}
+
+#[cfg(test)]
+mod db_test {
+    use std::sync::Arc;
+    use tempfile::TempDir;
+    use crate::db::DB;
+    use crate::db::filename::{log_file_name, parse_file_name, table_file_name, FileType};
+    use crate::db::options::{Options, WriteOptions};
+    use crate::filesystem::{LocalPath, Path};
+    use crate::merge_operator::StringAppendOperator;
+    use crate::sst::filter::BloomFilterPolicy;
+    use crate::sst::lsm_error::DataStoreError;
+    use crate::wal::WalRecoveryMode;
+    use crate::wal::writer::Writer;
+    use crate::write_batch::WriteBatch;
+
+    fn db_path(dir: &TempDir) -> Arc<dyn Path> {
+        return Arc::new(LocalPath::from_std_path(dir.path().join("db").as_path()));
+    }
+
+    fn options() -> Options {
+        Options {
+            create_if_missing: true,
+            ..Options::default()
+        }
+    }
+
+    #[test]
+    fn test_put_get_delete() {
+        let tmp_dir = TempDir::new().unwrap();
+        let db = DB::open(db_path(&tmp_dir), options()).unwrap();
+        assert_eq!(db.get(b"foo").unwrap(), None);
+        db.put(b"foo", b"v1").unwrap();
+        assert_eq!(db.get(b"foo").unwrap(), Some(b"v1".to_vec()));
+        db.put(b"bar", b"v2").unwrap();
+        db.put(b"foo", b"v3").unwrap();
+        assert_eq!(db.get(b"foo").unwrap(), Some(b"v3".to_vec()));
+        assert_eq!(db.get(b"bar").unwrap(), Some(b"v2".to_vec()));
+        db.delete(b"foo").unwrap();
+        assert_eq!(db.get(b"foo").unwrap(), None);
+        // Deleting a missing key is not an error.
+        db.delete(b"missing").unwrap();
+        db.put(b"", b"empty key").unwrap();
+        assert_eq!(db.get(b"").unwrap(), Some(b"empty key".to_vec()));
+    }
+
+    #[test]
+    fn test_write_batch() {
+        let tmp_dir = TempDir::new().unwrap();
+        let db = DB::open(db_path(&tmp_dir), Options {
+            merge_operator: Some(Arc::new(StringAppendOperator::new(b','))),
+            ..options()
+        }).unwrap();
+        db.put(b"a", b"va").unwrap();
+        db.put(b"c", b"vc").unwrap();
+        let mut batch = WriteBatch::new();
+        batch.put(b"b", b"vb");
+        batch.delete(b"a");
+        batch.merge(b"b", b"m1");
+        batch.merge(b"d", b"m2");
+        batch.delete_range(b"c", b"d");
+        db.write_with_options(&WriteOptions { sync: true }, batch).unwrap();
+        assert_eq!(db.get(b"a").unwrap(), None);
+        assert_eq!(db.get(b"b").unwrap(), Some(b"vb,m1".to_vec()));
+        assert_eq!(db.get(b"c").unwrap(), None);
+        assert_eq!(db.get(b"d").unwrap(), Some(b"m2".to_vec()));
+        db.write(WriteBatch::new()).unwrap();
+        drop(db);
+        // The merge operands can't be read without the merge operator.
+        assert!(matches!(DB::open(db_path(&tmp_dir), options()),
+            Err(DataStoreError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn test_log_write_error_stops_writes() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = db_path(&tmp_dir);
+        let db = DB::open(path.clone(), options()).unwrap();
+        db.put(b"a", b"va").unwrap();
+        // Appending to a removed log fails.
+        let fs = path.get_file_system();
+        for name in fs.children(path.as_ref()).unwrap() {
+            if parse_file_name(&name).is_some_and(|(_, file_type)| file_type == FileType::Log) {
+                fs.remove(path.join(&name).as_ref()).unwrap();
+            }
+        }
+        assert!(matches!(db.put(b"b", b"vb"), Err(DataStoreError::IOError(_))));
+        // Later writes are not appended after a possibly partial record.
+        assert!(matches!(db.put(b"c", b"vc"), Err(DataStoreError::BackgroundError(_))));
+        assert_eq!(db.get(b"a").unwrap(), Some(b"va".to_vec()));
+        assert_eq!(db.get(b"b").unwrap(), None);
+    }
+
+    #[test]
+    fn test_rejected_batch_is_not_logged() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = db_path(&tmp_dir);
+        {
+            let db = DB::open(path.clone(), options()).unwrap();
+            let mut batch = WriteBatch::new();
+            batch.put(b"a", b"va");
+            batch.merge(b"a", b"m1");
+            assert!(matches!(db.write(batch), Err(DataStoreError::NotSupported(_))));
+            db.put(b"b", b"vb").unwrap();
+        }
+        let db = DB::open(path, options()).unwrap();
+        assert_eq!(db.get(b"a").unwrap(), None);
+        assert_eq!(db.get(b"b").unwrap(), Some(b"vb".to_vec()));
+    }
+
+    #[test]
+    fn test_reopen() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = db_path(&tmp_dir);
+        {
+            let db = DB::open(path.clone(), options()).unwrap();
+            db.put(b"foo", b"v1").unwrap();
+            db.put(b"baz", b"v5").unwrap();
+            db.delete(b"baz").unwrap();
+        }
+        {
+            let db = DB::open(path.clone(), options()).unwrap();
+            assert_eq!(db.get(b"foo").unwrap(), Some(b"v1".to_vec()));
+            assert_eq!(db.get(b"baz").unwrap(), None);
+            // Writes after a reopen are newer than the recovered ones.
+            db.put(b"foo", b"v2").unwrap();
+            db.put(b"baz", b"v6").unwrap();
+        }
+        let db = DB::open(path, options()).unwrap();
+        assert_eq!(db.get(b"foo").unwrap(), Some(b"v2".to_vec()));
+        assert_eq!(db.get(b"baz").unwrap(), Some(b"v6".to_vec()));
+    }
+
+    #[test]
+    fn test_create_if_missing_and_error_if_exists() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = db_path(&tmp_dir);
+        assert!(matches!(DB::open(path.clone(), Options::default()),
+            Err(DataStoreError::InvalidArgument(_))));
+        assert!(!path.get_file_system().exists(path.as_ref()));
+        DB::open(path.clone(), options()).unwrap().put(b"k", b"v").unwrap();
+        assert!(matches!(DB::open(path.clone(), Options { error_if_exists: true, ..options() }),
+            Err(DataStoreError::InvalidArgument(_))));
+        let db = DB::open(path, Options::default()).unwrap();
+        assert_eq!(db.get(b"k").unwrap(), Some(b"v".to_vec()));
+    }
+
+    /// Writes three logs, as left by a crash with several logs pending,
+    /// each putting its key and "last". The second log is corrupted.
+    fn write_corrupted_logs(path: &Arc<dyn Path>) {
+        path.get_file_system().create_dir_all(path.as_ref()).unwrap();
+        for (number, (key, value)) in [(b"a", b"v1"), (b"b", b"v2"), (b"c", b"v3")]
+            .into_iter().enumerate() {
+            let mut batch = WriteBatch::new();
+            batch.put(key, value);
+            batch.put(b"last", value);
+            batch.set_sequence(2 * number as u64 + 1);
+            let log = path.join(&log_file_name(number as u64 + 1));
+            let mut writer = Writer::new(log.clone()).unwrap();
+            writer.add_record(batch.data()).unwrap();
+            writer.sync().unwrap();
+            if number == 1 {
+                let std_path = std::path::Path::new(log.as_os_str());
+                let mut contents = std::fs::read(std_path).unwrap();
+                let len = contents.len();
+                contents[len - 1] ^= 0x01;
+                std::fs::write(std_path, contents).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_point_in_time_recovery_across_logs() {
+        let open = |mode| {
+            let tmp_dir = TempDir::new().unwrap();
+            let path = db_path(&tmp_dir);
+            write_corrupted_logs(&path);
+            let db = DB::open(path, Options {
+                wal_recovery_mode: mode,
+                ..options()
+            });
+            (tmp_dir, db)
+        };
+        assert!(matches!(open(WalRecoveryMode::AbsoluteConsistency).1,
+            Err(DataStoreError::Corruption { .. })));
+        // Recovery stops at the corruption, ignoring the third log.
+        let (tmp_dir, db) = open(WalRecoveryMode::PointInTimeRecovery);
+        let db = db.unwrap();
+        assert_eq!(db.get(b"b").unwrap(), None);
+        assert_eq!(db.get(b"c").unwrap(), None);
+        assert_eq!(db.get(b"last").unwrap(), Some(b"v1".to_vec()));
+        // The ignored logs are deleted, so new writes reusing their sequence
+        // numbers are not mixed with them on the next open.
+        let path = db_path(&tmp_dir);
+        for number in 1..=3 {
+            assert!(!path.get_file_system().exists(path.join(&log_file_name(number)).as_ref()));
+        }
+        db.put(b"d", b"v4").unwrap();
+        drop(db);
+        let db = DB::open(path, options()).unwrap();
+        assert_eq!(db.get(b"c").unwrap(), None);
+        assert_eq!(db.get(b"d").unwrap(), Some(b"v4".to_vec()));
+        assert_eq!(db.get(b"last").unwrap(), Some(b"v1".to_vec()));
+        let (_tmp_dir, db) = open(WalRecoveryMode::SkipAnyCorruptedRecords);
+        let db = db.unwrap();
+        assert_eq!(db.get(b"b").unwrap(), None);
+        assert_eq!(db.get(b"c").unwrap(), Some(b"v3".to_vec()));
+        assert_eq!(db.get(b"last").unwrap(), Some(b"v3".to_vec()));
+    }
+
+    #[test]
+    fn test_concurrent_writers() {
+        let tmp_dir = TempDir::new().unwrap();
+        let db = Arc::new(DB::open(db_path(&tmp_dir), options()).unwrap());
+        let threads: Vec<_> = (0..4).map(|t| {
+            let db = db.clone();
+            std::thread::spawn(move || {
+                for i in 0..250 {
+                    db.put(format!("{}-{}", t, i).as_bytes(), &[t as u8; 10]).unwrap();
+                }
+            })
+        }).collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        for t in 0..4 {
+            for i in 0..250 {
+                assert_eq!(db.get(format!("{}-{}", t, i).as_bytes()).unwrap(),
+                    Some(vec![t as u8; 10]));
+            }
+        }
+    }
+
+    fn file_names(path: &Arc<dyn Path>) -> Vec<(u64, FileType)> {
+        let mut files: Vec<_> = path.get_file_system().children(path.as_ref()).unwrap()
+            .iter().filter_map(|name| parse_file_name(name)).collect();
+        files.sort_unstable_by_key(|(number, _)| *number);
+        return files;
+    }
+
+    #[test]
+    fn test_flush_to_level0() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = db_path(&tmp_dir);
+        let options = Options {
+            write_buffer_size: 32 * 1024,
+            // Keeps the tables in level 0.
+            level0_file_num_compaction_trigger: 100,
+            ..options()
+        };
+        let value = |i: usize| format!("value{:0100}", i).into_bytes();
+        {
+            let db = DB::open(path.clone(), options.clone()).unwrap();
+            for i in 0..2000 {
+                db.put(format!("key{:05}", i % 1000).as_bytes(), &value(i)).unwrap();
+            }
+            assert!(db.num_files_at_level(0) > 1);
+            for i in 0..1000 {
+                assert_eq!(db.get(format!("key{:05}", i).as_bytes()).unwrap(),
+                    Some(value(i + 1000)));
+            }
+            db.flush().unwrap();
+            // Logs holding flushed writes are deleted.
+            let files = file_names(&path);
+            assert_eq!(files.iter().filter(|(_, file_type)| *file_type == FileType::Log)
+                .count(), 1);
+            assert_eq!(files.iter().filter(|(_, file_type)| *file_type == FileType::Table)
+                .count(), db.num_files_at_level(0));
+            // Flushing an empty MemTable does nothing.
+            let num_files = db.num_files_at_level(0);
+            db.flush().unwrap();
+            assert_eq!(db.num_files_at_level(0), num_files);
+        }
+        let db = DB::open(path.clone(), options).unwrap();
+        for i in 0..1000 {
+            assert_eq!(db.get(format!("key{:05}", i).as_bytes()).unwrap(),
+                Some(value(i + 1000)));
+        }
+        db.put(b"key00000", b"new").unwrap();
+        assert_eq!(db.get(b"key00000").unwrap(), Some(b"new".to_vec()));
+    }
+
+    #[test]
+    fn test_reads_across_memtable_and_tables() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = db_path(&tmp_dir);
+        let options = Options {
+            merge_operator: Some(Arc::new(StringAppendOperator::new(b','))),
+            ..options()
+        };
+        {
+            let db = DB::open(path.clone(), options.clone()).unwrap();
+            db.put(b"a", b"v1").unwrap();
+            db.put(b"b", b"v1").unwrap();
+            db.put(b"m", b"base").unwrap();
+            for key in [b"r1", b"r2", b"r3"] {
+                db.put(key, b"v1").unwrap();
+            }
+            db.flush().unwrap();
+            db.put(b"a", b"v2").unwrap();
+            db.delete(b"b").unwrap();
+            let mut batch = WriteBatch::new();
+            batch.merge(b"m", b"x");
+            batch.delete_range(b"r1", b"r3");
+            db.write(batch).unwrap();
+            let check = |db: &DB| {
+                assert_eq!(db.get(b"a").unwrap(), Some(b"v2".to_vec()));
+                assert_eq!(db.get(b"b").unwrap(), None);
+                assert_eq!(db.get(b"m").unwrap(), Some(b"base,x".to_vec()));
+                assert_eq!(db.get(b"r1").unwrap(), None);
+                assert_eq!(db.get(b"r2").unwrap(), None);
+                assert_eq!(db.get(b"r3").unwrap(), Some(b"v1".to_vec()));
+            };
+            check(&db);
+            db.flush().unwrap();
+            assert_eq!(db.num_files_at_level(0), 2);
+            check(&db);
+            // Writes newer than the range deletion are visible.
+            db.put(b"r2", b"v2").unwrap();
+            let mut batch = WriteBatch::new();
+            batch.merge(b"m", b"y");
+            db.write(batch).unwrap();
+            assert_eq!(db.get(b"r2").unwrap(), Some(b"v2".to_vec()));
+            assert_eq!(db.get(b"m").unwrap(), Some(b"base,x,y".to_vec()));
+        }
+        let db = DB::open(path, options).unwrap();
+        assert_eq!(db.num_files_at_level(0), 3);
+        assert_eq!(db.get(b"r1").unwrap(), None);
+        assert_eq!(db.get(b"r2").unwrap(), Some(b"v2".to_vec()));
+        assert_eq!(db.get(b"m").unwrap(), Some(b"base,x,y".to_vec()));
+    }
+
+    #[test]
+    fn test_filter_policy() {
+        let tmp_dir = TempDir::new().unwrap();
+        let mut options = options();
+        options.table_options.filter_policy = Some(Arc::new(BloomFilterPolicy::new(10)));
+        let db = DB::open(db_path(&tmp_dir), options).unwrap();
+        for i in 0..100 {
+            db.put(format!("key{}", i).as_bytes(), b"value").unwrap();
+        }
+        db.flush().unwrap();
+        for i in 0..100 {
+            assert_eq!(db.get(format!("key{}", i).as_bytes()).unwrap(), Some(b"value".to_vec()));
+            assert_eq!(db.get(format!("missing{}", i).as_bytes()).unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn test_manifest_tracks_tables() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = db_path(&tmp_dir);
+        {
+            let db = DB::open(path.clone(), options()).unwrap();
+            db.put(b"a", b"v1").unwrap();
+            db.flush().unwrap();
+            db.put(b"b", b"v2").unwrap();
+            db.flush().unwrap();
+            db.put(b"c", b"v3").unwrap();
+        }
+        // A table missing from the MANIFEST, as left by a crash before the
+        // flush was recorded, is deleted.
+        let orphan = path.join(&table_file_name(1000));
+        path.get_file_system().create(orphan.as_ref()).unwrap();
+        {
+            let db = DB::open(path.clone(), options()).unwrap();
+            assert_eq!(db.num_files_at_level(0), 3);
+            for (key, value) in [(b"a", b"v1"), (b"b", b"v2"), (b"c", b"v3")] {
+                assert_eq!(db.get(key).unwrap(), Some(value.to_vec()));
+            }
+            let files = file_names(&path);
+            assert!(!files.contains(&(1000, FileType::Table)));
+            // Only the MANIFEST in use is kept.
+            assert_eq!(files.iter().filter(|(_, file_type)| *file_type == FileType::Manifest)
+                .count(), 1);
+            assert!(files.contains(&(0, FileType::Current)));
+        }
+        // A table of the MANIFEST is missing.
+        let (number, _) = *file_names(&path).iter()
+            .find(|(_, file_type)| *file_type == FileType::Table).unwrap();
+        path.get_file_system().remove(path.join(&table_file_name(number)).as_ref()).unwrap();
+        assert!(matches!(DB::open(path, options()), Err(DataStoreError::Corruption { .. })));
+    }
+}
+
+#[cfg(test)]
+mod compaction_test {
+    use std::cmp::Ordering;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+    use crate::comparator::Comparator;
+    use crate::db::DB;
+    use crate::db::options::{CompactionOptionsFifo, CompactionOptionsUniversal, CompactionStyle,
+        Options};
+    use crate::db::version_set::NUM_LEVELS;
+    use crate::filesystem::{LocalPath, Path};
+    use crate::merge_operator::StringAppendOperator;
+    use crate::sst::lsm_error::DataStoreError;
+    use crate::sst::table::Table;
+    use crate::write_batch::WriteBatch;
+
+    fn db_path(dir: &TempDir) -> Arc<dyn Path> {
+        return Arc::new(LocalPath::from_std_path(dir.path().join("db").as_path()));
+    }
+
+    fn level_tables(db: &DB, level: usize) -> Vec<Arc<Table>> {
+        let version = db.inner_.state_.lock().unwrap().versions.current();
+        return version.files(level).iter()
+            .map(|file| db.inner_.table_cache_.get(file.number).unwrap())
+            .collect();
+    }
+
+    /// Checks that the tables of the levels from 1 on do not overlap.
+    fn check_levels(db: &DB) {
+        let comparator = db.internal_comparator();
+        let version = db.inner_.state_.lock().unwrap().versions.current();
+        for level in 1..NUM_LEVELS {
+            for pair in version.files(level).windows(2) {
+                assert_eq!(comparator.compare(pair[0].largest.encode(),
+                    pair[1].smallest.encode()), Ordering::Less, "level {}", level);
+            }
+        }
+    }
+
+    #[test]
+    fn test_leveled_compaction() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = db_path(&tmp_dir);
+        let options = Options {
+            create_if_missing: true,
+            write_buffer_size: 16 * 1024,
+            level0_file_num_compaction_trigger: 2,
+            max_bytes_for_level_base: 64 * 1024,
+            max_bytes_for_level_multiplier: 2.0,
+            target_file_size_base: 16 * 1024,
+            ..Options::default()
+        };
+        let mut expected = BTreeMap::new();
+        {
+            let db = DB::open(path.clone(), options.clone()).unwrap();
+            for round in 0..3 {
+                for i in 0..2000 {
+                    // Spread the keys of a round over the whole key range.
+                    let key = format!("key{:05}", (i * 7919) % 2000).into_bytes();
+                    if round > 0 && i % 5 == 0 {
+                        db.delete(&key).unwrap();
+                        expected.remove(&key);
+                    } else {
+                        let value = format!("value{}-{:090}", round, i).into_bytes();
+                        db.put(&key, &value).unwrap();
+                        expected.insert(key, value);
+                    }
+                }
+            }
+            db.wait_for_compact().unwrap();
+            assert!(db.num_files_at_level(0) < 2);
+            assert!((2..NUM_LEVELS).any(|level| db.num_files_at_level(level) > 0));
+            check_levels(&db);
+            for i in 0..2000 {
+                let key = format!("key{:05}", i).into_bytes();
+                assert_eq!(db.get(&key).unwrap(), expected.get(&key).cloned());
+            }
+        }
+        let db = DB::open(path, options).unwrap();
+        for i in 0..2000 {
+            let key = format!("key{:05}", i).into_bytes();
+            assert_eq!(db.get(&key).unwrap(), expected.get(&key).cloned());
+        }
+    }
+
+    #[test]
+    fn test_bottommost_compaction_drops_obsolete_entries() {
+        let tmp_dir = TempDir::new().unwrap();
+        let db = DB::open(db_path(&tmp_dir), Options {
+            create_if_missing: true,
+            merge_operator: Some(Arc::new(StringAppendOperator::new(b','))),
+            level0_file_num_compaction_trigger: 2,
+            ..Options::default()
+        }).unwrap();
+        for key in [b"a", b"b", b"c", b"d"] {
+            db.put(key, b"v1").unwrap();
+        }
+        db.put(b"m", b"base").unwrap();
+        db.flush().unwrap();
+        db.put(b"a", b"v2").unwrap();
+        db.delete(b"b").unwrap();
+        let mut batch = WriteBatch::new();
+        batch.delete_range(b"c", b"d");
+        batch.merge(b"m", b"x");
+        batch.merge(b"n", b"y");
+        db.write(batch).unwrap();
+        db.flush().unwrap();
+        db.wait_for_compact().unwrap();
+
+        assert_eq!(db.num_files_at_level(0), 0);
+        let tables = level_tables(&db, 1);
+        assert_eq!(tables.len(), 1);
+        let properties = tables[0].properties();
+        // a, d, m and n, with the merge operands combined.
+        assert_eq!(properties.num_entries, 4);
+        assert_eq!(properties.num_deletions, 0);
+        assert_eq!(properties.num_range_deletions, 0);
+        assert_eq!(db.get(b"a").unwrap(), Some(b"v2".to_vec()));
+        assert_eq!(db.get(b"b").unwrap(), None);
+        assert_eq!(db.get(b"c").unwrap(), None);
+        assert_eq!(db.get(b"d").unwrap(), Some(b"v1".to_vec()));
+        assert_eq!(db.get(b"m").unwrap(), Some(b"base,x".to_vec()));
+        assert_eq!(db.get(b"n").unwrap(), Some(b"y".to_vec()));
+    }
+
+    #[test]
+    fn test_compaction_keeps_deletions_above_older_levels() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = db_path(&tmp_dir);
+        let options = Options {
+            create_if_missing: true,
+            level0_file_num_compaction_trigger: 2,
+            max_bytes_for_level_base: 4 * 1024,
+            ..Options::default()
+        };
+        let key = |i: usize| format!("key{:03}", i).into_bytes();
+        let db = DB::open(path.clone(), options.clone()).unwrap();
+        for i in 0..100 {
+            db.put(&key(i), &[b'v'; 100]).unwrap();
+            if i == 50 {
+                db.flush().unwrap();
+            }
+        }
+        db.flush().unwrap();
+        db.wait_for_compact().unwrap();
+        // Level 1 went over its target size, and was compacted to level 2.
+        assert_eq!(db.num_files_at_level(1), 0);
+        assert!(db.num_files_at_level(2) > 0);
+
+        let mut batch = WriteBatch::new();
+        batch.delete_range(&key(0), &key(50));
+        batch.delete(&key(60));
+        db.write(batch).unwrap();
+        db.flush().unwrap();
+        db.put(b"z", b"v").unwrap();
+        db.flush().unwrap();
+        db.wait_for_compact().unwrap();
+        let tables = level_tables(&db, 1);
+        assert_eq!(tables.len(), 1);
+        assert_eq!(tables[0].properties().num_deletions, 1);
+        assert_eq!(tables[0].properties().num_range_deletions, 1);
+        check_levels(&db);
+        drop(db);
+
+        let db = DB::open(path, options).unwrap();
+        for i in 0..100 {
+            let expected = if i < 50 || i == 60 {
+                None
+            } else {
+                Some(vec![b'v'; 100])
+            };
+            assert_eq!(db.get(&key(i)).unwrap(), expected, "{}", i);
+        }
+        assert_eq!(db.get(b"z").unwrap(), Some(b"v".to_vec()));
+    }
+
+    fn num_sorted_runs(db: &DB) -> usize {
+        return db.num_files_at_level(0)
+            + (1..NUM_LEVELS).filter(|level| db.num_files_at_level(*level) > 0).count();
+    }
+
+    #[test]
+    fn test_universal_compaction() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = db_path(&tmp_dir);
+        let options = Options {
+            create_if_missing: true,
+            write_buffer_size: 16 * 1024,
+            level0_file_num_compaction_trigger: 4,
+            target_file_size_base: 16 * 1024,
+            compaction_style: CompactionStyle::Universal,
+            ..Options::default()
+        };
+        let mut expected = BTreeMap::new();
+        {
+            let db = DB::open(path.clone(), options.clone()).unwrap();
+            for round in 0..4 {
+                for i in 0..1000 {
+                    let key = format!("key{:05}", (i * 7919) % 1000).into_bytes();
+                    if round > 0 && i % 7 == 0 {
+                        db.delete(&key).unwrap();
+                        expected.remove(&key);
+                    } else {
+                        let value = format!("value{}-{:090}", round, i).into_bytes();
+                        db.put(&key, &value).unwrap();
+                        expected.insert(key, value);
+                    }
+                }
+            }
+            db.flush().unwrap();
+            db.wait_for_compact().unwrap();
+            assert!(num_sorted_runs(&db) < 4);
+            // Sorted runs merged with the oldest one end up in the last level.
+            assert!(db.num_files_at_level(NUM_LEVELS - 1) > 0);
+            check_levels(&db);
+            for i in 0..1000 {
+                let key = format!("key{:05}", i).into_bytes();
+                assert_eq!(db.get(&key).unwrap(), expected.get(&key).cloned());
+            }
+        }
+        let db = DB::open(path, options).unwrap();
+        for i in 0..1000 {
+            let key = format!("key{:05}", i).into_bytes();
+            assert_eq!(db.get(&key).unwrap(), expected.get(&key).cloned());
+        }
+    }
+
+    #[test]
+    fn test_universal_compaction_merges_runs_of_similar_sizes() {
+        let tmp_dir = TempDir::new().unwrap();
+        let db = DB::open(db_path(&tmp_dir), Options {
+            create_if_missing: true,
+            level0_file_num_compaction_trigger: 3,
+            compaction_style: CompactionStyle::Universal,
+            compaction_options_universal: CompactionOptionsUniversal {
+                // Never merges all the runs for their space amplification.
+                max_size_amplification_percent: u64::MAX,
+                ..CompactionOptionsUniversal::default()
+            },
+            ..Options::default()
+        }).unwrap();
+        let write_run = |first: usize, count: usize| {
+            for i in first..first + count {
+                db.put(format!("key{:05}", i).as_bytes(), &[b'v'; 100]).unwrap();
+            }
+            db.flush().unwrap();
+        };
+        write_run(0, 1000);
+        write_run(1000, 10);
+        write_run(1010, 10);
+        db.wait_for_compact().unwrap();
+        // The two small runs are merged into a level 0 table, above the
+        // large run.
+        assert_eq!(db.num_files_at_level(0), 2);
+        let tables = level_tables(&db, 0);
+        assert_eq!(tables[0].properties().num_entries, 20);
+        assert_eq!(tables[1].properties().num_entries, 1000);
+
+        write_run(1020, 10);
+        db.delete(b"key00000").unwrap();
+        db.flush().unwrap();
+        db.wait_for_compact().unwrap();
+        // Too many runs: the newest ones are merged, though much smaller
+        // than the others.
+        assert_eq!(num_sorted_runs(&db), 2);
+        assert_eq!(db.get(b"key00000").unwrap(), None);
+        for i in 1..1030 {
+            assert_eq!(db.get(format!("key{:05}", i).as_bytes()).unwrap(),
+                Some(vec![b'v'; 100]));
+        }
+    }
+
+    #[test]
+    fn test_universal_compaction_min_merge_width_above_trigger() {
+        let tmp_dir = TempDir::new().unwrap();
+        let db = DB::open(db_path(&tmp_dir), Options {
+            create_if_missing: true,
+            level0_file_num_compaction_trigger: 2,
+            compaction_style: CompactionStyle::Universal,
+            compaction_options_universal: CompactionOptionsUniversal {
+                min_merge_width: 3,
+                max_merge_width: 3,
+                max_size_amplification_percent: u64::MAX,
+                ..CompactionOptionsUniversal::default()
+            },
+            ..Options::default()
+        }).unwrap();
+        // Each run is much smaller than the older one, so runs of similar
+        // sizes are never picked.
+        let write_run = |first: usize, count: usize| {
+            for i in first..first + count {
+                db.put(format!("key{:05}", i).as_bytes(), &[b'v'; 100]).unwrap();
+            }
+            db.flush().unwrap();
+        };
+        write_run(0, 1000);
+        write_run(1000, 100);
+        db.wait_for_compact().unwrap();
+        // Above the trigger, but too few runs to merge.
+        assert_eq!(num_sorted_runs(&db), 2);
+        write_run(1100, 10);
+        db.wait_for_compact().unwrap();
+        assert_eq!(num_sorted_runs(&db), 1);
+        for i in 0..1110 {
+            assert_eq!(db.get(format!("key{:05}", i).as_bytes()).unwrap(),
+                Some(vec![b'v'; 100]));
+        }
+    }
+
+    #[test]
+    fn test_fifo_compaction_caps_size() {
+        let tmp_dir = TempDir::new().unwrap();
+        let db = DB::open(db_path(&tmp_dir), Options {
+            create_if_missing: true,
+            compaction_style: CompactionStyle::Fifo,
+            compaction_options_fifo: CompactionOptionsFifo {
+                max_table_files_size: 40 * 1024,
+                ..CompactionOptionsFifo::default()
+            },
+            ..Options::default()
+        }).unwrap();
+        let key = |i: usize| format!("key{:05}", i).into_bytes();
+        for batch in 0..10 {
+            for i in batch * 100..(batch + 1) * 100 {
+                db.put(&key(i), &[b'v'; 100]).unwrap();
+            }
+            db.flush().unwrap();
+        }
+        db.wait_for_compact().unwrap();
+        let version = db.inner_.state_.lock().unwrap().versions.current();
+        assert!(version.level_size(0) <= 40 * 1024);
+        assert!(version.num_files(0) < 10);
+        assert!((1..NUM_LEVELS).all(|level| version.num_files(level) == 0));
+        // The oldest tables were deleted.
+        assert_eq!(db.get(&key(0)).unwrap(), None);
+        assert_eq!(db.get(&key(999)).unwrap(), Some(vec![b'v'; 100]));
+    }
+
+    #[test]
+    fn test_fifo_compaction_ttl() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = db_path(&tmp_dir);
+        let options = Options {
+            create_if_missing: true,
+            compaction_style: CompactionStyle::Fifo,
+            compaction_options_fifo: CompactionOptionsFifo {
+                ttl: 1,
+                ..CompactionOptionsFifo::default()
+            },
+            ..Options::default()
+        };
+        let db = DB::open(path.clone(), options.clone()).unwrap();
+        db.put(b"old", b"v").unwrap();
+        db.flush().unwrap();
+        assert_eq!(db.num_files_at_level(0), 1);
+        std::thread::sleep(std::time::Duration::from_millis(2100));
+        db.put(b"new", b"v").unwrap();
+        db.flush().unwrap();
+        db.wait_for_compact().unwrap();
+        assert_eq!(db.num_files_at_level(0), 1);
+        assert_eq!(db.get(b"old").unwrap(), None);
+        assert_eq!(db.get(b"new").unwrap(), Some(b"v".to_vec()));
+        drop(db);
+
+        // Tables which expired while the DB was closed are deleted on open.
+        std::thread::sleep(std::time::Duration::from_millis(2100));
+        let db = DB::open(path, options).unwrap();
+        db.wait_for_compact().unwrap();
+        assert_eq!(db.num_files_at_level(0), 0);
+        assert_eq!(db.get(b"new").unwrap(), None);
+    }
+
+    #[test]
+    fn test_fifo_compaction_ttl_without_writes() {
+        let tmp_dir = TempDir::new().unwrap();
+        let db = DB::open(db_path(&tmp_dir), Options {
+            create_if_missing: true,
+            compaction_style: CompactionStyle::Fifo,
+            compaction_options_fifo: CompactionOptionsFifo {
+                ttl: 1,
+                ..CompactionOptionsFifo::default()
+            },
+            ..Options::default()
+        }).unwrap();
+        db.put(b"old", b"v").unwrap();
+        db.flush().unwrap();
+        assert_eq!(db.num_files_at_level(0), 1);
+        // Nothing is flushed nor compacted from now on, the table is
+        // deleted by the periodic check.
+        let start = std::time::Instant::now();
+        while db.num_files_at_level(0) > 0 && start.elapsed().as_secs() < 10 {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        assert_eq!(db.num_files_at_level(0), 0);
+        assert_eq!(db.get(b"old").unwrap(), None);
+    }
+
+    #[test]
+    fn test_fifo_compaction_rejects_tables_below_level0() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = db_path(&tmp_dir);
+        {
+            let db = DB::open(path.clone(), Options {
+                create_if_missing: true,
+                level0_file_num_compaction_trigger: 2,
+                ..Options::default()
+            }).unwrap();
+            for key in [b"a", b"b"] {
+                db.put(key, b"v").unwrap();
+                db.flush().unwrap();
+            }
+            db.wait_for_compact().unwrap();
+            assert_eq!(db.num_files_at_level(0), 0);
+        }
+        assert!(matches!(DB::open(path, Options {
+            compaction_style: CompactionStyle::Fifo,
+            ..Options::default()
+        }), Err(DataStoreError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn test_fifo_compaction_merges_small_tables() {
+        let tmp_dir = TempDir::new().unwrap();
+        let db = DB::open(db_path(&tmp_dir), Options {
+            create_if_missing: true,
+            level0_file_num_compaction_trigger: 3,
+            target_file_size_base: 16 * 1024,
+            compaction_style: CompactionStyle::Fifo,
+            compaction_options_fifo: CompactionOptionsFifo {
+                allow_compaction: true,
+                ..CompactionOptionsFifo::default()
+            },
+            ..Options::default()
+        }).unwrap();
+        let key = |i: usize| format!("key{:05}", i).into_bytes();
+        // A large table, which is never rewritten.
+        for i in 0..200 {
+            db.put(&key(i), &[b'v'; 100]).unwrap();
+        }
+        db.flush().unwrap();
+        let large = db.inner_.state_.lock().unwrap().versions.current().files(0)[0].clone();
+        assert!(large.file_size >= 16 * 1024);
+        // Small tables, merged once three of them add up to the target
+        // file size.
+        let mut oldest_creation_time = 0;
+        for i in 200..260 {
+            db.put(&key(i), &[b'v'; 300]).unwrap();
+            if i == 219 {
+                db.flush().unwrap();
+                let version = db.inner_.state_.lock().unwrap().versions.current();
+                oldest_creation_time = version.files(0)[0].creation_time;
+                // The next tables are created later.
+                std::thread::sleep(std::time::Duration::from_millis(1100));
+            } else if i % 20 == 19 {
+                db.flush().unwrap();
+            }
+            if i == 239 {
+                // Two small tables are not merged.
+                db.wait_for_compact().unwrap();
+                assert_eq!(db.inner_.state_.lock().unwrap().versions.current().num_files(0), 3);
+            }
+        }
+        db.wait_for_compact().unwrap();
+        let version = db.inner_.state_.lock().unwrap().versions.current();
+        assert_eq!(version.num_files(0), 2);
+        assert_eq!(version.files(0)[1], large);
+        let tables = level_tables(&db, 0);
+        assert_eq!(tables[0].properties().num_entries, 60);
+        // The merged table is as old as the oldest table merged.
+        assert_eq!(version.files(0)[0].creation_time, oldest_creation_time);
+        assert_eq!(tables[0].properties().creation_time, oldest_creation_time);
+        for i in 0..260 {
+            let size = if i < 200 { 100 } else { 300 };
+            assert_eq!(db.get(&key(i)).unwrap(), Some(vec![b'v'; size]));
+        }
+        // The merged table is not small, so a stable level 0 is not
+        // compacted again.
+        let numbers: Vec<u64> = version.files(0).iter().map(|file| file.number).collect();
+        db.put(&key(260), &[b'v'; 300]).unwrap();
+        db.flush().unwrap();
+        db.wait_for_compact().unwrap();
+        let version = db.inner_.state_.lock().unwrap().versions.current();
+        assert_eq!(version.num_files(0), 3);
+        assert_eq!(version.files(0)[1..].iter().map(|file| file.number).collect::<Vec<_>>(),
+            numbers);
+    }
+}
@@ -0,0 +1,305 @@
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use crate::coding::{get_varint, put_varint};
+use crate::comparator::Comparator;
+use crate::db::filename::table_file_name;
+use crate::memtable::internal_key::{InternalKey, InternalKeyComparator, SequenceNumber,
+    ValueType, MAX_SEQUENCE_NUMBER};
+use crate::sst::lsm_error::DataStoreError;
+use crate::sst::table::Table;
+
+// A VersionEdit is encoded as a sequence of fields, each a varint tag
+// followed by its value:
+//  COMPARATOR       : length prefixed name of the user comparator
+//  LOG_NUMBER       : varint
+//  NEXT_FILE_NUMBER : varint
+//  LAST_SEQUENCE    : varint
+//  DELETED_FILE     : varint level, varint file number
+//  NEW_FILE         : varint level, varint file number, varint file size,
+//                     length prefixed smallest and largest internal keys,
+//                     varint smallest and largest sequence numbers
+//  MERGE_OPERATOR   : length prefixed name of the merge operator
+//  FILE_CREATION_TIME : varint file number, varint creation time, of a
+//                       table added by a previous NEW_FILE field
+const COMPARATOR: u64 = 1;
+const LOG_NUMBER: u64 = 2;
+const NEXT_FILE_NUMBER: u64 = 3;
+const LAST_SEQUENCE: u64 = 4;
+const DELETED_FILE: u64 = 5;
+const NEW_FILE: u64 = 6;
+const MERGE_OPERATOR: u64 = 7;
+const FILE_CREATION_TIME: u64 = 8;
+
+/// Description of a table of the DB.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileMetaData {
+    pub number: u64,
+    pub file_size: u64,
+    /// Smallest and largest internal keys of the table, range deletions
+    /// included.
+    pub smallest: InternalKey,
+    pub largest: InternalKey,
+    pub smallest_sequence: SequenceNumber,
+    pub largest_sequence: SequenceNumber,
+    /// Creation time of the table, from its properties. 0 if unknown.
+    pub creation_time: u64,
+}
+
+impl FileMetaData {
+    /// Describes 'table', numbered 'number', from its properties and range
+    /// deletions. A range deletion ending at 'end' extends the table up to
+    /// the first internal key of 'end', exclusive. Fails with
+    /// DataStoreError::Corruption if the table has neither entries nor range
+    /// deletions.
+    pub fn from_table(number: u64, table: &Table,
+        comparator: &InternalKeyComparator) -> Result<FileMetaData, DataStoreError> {
+        let properties = table.properties();
+        let mut bounds: Option<(InternalKey, InternalKey)> = None;
+        if properties.num_entries > 0 {
+            bounds = Some((InternalKey::decode_from(&properties.smallest_key),
+                InternalKey::decode_from(&properties.largest_key)));
+        }
+        for (begin, end) in table.range_deletions() {
+            let begin = InternalKey::decode_from(begin);
+            let end = InternalKey::new(end, MAX_SEQUENCE_NUMBER, ValueType::RangeDeletion);
+            bounds = Some(match bounds {
+                None => (begin, end),
+                Some((smallest, largest)) => (
+                    if comparator.compare(begin.encode(), smallest.encode()) == Ordering::Less {
+                        begin
+                    } else {
+                        smallest
+                    },
+                    if comparator.compare(end.encode(), largest.encode()) == Ordering::Greater {
+                        end
+                    } else {
+                        largest
+                    }),
+            });
+        }
+        let (smallest, largest) = bounds.ok_or_else(|| DataStoreError::Corruption {
+            file: table_file_name(number),
+            offset: 0,
+            reason: "table has no entries".to_string(),
+        })?;
+        return Ok(FileMetaData {
+            number,
+            file_size: table.file_size(),
+            smallest,
+            largest,
+            smallest_sequence: properties.smallest_sequence,
+            largest_sequence: properties.largest_sequence,
+            creation_time: properties.creation_time,
+        });
+    }
+}
+
+/// Change from a Version of the DB to the next one: tables added and
+/// deleted, and the counters of the DB. VersionEdits are recorded in the
+/// MANIFEST, replaying them recovers the current Version.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VersionEdit {
+    comparator_: Option<String>,
+    merge_operator_: Option<String>,
+    log_number_: Option<u64>,
+    next_file_number_: Option<u64>,
+    last_sequence_: Option<SequenceNumber>,
+    deleted_files_: BTreeSet<(usize, u64)>,
+    new_files_: Vec<(usize, FileMetaData)>,
+}
+
+impl VersionEdit {
+    pub fn new() -> VersionEdit {
+        Self::default()
+    }
+
+    /// Records the name of the user comparator, checked on recovery.
+    pub fn set_comparator_name(&mut self, name: &str) {
+        self.comparator_ = Some(name.to_string());
+    }
+
+    /// Records the name of the merge operator, checked on recovery.
+    pub fn set_merge_operator_name(&mut self, name: &str) {
+        self.merge_operator_ = Some(name.to_string());
+    }
+
+    /// Logs numbered before 'number' hold no writes missing from tables.
+    pub fn set_log_number(&mut self, number: u64) {
+        self.log_number_ = Some(number);
+    }
+
+    pub fn set_next_file_number(&mut self, number: u64) {
+        self.next_file_number_ = Some(number);
+    }
+
+    pub fn set_last_sequence(&mut self, sequence: SequenceNumber) {
+        self.last_sequence_ = Some(sequence);
+    }
+
+    pub fn add_file(&mut self, level: usize, file: FileMetaData) {
+        self.new_files_.push((level, file));
+    }
+
+    pub fn delete_file(&mut self, level: usize, number: u64) {
+        self.deleted_files_.insert((level, number));
+    }
+
+    #[inline(always)]
+    pub fn comparator_name(&self) -> Option<&str> {
+        self.comparator_.as_deref()
+    }
+
+    #[inline(always)]
+    pub fn merge_operator_name(&self) -> Option<&str> {
+        self.merge_operator_.as_deref()
+    }
+
+    #[inline(always)]
+    pub fn log_number(&self) -> Option<u64> {
+        self.log_number_
+    }
+
+    #[inline(always)]
+    pub fn next_file_number(&self) -> Option<u64> {
+        self.next_file_number_
+    }
+
+    #[inline(always)]
+    pub fn last_sequence(&self) -> Option<SequenceNumber> {
+        self.last_sequence_
+    }
+
+    /// (level, number) of the tables deleted.
+    #[inline(always)]
+    pub fn deleted_files(&self) -> &BTreeSet<(usize, u64)> {
+        &self.deleted_files_
+    }
+
+    /// (level, table) of the tables added.
+    #[inline(always)]
+    pub fn new_files(&self) -> &[(usize, FileMetaData)] {
+        &self.new_files_
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut dst = vec!();
+        if let Some(name) = self.comparator_.as_ref() {
+            put_varint(&mut dst, COMPARATOR);
+            put_length_prefixed(&mut dst, name.as_bytes());
+        }
+        if let Some(name) = self.merge_operator_.as_ref() {
+            put_varint(&mut dst, MERGE_OPERATOR);
+            put_length_prefixed(&mut dst, name.as_bytes());
+        }
+        for (tag, value) in [(LOG_NUMBER, self.log_number_),
+            (NEXT_FILE_NUMBER, self.next_file_number_),
+            (LAST_SEQUENCE, self.last_sequence_)] {
+            if let Some(value) = value {
+                put_varint(&mut dst, tag);
+                put_varint(&mut dst, value);
+            }
+        }
+        for (level, number) in self.deleted_files_.iter() {
+            put_varint(&mut dst, DELETED_FILE);
+            put_varint(&mut dst, *level as u64);
+            put_varint(&mut dst, *number);
+        }
+        for (level, file) in self.new_files_.iter() {
+            put_varint(&mut dst, NEW_FILE);
+            put_varint(&mut dst, *level as u64);
+            put_varint(&mut dst, file.number);
+            put_varint(&mut dst, file.file_size);
+            put_length_prefixed(&mut dst, file.smallest.encode());
+            put_length_prefixed(&mut dst, file.largest.encode());
+            put_varint(&mut dst, file.smallest_sequence);
+            put_varint(&mut dst, file.largest_sequence);
+            if file.creation_time > 0 {
+                put_varint(&mut dst, FILE_CREATION_TIME);
+                put_varint(&mut dst, file.number);
+                put_varint(&mut dst, file.creation_time);
+            }
+        }
+        return dst;
+    }
+
+    /// Decodes an edit encoded by 'encode'. Fails with
+    /// DataStoreError::MalformedVersionEdit.
+    pub fn decode(mut input: &[u8]) -> Result<VersionEdit, DataStoreError> {
+        let mut edit = VersionEdit::new();
+        let input = &mut input;
+        while !input.is_empty() {
+            let tag = get_varint(input).ok_or_else(|| malformed("tag"))?;
+            match tag {
+                COMPARATOR => {
+                    let name = get_length_prefixed(input, "comparator name")?;
+                    edit.comparator_ = Some(String::from_utf8(name.to_vec())
+                        .map_err(|_| malformed("comparator name"))?);
+                }
+                MERGE_OPERATOR => {
+                    let name = get_length_prefixed(input, "merge operator name")?;
+                    edit.merge_operator_ = Some(String::from_utf8(name.to_vec())
+                        .map_err(|_| malformed("merge operator name"))?);
+                }
+                LOG_NUMBER => edit.log_number_ = Some(get_number(input, "log number")?),
+                NEXT_FILE_NUMBER => edit.next_file_number_ =
+                    Some(get_number(input, "next file number")?),
+                LAST_SEQUENCE => edit.last_sequence_ =
+                    Some(get_number(input, "last sequence")?),
+                DELETED_FILE => {
+                    let level = get_number(input, "deleted file")? as usize;
+                    edit.deleted_files_.insert((level, get_number(input, "deleted file")?));
+                }
+                NEW_FILE => {
+                    let level = get_number(input, "new file")? as usize;
+                    let file = FileMetaData {
+                        number: get_number(input, "new file")?,
+                        file_size: get_number(input, "new file")?,
+                        smallest: InternalKey::decode_from(
+                            get_length_prefixed(input, "new file")?),
+                        largest: InternalKey::decode_from(
+                            get_length_prefixed(input, "new file")?),
+                        smallest_sequence: get_number(input, "new file")?,
+                        largest_sequence: get_number(input, "new file")?,
+                        creation_time: 0,
+                    };
+                    edit.new_files_.push((level, file));
+                }
+                FILE_CREATION_TIME => {
+                    let number = get_number(input, "file creation time")?;
+                    let creation_time = get_number(input, "file creation time")?;
+                    let (_, file) = edit.new_files_.iter_mut()
+                        .find(|(_, file)| file.number == number)
+                        .ok_or_else(|| malformed("file creation time"))?;
+                    file.creation_time = creation_time;
+                }
+                _ => return Err(DataStoreError::MalformedVersionEdit(
+                    format!("unknown tag {}", tag))),
+            }
+        }
+        return Ok(edit);
+    }
+}
+
+fn malformed(field: &str) -> DataStoreError {
+    DataStoreError::MalformedVersionEdit(format!("bad {}", field))
+}
+
+fn get_number(input: &mut &[u8], field: &str) -> Result<u64, DataStoreError> {
+    return get_varint(input).ok_or_else(|| malformed(field));
+}
+
+fn put_length_prefixed(dst: &mut Vec<u8>, value: &[u8]) {
+    put_varint(dst, value.len() as u64);
+    dst.extend_from_slice(value);
+}
+
+fn get_length_prefixed<'a>(input: &mut &'a [u8], field: &str)
+    -> Result<&'a [u8], DataStoreError> {
+    let len = get_number(input, field)? as usize;
+    if input.len() < len {
+        return Err(malformed(field));
+    }
+    let (value, rest) = input.split_at(len);
+    *input = rest;
+    return Ok(value);
+}
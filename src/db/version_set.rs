@@ -0,0 +1,402 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::sync::{Arc, Weak};
+use crate::comparator::Comparator;
+use crate::db::filename::{manifest_file_name, read_current_file, set_current_file};
+use crate::db::get_context::GetContext;
+use crate::db::table_cache::TableCache;
+use crate::db::version_edit::{FileMetaData, VersionEdit};
+use crate::filesystem::Path;
+use crate::memtable::internal_key::{InternalKeyComparator, SequenceNumber};
+use crate::merge_operator::MergeOperator;
+use crate::sst::lsm_error::DataStoreError;
+use crate::sst::options::ReadOptions;
+use crate::wal::WalRecoveryMode;
+use crate::wal::reader::Reader;
+use crate::wal::writer::Writer;
+
+pub const NUM_LEVELS: usize = 7;
+
+/// Tables of the DB at a point in time, per level. Level 0 tables may
+/// overlap and are kept newest first. Tables of the other levels do not
+/// overlap and are kept in key order. Versions are immutable and shared:
+/// the tables of a Version are not deleted while it is in use.
+#[derive(Debug)]
+pub struct Version {
+    files_: Vec<Vec<Arc<FileMetaData>>>,
+}
+
+impl Version {
+    fn new() -> Version {
+        Version {
+            files_: vec![vec!(); NUM_LEVELS],
+        }
+    }
+
+    #[inline(always)]
+    pub fn files(&self, level: usize) -> &[Arc<FileMetaData>] {
+        &self.files_[level]
+    }
+
+    #[inline(always)]
+    pub fn num_files(&self, level: usize) -> usize {
+        self.files_[level].len()
+    }
+
+    /// Total size of the tables of 'level'.
+    pub fn level_size(&self, level: usize) -> u64 {
+        self.files_[level].iter().map(|file| file.file_size).sum()
+    }
+
+    /// Tables of 'level' holding user keys from 'begin' to 'end', both
+    /// inclusive.
+    pub fn overlapping_files(&self, level: usize, begin: &[u8], end: &[u8],
+        user_comparator: &dyn Comparator) -> Vec<Arc<FileMetaData>> {
+        self.files_[level].iter()
+            .filter(|file| user_comparator.compare(file.largest.user_key(), begin)
+                != Ordering::Less
+                && user_comparator.compare(file.smallest.user_key(), end) != Ordering::Greater)
+            .cloned()
+            .collect()
+    }
+
+    /// Searches the tables which may hold the key of 'context', newest
+    /// first, until the lookup is complete.
+    pub fn get(&self, context: &mut GetContext, comparator: &InternalKeyComparator,
+        table_cache: &TableCache, read_options: &ReadOptions) -> Result<(), DataStoreError> {
+        let user_comparator = comparator.user_comparator();
+        let user_key = context.user_key().to_vec();
+        let overlaps = |file: &FileMetaData| {
+            user_comparator.compare(&user_key, file.smallest.user_key()) != Ordering::Less
+                && user_comparator.compare(&user_key, file.largest.user_key()) != Ordering::Greater
+        };
+        for file in self.files_[0].iter() {
+            if context.is_done() {
+                return Ok(());
+            }
+            if overlaps(file) {
+                context.search_table(&*table_cache.get(file.number)?, read_options)?;
+            }
+        }
+        for files in self.files_[1..].iter() {
+            // First table which may hold entries visible to the lookup. The
+            // entries of the key may continue in the following tables.
+            let start = files.partition_point(|file| comparator.compare(
+                file.largest.encode(), context.internal_key()) == Ordering::Less);
+            for file in files[start..].iter() {
+                if context.is_done() || !overlaps(file) {
+                    break;
+                }
+                context.search_table(&*table_cache.get(file.number)?, read_options)?;
+            }
+        }
+        return Ok(());
+    }
+
+    /// Returns the Version resulting from applying 'edit' to this one.
+    fn apply(&self, edit: &VersionEdit, comparator: &InternalKeyComparator) -> Version {
+        let mut files = self.files_.clone();
+        for (level, level_files) in files.iter_mut().enumerate() {
+            level_files.retain(|file| !edit.deleted_files().contains(&(level, file.number)));
+        }
+        for (level, file) in edit.new_files() {
+            files[*level].push(Arc::new(file.clone()));
+        }
+        files[0].sort_by(|a, b| b.largest_sequence.cmp(&a.largest_sequence)
+            .then(b.number.cmp(&a.number)));
+        for level_files in files[1..].iter_mut() {
+            level_files.sort_by(|a, b| comparator.compare(a.smallest.encode(),
+                b.smallest.encode()));
+        }
+        Version {
+            files_: files,
+        }
+    }
+}
+
+/// Current Version of the DB and the counters persisted with it. Changes
+/// are recorded as VersionEdits in the MANIFEST, a log in the format of
+/// the write ahead log. The CURRENT file names the MANIFEST in use.
+/// A new MANIFEST is started on open, holding a snapshot of the recovered
+/// Version.
+pub struct VersionSet {
+    dbname_: Arc<dyn Path>,
+    internal_comparator_: InternalKeyComparator,
+    /// Name of the merge operator of the options, if any.
+    merge_operator_name_: Option<String>,
+    current_: Arc<Version>,
+    /// Versions which were current, kept to tell which tables are in use.
+    versions_: Vec<Weak<Version>>,
+    /// None while an edit is written, or until the first edit starts a new
+    /// MANIFEST.
+    manifest_: Option<Writer>,
+    manifest_writing_: bool,
+    manifest_file_number_: u64,
+    next_file_number_: u64,
+    log_number_: u64,
+    last_sequence_: SequenceNumber,
+}
+
+impl VersionSet {
+    pub fn new(dbname: Arc<dyn Path>, internal_comparator: InternalKeyComparator,
+        merge_operator: Option<&dyn MergeOperator>) -> VersionSet {
+        let current = Arc::new(Version::new());
+        VersionSet {
+            dbname_: dbname,
+            internal_comparator_: internal_comparator,
+            merge_operator_name_: merge_operator.map(|operator| operator.name().to_string()),
+            versions_: vec!(Arc::downgrade(&current)),
+            current_: current,
+            manifest_: None,
+            manifest_writing_: false,
+            manifest_file_number_: 0,
+            next_file_number_: 2,
+            log_number_: 0,
+            last_sequence_: 0,
+        }
+    }
+
+    /// Creates the MANIFEST of a new DB, numbered 1, and points CURRENT
+    /// at it.
+    pub fn create(dbname: &Arc<dyn Path>, comparator: &dyn Comparator,
+        merge_operator: Option<&dyn MergeOperator>) -> Result<(), DataStoreError> {
+        let mut edit = VersionEdit::new();
+        edit.set_comparator_name(comparator.name());
+        if let Some(merge_operator) = merge_operator {
+            edit.set_merge_operator_name(merge_operator.name());
+        }
+        edit.set_log_number(0);
+        edit.set_next_file_number(2);
+        edit.set_last_sequence(0);
+        let mut manifest = Writer::new(dbname.join(&manifest_file_name(1)))?;
+        manifest.add_record(&edit.encode())?;
+        manifest.sync()?;
+        return set_current_file(dbname, 1);
+    }
+
+    /// Recovers the current Version by replaying the MANIFEST named by
+    /// CURRENT. Fails with DataStoreError::InvalidArgument if the DB was
+    /// created with another comparator, or written with a merge operator
+    /// other than the one of the set, if any.
+    pub fn recover(&mut self) -> Result<(), DataStoreError> {
+        let manifest_number = read_current_file(&self.dbname_)?;
+        let manifest_name = manifest_file_name(manifest_number);
+        // A MANIFEST is synced after every edit, only the edit being
+        // written during a crash can be incomplete.
+        let mut reader = Reader::new(self.dbname_.join(&manifest_name), None, true,
+            WalRecoveryMode::TolerateCorruptedTailRecords);
+        let mut version = Version::new();
+        let mut log_number = None;
+        let mut next_file_number = None;
+        let mut last_sequence = None;
+        let mut merge_operator_name = None;
+        while let Some(record) = reader.read_record()? {
+            let edit = VersionEdit::decode(&record)?;
+            if let Some(name) = edit.comparator_name() {
+                let expected = self.internal_comparator_.user_comparator().name();
+                if name != expected {
+                    return Err(DataStoreError::InvalidArgument(format!(
+                        "comparator {} does not match existing comparator {}", expected, name)));
+                }
+            }
+            version = version.apply(&edit, &self.internal_comparator_);
+            log_number = edit.log_number().or(log_number);
+            next_file_number = edit.next_file_number().or(next_file_number);
+            last_sequence = edit.last_sequence().or(last_sequence);
+            merge_operator_name = edit.merge_operator_name().map(str::to_string)
+                .or(merge_operator_name);
+        }
+        // A DB written without a merge operator can be opened with one, it
+        // is recorded by the next MANIFEST.
+        if let Some(name) = merge_operator_name {
+            if self.merge_operator_name_.as_ref() != Some(&name) {
+                return Err(DataStoreError::InvalidArgument(format!(
+                    "merge operator {} does not match existing merge operator {}",
+                    self.merge_operator_name_.as_deref().unwrap_or("(none)"), name)));
+            }
+        }
+        let missing = |field: &str| DataStoreError::Corruption {
+            file: manifest_name.clone(),
+            offset: 0,
+            reason: format!("no {} entry", field),
+        };
+        self.log_number_ = log_number.ok_or_else(|| missing("log number"))?;
+        self.next_file_number_ = next_file_number.ok_or_else(|| missing("next file number"))?;
+        self.last_sequence_ = last_sequence.ok_or_else(|| missing("last sequence"))?;
+        self.manifest_file_number_ = manifest_number;
+        self.mark_file_number_used(manifest_number);
+        self.install(version);
+        return Ok(());
+    }
+
+    /// Applies 'edit' to the current Version, records it in the MANIFEST
+    /// and makes the result current. The edit records the counters of the
+    /// set as well.
+    pub fn log_and_apply(&mut self, edit: &mut VersionEdit) -> Result<(), DataStoreError> {
+        let mut write = self.prepare_edit(edit);
+        let result = write.write();
+        return self.finish_edit(write, result);
+    }
+
+    /// First step of 'log_and_apply', returning the MANIFEST write to do,
+    /// which does not need the set, and then to pass to 'finish_edit'. No
+    /// other edit can be prepared until then.
+    pub fn prepare_edit(&mut self, edit: &mut VersionEdit) -> ManifestWrite {
+        assert!(!self.manifest_writing_, "A MANIFEST write is in progress");
+        match edit.log_number() {
+            Some(number) => assert!(number >= self.log_number_
+                && number < self.next_file_number_, "Log number out of range"),
+            None => edit.set_log_number(self.log_number_),
+        }
+        let (manifest_file_number, snapshot) = match self.manifest_ {
+            Some(_) => (self.manifest_file_number_, None),
+            None => (self.new_file_number(), Some(self.snapshot().encode())),
+        };
+        edit.set_next_file_number(self.next_file_number_);
+        edit.set_last_sequence(self.last_sequence_);
+        self.manifest_writing_ = true;
+        ManifestWrite {
+            dbname_: self.dbname_.clone(),
+            manifest_: self.manifest_.take(),
+            manifest_file_number_: manifest_file_number,
+            snapshot_: snapshot,
+            record_: edit.encode(),
+            log_number_: edit.log_number().unwrap(),
+            version_: self.current_.apply(edit, &self.internal_comparator_),
+        }
+    }
+
+    /// Last step of 'log_and_apply': makes the Version of 'write' current
+    /// if 'result', the result of the write, is Ok.
+    pub fn finish_edit(&mut self, write: ManifestWrite, result: Result<(), DataStoreError>)
+        -> Result<(), DataStoreError> {
+        self.manifest_writing_ = false;
+        if let Err(error) = result {
+            if write.snapshot_.is_some() {
+                // The MANIFEST is started over by the next edit.
+                let _ = self.dbname_.get_file_system().remove(self.dbname_.join(
+                    &manifest_file_name(write.manifest_file_number_)).as_ref());
+            } else {
+                self.manifest_ = write.manifest_;
+            }
+            return Err(error);
+        }
+        self.manifest_ = write.manifest_;
+        self.manifest_file_number_ = write.manifest_file_number_;
+        self.log_number_ = write.log_number_;
+        self.install(write.version_);
+        return Ok(());
+    }
+
+    /// Whether an edit was prepared and not finished yet.
+    #[inline(always)]
+    pub fn is_writing_manifest(&self) -> bool {
+        self.manifest_writing_
+    }
+
+    /// Edit recreating the current Version from scratch.
+    fn snapshot(&self) -> VersionEdit {
+        let mut edit = VersionEdit::new();
+        edit.set_comparator_name(self.internal_comparator_.user_comparator().name());
+        if let Some(name) = self.merge_operator_name_.as_ref() {
+            edit.set_merge_operator_name(name);
+        }
+        for (level, files) in self.current_.files_.iter().enumerate() {
+            for file in files.iter() {
+                edit.add_file(level, file.as_ref().clone());
+            }
+        }
+        return edit;
+    }
+
+    fn install(&mut self, version: Version) {
+        self.current_ = Arc::new(version);
+        self.versions_.retain(|version| version.strong_count() > 0);
+        self.versions_.push(Arc::downgrade(&self.current_));
+    }
+
+    #[inline(always)]
+    pub fn current(&self) -> Arc<Version> {
+        self.current_.clone()
+    }
+
+    /// Numbers of the tables of the current Version and of the older
+    /// Versions still in use.
+    pub fn live_files(&mut self) -> HashSet<u64> {
+        self.versions_.retain(|version| version.strong_count() > 0);
+        let mut live = HashSet::new();
+        for version in self.versions_.iter().filter_map(|version| version.upgrade()) {
+            for files in version.files_.iter() {
+                live.extend(files.iter().map(|file| file.number));
+            }
+        }
+        return live;
+    }
+
+    pub fn new_file_number(&mut self) -> u64 {
+        let number = self.next_file_number_;
+        self.next_file_number_ += 1;
+        return number;
+    }
+
+    /// Makes sure 'number' is not handed out by new_file_number.
+    pub fn mark_file_number_used(&mut self, number: u64) {
+        self.next_file_number_ = self.next_file_number_.max(number + 1);
+    }
+
+    #[inline(always)]
+    pub fn manifest_file_number(&self) -> u64 {
+        self.manifest_file_number_
+    }
+
+    /// Logs numbered before it hold no writes missing from the tables.
+    #[inline(always)]
+    pub fn log_number(&self) -> u64 {
+        self.log_number_
+    }
+
+    #[inline(always)]
+    pub fn last_sequence(&self) -> SequenceNumber {
+        self.last_sequence_
+    }
+
+    pub fn set_last_sequence(&mut self, sequence: SequenceNumber) {
+        assert!(sequence >= self.last_sequence_, "Last sequence going backwards");
+        self.last_sequence_ = sequence;
+    }
+}
+
+/// MANIFEST write of an edit, prepared by VersionSet::prepare_edit. It is
+/// done without the VersionSet, so the lock guarding the set can be
+/// released during the write.
+pub struct ManifestWrite {
+    dbname_: Arc<dyn Path>,
+    /// Writer of the current MANIFEST, None to start a new one.
+    manifest_: Option<Writer>,
+    manifest_file_number_: u64,
+    /// Edit recreating the current Version, written first to a new MANIFEST.
+    snapshot_: Option<Vec<u8>>,
+    record_: Vec<u8>,
+    log_number_: u64,
+    version_: Version,
+}
+
+impl ManifestWrite {
+    /// Appends the edit to the MANIFEST and syncs it. A new MANIFEST is
+    /// named by CURRENT once synced.
+    pub fn write(&mut self) -> Result<(), DataStoreError> {
+        if let Some(snapshot) = self.snapshot_.as_ref() {
+            let mut manifest = Writer::new(self.dbname_.join(
+                &manifest_file_name(self.manifest_file_number_)))?;
+            manifest.add_record(snapshot)?;
+            self.manifest_ = Some(manifest);
+        }
+        let manifest = self.manifest_.as_mut().unwrap();
+        manifest.add_record(&self.record_)?;
+        manifest.sync()?;
+        if self.snapshot_.is_some() {
+            set_current_file(&self.dbname_, self.manifest_file_number_)?;
+        }
+        return Ok(());
+    }
+}
@@ -1,32 +1,35 @@
-use std;
 use std::fs::File;
 use std::io::{BufWriter, BufReader, Error, Read, Seek, SeekFrom, Write};
 use std::ffi::OsStr;
 use std::fs::OpenOptions;
+use std::path::PathBuf;
+use std::sync::Arc;
 
 /// Names a file or directory in a FileSystem
-pub trait Path {
+pub trait Path: Send + Sync {
     fn get_file_system(&self) -> &dyn FileSystem;
     fn as_os_str(&self) -> &OsStr;
     fn to_str(&self) -> Option<&str>;
+    /// Path of the entry 'name' in this directory.
+    fn join(&self, name: &str) -> Arc<dyn Path>;
 }
 
 /// Local path
-pub struct LocalPath<'a> {
-    local_path: &'a std::path::Path,
+pub struct LocalPath {
+    local_path: PathBuf,
     filesystem: LocalFileSystem
 }
 
-impl LocalPath<'_> {
+impl LocalPath {
     pub fn from_std_path(path: &std::path::Path) -> LocalPath {
         LocalPath {
-            local_path: path,
+            local_path: path.to_path_buf(),
             filesystem: LocalFileSystem {}
         }
     }
 }
 
-impl Path for LocalPath<'_> {
+impl Path for LocalPath {
     fn get_file_system(&self) -> &dyn FileSystem {
         &self.filesystem
     }
@@ -38,6 +41,10 @@ impl Path for LocalPath<'_> {
     fn to_str(&self) -> Option<&str> {
         self.local_path.to_str()
     }
+
+    fn join(&self, name: &str) -> Arc<dyn Path> {
+        Arc::new(LocalPath::from_std_path(&self.local_path.join(name)))
+    }
 }
 
 /// Generic Filesystem
@@ -45,9 +52,22 @@ pub trait FileSystem {
     fn create(&self, path: &dyn Path) -> Result<(), Error>;
     fn append(&self, path: &dyn Path, buffer: &[u8])
         -> Result<(), Error>;
+    /// Flushes the contents of the file to durable storage.
+    fn sync(&self, path: &dyn Path) -> Result<(), Error>;
     fn read(&self, path: &dyn Path, buffer: &mut [u8]) -> Result<usize, Error>;
+    /// Reads from 'offset' until 'buffer' is full or the end of the file
+    /// is reached. Returns the number of bytes read.
     fn seek_read(&self, path: &dyn Path, offset: u64,
         buffer: &mut [u8]) -> Result<usize, Error>;
+    fn file_size(&self, path: &dyn Path) -> Result<u64, Error>;
+    fn exists(&self, path: &dyn Path) -> bool;
+    /// Creates the directory and any missing parent directories.
+    fn create_dir_all(&self, path: &dyn Path) -> Result<(), Error>;
+    /// Returns the names of the entries of the directory.
+    fn children(&self, path: &dyn Path) -> Result<Vec<String>, Error>;
+    /// Renames 'from' to 'to', replacing 'to' if it exists.
+    fn rename(&self, from: &dyn Path, to: &dyn Path) -> Result<(), Error>;
+    fn remove(&self, path: &dyn Path) -> Result<(), Error>;
     fn close(&self) -> Result<(), Error>;
 }
 
@@ -75,6 +95,14 @@ impl FileSystem for LocalFileSystem {
         return Ok(());
     }
 
+    fn sync(&self, path: &dyn Path) -> Result<(), Error> {
+        let file = OpenOptions::new()
+            .append(true)
+            .open(path.to_str().unwrap())?;
+        file.sync_all()?;
+        return Ok(());
+    }
+
     fn read(&self, path: &dyn Path, buffer: &mut [u8]) -> Result<usize, Error> {
         let file = File::open(path.to_str().unwrap())?;
         let bytes = BufReader::new(file).read(buffer)?;
@@ -86,10 +114,50 @@ impl FileSystem for LocalFileSystem {
         let mut file = File::open(path.to_str().unwrap())?;
         // move the cursor 'offset' bytes from the start of the file
         file.seek(SeekFrom::Start(offset))?;
-        let bytes = BufReader::new(file).read(buffer)?;
+        let mut bytes = 0;
+        while bytes < buffer.len() {
+            match file.read(&mut buffer[bytes..])? {
+                0 => break,
+                n => bytes += n,
+            }
+        }
         return Ok(bytes);
     }
 
+    fn file_size(&self, path: &dyn Path) -> Result<u64, Error> {
+        let metadata = std::fs::metadata(path.to_str().unwrap())?;
+        return Ok(metadata.len());
+    }
+
+    fn exists(&self, path: &dyn Path) -> bool {
+        std::path::Path::new(path.as_os_str()).exists()
+    }
+
+    fn create_dir_all(&self, path: &dyn Path) -> Result<(), Error> {
+        std::fs::create_dir_all(path.to_str().unwrap())?;
+        return Ok(());
+    }
+
+    fn children(&self, path: &dyn Path) -> Result<Vec<String>, Error> {
+        let mut names = vec!();
+        for entry in std::fs::read_dir(path.to_str().unwrap())? {
+            if let Some(name) = entry?.file_name().to_str() {
+                names.push(name.to_string());
+            }
+        }
+        return Ok(names);
+    }
+
+    fn rename(&self, from: &dyn Path, to: &dyn Path) -> Result<(), Error> {
+        std::fs::rename(from.to_str().unwrap(), to.to_str().unwrap())?;
+        return Ok(());
+    }
+
+    fn remove(&self, path: &dyn Path) -> Result<(), Error> {
+        std::fs::remove_file(path.to_str().unwrap())?;
+        return Ok(());
+    }
+
     fn close(&self) -> Result<(), Error> {
         Ok(())
     }
@@ -1,6 +1,7 @@
 #[cfg(test)]
 mod local_filesystem_test {
     use crate::filesystem::LocalPath;
+    use crate::filesystem::Path;
     use crate::filesystem::FileSystem;
     use tempfile::TempDir;
 
@@ -54,4 +55,49 @@ mod local_filesystem_test {
         // Test seeking beyond EOF. No errors thrown
         assert_eq!(filesystem.seek_read(&local_path, 200, &mut buf).unwrap(), 0);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_file_size() {
+        let tmp_dir = TempDir::new().unwrap();
+        let file_path = tmp_dir.path().join("test.txt");
+        let local_path = LocalPath::from_std_path(file_path.as_path());
+        let filesystem = &local_path.filesystem;
+        // Missing file
+        assert!(filesystem.file_size(&local_path).is_err());
+        assert!(filesystem.create(&local_path).is_ok());
+        assert_eq!(filesystem.file_size(&local_path).unwrap(), 0);
+        assert!(filesystem.append(&local_path, b"test string 1").is_ok());
+        assert!(filesystem.sync(&local_path).is_ok());
+        assert_eq!(filesystem.file_size(&local_path).unwrap(), 13);
+    }
+
+    #[test]
+    fn test_directories() {
+        let tmp_dir = TempDir::new().unwrap();
+        let dir = LocalPath::from_std_path(tmp_dir.path().join("a/b").as_path());
+        let filesystem = &dir.filesystem;
+        assert!(!filesystem.exists(&dir));
+        assert!(filesystem.children(&dir).is_err());
+        assert!(filesystem.create_dir_all(&dir).is_ok());
+        assert!(filesystem.exists(&dir));
+        // Creating an existing directory succeeds.
+        assert!(filesystem.create_dir_all(&dir).is_ok());
+        for name in ["x.txt", "y.txt"] {
+            let child = dir.join(name);
+            assert!(filesystem.create(child.as_ref()).is_ok());
+            assert!(filesystem.exists(child.as_ref()));
+        }
+        let mut children = filesystem.children(&dir).unwrap();
+        children.sort();
+        assert_eq!(children, vec!("x.txt", "y.txt"));
+        // Rename replaces the target.
+        let (x, y) = (dir.join("x.txt"), dir.join("y.txt"));
+        assert!(filesystem.append(x.as_ref(), b"x").is_ok());
+        assert!(filesystem.rename(x.as_ref(), y.as_ref()).is_ok());
+        assert!(!filesystem.exists(x.as_ref()));
+        assert_eq!(filesystem.file_size(y.as_ref()).unwrap(), 1);
+        assert!(filesystem.remove(y.as_ref()).is_ok());
+        assert!(filesystem.remove(y.as_ref()).is_err());
+        assert!(filesystem.children(&dir).unwrap().is_empty());
+    }
+}
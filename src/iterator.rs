@@ -0,0 +1,182 @@
+use std::cmp::Ordering;
+use std::sync::Arc;
+use crate::comparator::Comparator;
+use crate::sst::lsm_error::DataStoreError;
+
+/// Iterator over sorted (key, value) entries, such as the entries of a
+/// MemTable or of a table. Lets lookups, flushes and compactions handle
+/// every source of entries alike.
+pub trait InternalIterator {
+    fn valid(&self) -> bool;
+
+    fn seek_to_first(&mut self) -> Result<(), DataStoreError>;
+
+    /// Positions at the first entry with key at or after 'target'.
+    fn seek(&mut self, target: &[u8]) -> Result<(), DataStoreError>;
+
+    /// Moves to the next entry. Must be valid.
+    fn next(&mut self) -> Result<(), DataStoreError>;
+
+    /// Key of the current entry. Must be valid.
+    fn key(&self) -> &[u8];
+
+    /// Value of the current entry. Must be valid.
+    fn value(&self) -> &[u8];
+}
+
+/// Merges the entries of several iterators into a single sorted stream.
+/// Entries with equal keys are returned in the order of the children, so
+/// children should be given newest first.
+pub struct MergingIterator<'a> {
+    comparator_: Arc<dyn Comparator>,
+    children_: Vec<Box<dyn InternalIterator + 'a>>,
+    /// Index of the child holding the smallest key.
+    current_: Option<usize>,
+}
+
+impl<'a> MergingIterator<'a> {
+    pub fn new(comparator: Arc<dyn Comparator>, children: Vec<Box<dyn InternalIterator + 'a>>)
+        -> MergingIterator<'a> {
+        MergingIterator {
+            comparator_: comparator,
+            children_: children,
+            current_: None,
+        }
+    }
+
+    fn find_smallest(&mut self) {
+        let mut smallest: Option<usize> = None;
+        for (i, child) in self.children_.iter().enumerate() {
+            if !child.valid() {
+                continue;
+            }
+            smallest = match smallest {
+                Some(s) if self.comparator_.compare(child.key(), self.children_[s].key())
+                    != Ordering::Less => Some(s),
+                _ => Some(i),
+            };
+        }
+        self.current_ = smallest;
+    }
+}
+
+impl InternalIterator for MergingIterator<'_> {
+    #[inline(always)]
+    fn valid(&self) -> bool {
+        self.current_.is_some()
+    }
+
+    fn seek_to_first(&mut self) -> Result<(), DataStoreError> {
+        for child in self.children_.iter_mut() {
+            child.seek_to_first()?;
+        }
+        self.find_smallest();
+        return Ok(());
+    }
+
+    fn seek(&mut self, target: &[u8]) -> Result<(), DataStoreError> {
+        for child in self.children_.iter_mut() {
+            child.seek(target)?;
+        }
+        self.find_smallest();
+        return Ok(());
+    }
+
+    fn next(&mut self) -> Result<(), DataStoreError> {
+        let current = self.current_.expect("Iterator is not valid");
+        self.children_[current].next()?;
+        self.find_smallest();
+        return Ok(());
+    }
+
+    fn key(&self) -> &[u8] {
+        self.children_[self.current_.expect("Iterator is not valid")].key()
+    }
+
+    fn value(&self) -> &[u8] {
+        self.children_[self.current_.expect("Iterator is not valid")].value()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::comparator::BytewiseComparator;
+    use crate::iterator::{InternalIterator, MergingIterator};
+    use crate::sst::lsm_error::DataStoreError;
+
+    /// Iterator over sorted entries held in memory.
+    struct VecIterator {
+        entries: Vec<(Vec<u8>, Vec<u8>)>,
+        pos: usize,
+    }
+
+    impl VecIterator {
+        fn new(entries: &[(&str, &str)]) -> Box<VecIterator> {
+            Box::new(VecIterator {
+                entries: entries.iter()
+                    .map(|(key, value)| (key.as_bytes().to_vec(), value.as_bytes().to_vec()))
+                    .collect(),
+                pos: 0,
+            })
+        }
+    }
+
+    impl InternalIterator for VecIterator {
+        fn valid(&self) -> bool {
+            self.pos < self.entries.len()
+        }
+
+        fn seek_to_first(&mut self) -> Result<(), DataStoreError> {
+            self.pos = 0;
+            return Ok(());
+        }
+
+        fn seek(&mut self, target: &[u8]) -> Result<(), DataStoreError> {
+            self.pos = self.entries.partition_point(|(key, _)| &key[..] < target);
+            return Ok(());
+        }
+
+        fn next(&mut self) -> Result<(), DataStoreError> {
+            self.pos += 1;
+            return Ok(());
+        }
+
+        fn key(&self) -> &[u8] {
+            &self.entries[self.pos].0
+        }
+
+        fn value(&self) -> &[u8] {
+            &self.entries[self.pos].1
+        }
+    }
+
+    fn collect(iter: &mut dyn InternalIterator) -> Vec<(String, String)> {
+        let mut entries = vec!();
+        while iter.valid() {
+            entries.push((String::from_utf8(iter.key().to_vec()).unwrap(),
+                String::from_utf8(iter.value().to_vec()).unwrap()));
+            iter.next().unwrap();
+        }
+        return entries;
+    }
+
+    #[test]
+    fn test_merging_iterator() {
+        let mut iter = MergingIterator::new(Arc::new(BytewiseComparator), vec!(
+            VecIterator::new(&[("b", "1"), ("d", "1"), ("f", "1")]),
+            VecIterator::new(&[]),
+            VecIterator::new(&[("a", "2"), ("d", "2"), ("g", "2")]),
+        ));
+        assert!(!iter.valid());
+        iter.seek_to_first().unwrap();
+        let expected: Vec<(String, String)> = [("a", "2"), ("b", "1"), ("d", "1"),
+            ("d", "2"), ("f", "1"), ("g", "2")].iter()
+            .map(|(key, value)| (key.to_string(), value.to_string())).collect();
+        assert_eq!(collect(&mut iter), expected);
+        iter.seek(b"c").unwrap();
+        assert_eq!(collect(&mut iter), expected[2..].to_vec());
+        iter.seek(b"h").unwrap();
+        assert!(!iter.valid());
+    }
+}
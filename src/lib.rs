@@ -0,0 +1,13 @@
+// Functions end with an explicit return, as in the rest of the code base.
+#![allow(clippy::needless_return)]
+pub mod checksum;
+pub mod coding;
+pub mod comparator;
+pub mod db;
+pub mod filesystem;
+pub mod iterator;
+pub mod memtable;
+pub mod merge_operator;
+pub mod sst;
+pub mod wal;
+pub mod write_batch;
@@ -1,6 +1,46 @@
-mod sst;
-mod filesystem;
-mod memtable;
-fn main() {
-    println!("Hello, world!");
+use std::process::ExitCode;
+use std::sync::Arc;
+use lsm::db::DB;
+use lsm::db::options::Options;
+use lsm::filesystem::LocalPath;
+
+const USAGE: &str = "usage: lsm <db_dir> put <key> <value> | get <key> | delete <key>";
+
+#[allow(clippy::needless_return)]
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.len() < 3 {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    }
+    let options = Options {
+        create_if_missing: true,
+        ..Options::default()
+    };
+    let db = match DB::open(Arc::new(LocalPath::from_std_path(std::path::Path::new(&args[0]))),
+        options) {
+        Ok(db) => db,
+        Err(e) => {
+            eprintln!("cannot open {}: {}", args[0], e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let key = args[2].as_bytes();
+    let result = match (args[1].as_str(), args.len()) {
+        ("put", 4) => db.put(key, args[3].as_bytes()),
+        ("delete", 3) => db.delete(key),
+        ("get", 3) => db.get(key).map(|value| match value {
+            Some(value) => println!("{}", String::from_utf8_lossy(&value)),
+            None => println!("(not found)"),
+        }),
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("{} failed: {}", args[1], e);
+        return ExitCode::FAILURE;
+    }
+    return ExitCode::SUCCESS;
 }
@@ -0,0 +1,120 @@
+use std::alloc::{alloc, dealloc, Layout};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Arena
+// -----
+// Hands out chunks of memory carved from larger blocks. Individual
+// allocations are never freed; all the blocks are released together
+// when the Arena is dropped. Allocation is guarded by a mutex, so the
+// Arena can be shared by concurrent writers of a SkipList.
+
+/// Size of a regular block.
+const BLOCK_SIZE: usize = 4096;
+/// Alignment of every block.
+const BLOCK_ALIGN: usize = 8;
+
+struct ArenaState {
+    /// Next free byte in the current block.
+    alloc_ptr_: *mut u8,
+    /// Bytes left in the current block.
+    alloc_bytes_remaining_: usize,
+    /// Every block allocated so far, to be released on drop.
+    blocks_: Vec<(*mut u8, Layout)>,
+}
+
+pub struct Arena {
+    state_: Mutex<ArenaState>,
+    /// Total bytes allocated for blocks along with their bookkeeping.
+    memory_usage_: AtomicUsize,
+}
+
+// Raw pointers in ArenaState are only touched while holding the mutex,
+// and memory handed out is owned by the callers.
+unsafe impl Send for Arena {}
+unsafe impl Sync for Arena {}
+
+impl Arena {
+    pub fn new() -> Arena {
+        Arena {
+            state_: Mutex::new(ArenaState {
+                alloc_ptr_: std::ptr::null_mut(),
+                alloc_bytes_remaining_: 0,
+                blocks_: vec!(),
+            }),
+            memory_usage_: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns a pointer to a newly allocated chunk of 'bytes' bytes.
+    pub fn allocate(&self, bytes: usize) -> *mut u8 {
+        self.allocate_aligned(bytes, 1)
+    }
+
+    /// Returns a pointer to a newly allocated chunk of 'bytes' bytes
+    /// aligned to 'align', which must be a power of two.
+    pub fn allocate_aligned(&self, bytes: usize, align: usize) -> *mut u8 {
+        assert!(bytes > 0, "Zero sized allocations are not supported");
+        assert!(align.is_power_of_two(), "Alignment must be a power of two");
+        let mut state = self.state_.lock().unwrap();
+        let current_mod = state.alloc_ptr_ as usize & (align - 1);
+        let slop = if current_mod == 0 { 0 } else { align - current_mod };
+        let needed = bytes + slop;
+        if needed <= state.alloc_bytes_remaining_ {
+            let result = unsafe { state.alloc_ptr_.add(slop) };
+            state.alloc_ptr_ = unsafe { state.alloc_ptr_.add(needed) };
+            state.alloc_bytes_remaining_ -= needed;
+            return result;
+        }
+        return self.allocate_fallback(&mut state, bytes, align);
+    }
+
+    /// Approximate memory held by the Arena.
+    pub fn memory_usage(&self) -> usize {
+        self.memory_usage_.load(Ordering::Relaxed)
+    }
+
+    fn allocate_fallback(&self, state: &mut ArenaState, bytes: usize,
+        align: usize) -> *mut u8 {
+        if bytes > BLOCK_SIZE / 4 || align > BLOCK_ALIGN {
+            // Object is large enough or needs unusual alignment, so it gets
+            // its own block rather than wasting the rest of the current one.
+            return self.allocate_new_block(state, bytes, align);
+        }
+        // Remaining space in the current block is wasted.
+        let result = self.allocate_new_block(state, BLOCK_SIZE, BLOCK_ALIGN);
+        state.alloc_ptr_ = unsafe { result.add(bytes) };
+        state.alloc_bytes_remaining_ = BLOCK_SIZE - bytes;
+        return result;
+    }
+
+    fn allocate_new_block(&self, state: &mut ArenaState, block_bytes: usize,
+        align: usize) -> *mut u8 {
+        let layout = Layout::from_size_align(block_bytes,
+            align.max(BLOCK_ALIGN)).unwrap();
+        let block = unsafe { alloc(layout) };
+        if block.is_null() {
+            std::alloc::handle_alloc_error(layout);
+        }
+        state.blocks_.push((block, layout));
+        self.memory_usage_.fetch_add(
+            block_bytes + std::mem::size_of::<(*mut u8, Layout)>(),
+            Ordering::Relaxed);
+        return block;
+    }
+}
+
+impl Default for Arena {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        let state = self.state_.get_mut().unwrap();
+        for (block, layout) in state.blocks_.drain(..) {
+            unsafe { dealloc(block, layout) };
+        }
+    }
+}
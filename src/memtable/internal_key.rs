@@ -0,0 +1,272 @@
+use std::cmp::Ordering;
+use std::sync::Arc;
+use crate::comparator::Comparator;
+use crate::sst::filter::FilterPolicy;
+
+// Internal Key Format
+// -------------------
+// Every entry written to the store is identified by an internal key,
+// which is the user key followed by a fixed 8 byte little endian tag:
+//  user key     : char[user_key_size]
+//  tag          : (sequence << 8) | value_type
+// Internal keys are ordered by user key ascending, as per the user
+// comparator, and then by tag descending, so the newest version of a
+// user key sorts first.
+
+/// Sequence number assigned to every write. Only the lower 56 bits are
+/// used so that it can be packed together with the value type.
+pub type SequenceNumber = u64;
+
+/// Largest sequence number that fits into the tag.
+pub const MAX_SEQUENCE_NUMBER: SequenceNumber = (1 << 56) - 1;
+
+/// Size of the tag appended to the user key.
+pub const TAG_SIZE: usize = 8;
+
+/// Type of the entry stored against an internal key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ValueType {
+    Deletion = 0x0,
+    Value = 0x1,
+    /// Merge operand, combined with older entries by a MergeOperator.
+    Merge = 0x2,
+    /// Deletion of the user keys from the user key of the entry, inclusive,
+    /// to the value of the entry, exclusive.
+    RangeDeletion = 0xF,
+}
+
+/// Value type used while seeking. Entries with the same sequence number
+/// are ordered by value type descending, so seeking with the largest
+/// value type positions at the first entry for that sequence number.
+pub const VALUE_TYPE_FOR_SEEK: ValueType = ValueType::RangeDeletion;
+
+impl ValueType {
+    pub fn from_u8(value: u8) -> Option<ValueType> {
+        match value {
+            0x0 => Some(ValueType::Deletion),
+            0x1 => Some(ValueType::Value),
+            0x2 => Some(ValueType::Merge),
+            0xF => Some(ValueType::RangeDeletion),
+            _ => None,
+        }
+    }
+}
+
+#[inline(always)]
+pub fn pack_sequence_and_type(sequence: SequenceNumber,
+    value_type: ValueType) -> u64 {
+    assert!(sequence <= MAX_SEQUENCE_NUMBER, "Sequence number out of range");
+    return (sequence << 8) | value_type as u64;
+}
+
+/// Appends the internal key for ('user_key', 'sequence', 'value_type')
+/// to 'result'.
+pub fn append_internal_key(result: &mut Vec<u8>, user_key: &[u8],
+    sequence: SequenceNumber, value_type: ValueType) {
+    result.extend_from_slice(user_key);
+    result.extend_from_slice(
+        &pack_sequence_and_type(sequence, value_type).to_le_bytes());
+}
+
+/// Returns the user key portion of an internal key.
+#[inline(always)]
+pub fn extract_user_key(internal_key: &[u8]) -> &[u8] {
+    assert!(internal_key.len() >= TAG_SIZE, "Internal key is too short");
+    return &internal_key[..internal_key.len() - TAG_SIZE];
+}
+
+#[inline(always)]
+fn extract_tag(internal_key: &[u8]) -> u64 {
+    let tag_start = internal_key.len() - TAG_SIZE;
+    return u64::from_le_bytes(internal_key[tag_start..].try_into().unwrap());
+}
+
+/// Internal key split up into its components.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParsedInternalKey<'a> {
+    pub user_key: &'a [u8],
+    pub sequence: SequenceNumber,
+    pub value_type: ValueType,
+}
+
+/// Parses 'internal_key'. Returns None if it is too short or carries
+/// an unknown value type.
+pub fn parse_internal_key(internal_key: &[u8]) -> Option<ParsedInternalKey<'_>> {
+    if internal_key.len() < TAG_SIZE {
+        return None;
+    }
+    let tag = extract_tag(internal_key);
+    let value_type = ValueType::from_u8((tag & 0xff) as u8)?;
+    return Some(ParsedInternalKey {
+        user_key: extract_user_key(internal_key),
+        sequence: tag >> 8,
+        value_type,
+    });
+}
+
+/// Name of InternalKeyComparator. Tables built with it hold internal keys.
+pub const INTERNAL_KEY_COMPARATOR_NAME: &str = "lsm.InternalKeyComparator";
+
+/// Orders internal keys by user key ascending as per the user comparator
+/// and then by sequence number and value type descending.
+#[derive(Clone)]
+pub struct InternalKeyComparator {
+    user_comparator_: Arc<dyn Comparator>,
+}
+
+impl InternalKeyComparator {
+    pub fn new(user_comparator: Arc<dyn Comparator>) -> InternalKeyComparator {
+        InternalKeyComparator { user_comparator_: user_comparator }
+    }
+
+    #[inline(always)]
+    pub fn user_comparator(&self) -> &Arc<dyn Comparator> {
+        &self.user_comparator_
+    }
+}
+
+impl Comparator for InternalKeyComparator {
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        match self.user_comparator_.compare(extract_user_key(a), extract_user_key(b)) {
+            Ordering::Equal => extract_tag(b).cmp(&extract_tag(a)),
+            ordering => ordering,
+        }
+    }
+
+    fn name(&self) -> &str {
+        INTERNAL_KEY_COMPARATOR_NAME
+    }
+
+    fn find_shortest_separator(&self, start: &mut Vec<u8>, limit: &[u8]) {
+        // Attempt to shorten the user portion of the key.
+        let user_start = extract_user_key(start);
+        let user_limit = extract_user_key(limit);
+        let mut tmp = user_start.to_vec();
+        self.user_comparator_.find_shortest_separator(&mut tmp, user_limit);
+        if tmp.len() < user_start.len()
+            && self.user_comparator_.compare(user_start, &tmp) == Ordering::Less {
+            // User key has become shorter physically, but larger logically.
+            // Tack on the earliest possible tag so it still sorts before
+            // every entry of the separator user key.
+            tmp.extend_from_slice(&pack_sequence_and_type(MAX_SEQUENCE_NUMBER,
+                VALUE_TYPE_FOR_SEEK).to_le_bytes());
+            debug_assert!(self.compare(start, &tmp) == Ordering::Less);
+            debug_assert!(self.compare(&tmp, limit) == Ordering::Less);
+            *start = tmp;
+        }
+    }
+
+    fn find_short_successor(&self, key: &mut Vec<u8>) {
+        let user_key = extract_user_key(key);
+        let mut tmp = user_key.to_vec();
+        self.user_comparator_.find_short_successor(&mut tmp);
+        if tmp.len() < user_key.len()
+            && self.user_comparator_.compare(user_key, &tmp) == Ordering::Less {
+            tmp.extend_from_slice(&pack_sequence_and_type(MAX_SEQUENCE_NUMBER,
+                VALUE_TYPE_FOR_SEEK).to_le_bytes());
+            debug_assert!(self.compare(key, &tmp) == Ordering::Less);
+            *key = tmp;
+        }
+    }
+}
+
+/// Filter policy for tables keyed by internal keys. Filters are built
+/// from, and probed with, the user keys of the internal keys, so that a
+/// lookup matches every version of a user key.
+pub struct InternalFilterPolicy {
+    user_policy_: Arc<dyn FilterPolicy>,
+}
+
+impl InternalFilterPolicy {
+    pub fn new(user_policy: Arc<dyn FilterPolicy>) -> InternalFilterPolicy {
+        InternalFilterPolicy { user_policy_: user_policy }
+    }
+}
+
+impl FilterPolicy for InternalFilterPolicy {
+    fn name(&self) -> &str {
+        self.user_policy_.name()
+    }
+
+    fn create_filter(&self, keys: &[&[u8]]) -> Vec<u8> {
+        let user_keys: Vec<&[u8]> = keys.iter().map(|key| extract_user_key(key)).collect();
+        self.user_policy_.create_filter(&user_keys)
+    }
+
+    fn key_may_match(&self, key: &[u8], filter: &[u8]) -> bool {
+        self.user_policy_.key_may_match(extract_user_key(key), filter)
+    }
+}
+
+/// Owned internal key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InternalKey {
+    rep_: Vec<u8>,
+}
+
+impl InternalKey {
+    pub fn new(user_key: &[u8], sequence: SequenceNumber,
+        value_type: ValueType) -> InternalKey {
+        let mut rep = Vec::with_capacity(user_key.len() + TAG_SIZE);
+        append_internal_key(&mut rep, user_key, sequence, value_type);
+        InternalKey { rep_: rep }
+    }
+
+    pub fn decode_from(internal_key: &[u8]) -> InternalKey {
+        InternalKey { rep_: internal_key.to_vec() }
+    }
+
+    #[inline(always)]
+    pub fn encode(&self) -> &[u8] {
+        &self.rep_
+    }
+
+    #[inline(always)]
+    pub fn user_key(&self) -> &[u8] {
+        extract_user_key(&self.rep_)
+    }
+}
+
+/// Key used to look up a user key at a given sequence number.
+/// Format is:
+///  internal_key_size : leb128 encoding of internal key size
+///  user key bytes    : char[user_key_size]
+///  tag               : fixed64 of (sequence << 8) | VALUE_TYPE_FOR_SEEK
+/// The whole buffer is a memtable key and the suffix starting
+/// at the user key is an internal key.
+pub struct LookupKey {
+    data_: Vec<u8>,
+    /// Offset of the user key within 'data_'.
+    kstart_: usize,
+}
+
+impl LookupKey {
+    pub fn new(user_key: &[u8], sequence: SequenceNumber) -> LookupKey {
+        let internal_key_len = user_key.len() + TAG_SIZE;
+        let mut data = Vec::with_capacity(internal_key_len + 5);
+        let kstart = leb128::write::unsigned(&mut data,
+            internal_key_len as u64).unwrap();
+        append_internal_key(&mut data, user_key, sequence, VALUE_TYPE_FOR_SEEK);
+        LookupKey {
+            data_: data,
+            kstart_: kstart,
+        }
+    }
+
+    /// Key suitable for lookups in the memtable.
+    #[inline(always)]
+    pub fn memtable_key(&self) -> &[u8] {
+        &self.data_
+    }
+
+    #[inline(always)]
+    pub fn internal_key(&self) -> &[u8] {
+        &self.data_[self.kstart_..]
+    }
+
+    #[inline(always)]
+    pub fn user_key(&self) -> &[u8] {
+        &self.data_[self.kstart_..self.data_.len() - TAG_SIZE]
+    }
+}
@@ -1,2 +1,414 @@
+pub mod arena;
+pub mod internal_key;
+pub mod range_tombstone;
 pub mod skiplist;
 mod tests;
+
+use std::cmp::Ordering;
+use std::io::Write;
+use std::sync::atomic::{self, AtomicUsize};
+use std::sync::{Arc, RwLock};
+use crate::coding::varint_length;
+use crate::comparator::{BytewiseComparator, Comparator};
+use crate::iterator::InternalIterator;
+use crate::memtable::internal_key::{parse_internal_key, InternalKeyComparator,
+    LookupKey, SequenceNumber, ValueType, TAG_SIZE, pack_sequence_and_type};
+use crate::memtable::range_tombstone::FragmentedRangeTombstones;
+use crate::memtable::skiplist::{KeyComparator, SkipList};
+use crate::merge_operator::MergeOperator;
+use crate::sst::lsm_error::DataStoreError;
+
+/// Entry used as the head of the SkipList. It is never compared, but
+/// it still needs to be decodable.
+static EMPTY_ENTRY: [u8; 2] = [0, 0];
+
+/// Reads a leb128 length prefixed slice starting at 'data' and returns
+/// it along with a pointer to the byte following it.
+/// 'data' must point to a valid length prefixed slice.
+unsafe fn get_length_prefixed_slice<'a>(data: *const u8) -> (&'a [u8], *const u8) {
+    let mut len = 0;
+    let mut shift = 0;
+    let mut p = data;
+    loop {
+        let byte = *p;
+        p = p.add(1);
+        len |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    return (std::slice::from_raw_parts(p, len), p.add(len));
+}
+
+/// Key stored in the SkipList backing a MemTable. It points to an entry
+/// allocated from the Arena of the SkipList.
+/// Format of an entry is concatenation of:
+///  internal_key_size : leb128 encoding of internal key size
+///  internal key      : char[internal_key_size]
+///  value_size        : leb128 encoding of value size
+///  value bytes       : char[value_size]
+/// Entries are ordered only by their internal key.
+#[derive(Clone, Copy)]
+pub struct MemTableKey {
+    data_: *const u8,
+}
+
+// Entries are immutable once inserted and live as long as the SkipList.
+unsafe impl Send for MemTableKey {}
+unsafe impl Sync for MemTableKey {}
+
+impl MemTableKey {
+    /// 'data' must stay valid for as long as the key is in use.
+    fn new(data: *const u8) -> MemTableKey {
+        MemTableKey { data_: data }
+    }
+
+    #[inline(always)]
+    fn internal_key(&self) -> &[u8] {
+        unsafe { get_length_prefixed_slice(self.data_).0 }
+    }
+
+    #[inline(always)]
+    fn value(&self) -> &[u8] {
+        unsafe {
+            let (_, rest) = get_length_prefixed_slice(self.data_);
+            get_length_prefixed_slice(rest).0
+        }
+    }
+}
+
+/// Orders MemTable entries by their internal keys.
+pub struct MemTableKeyComparator {
+    comparator_: InternalKeyComparator,
+}
+
+impl KeyComparator<MemTableKey> for MemTableKeyComparator {
+    #[inline(always)]
+    fn compare(&self, a: &MemTableKey, b: &MemTableKey) -> Ordering {
+        self.comparator_.compare(a.internal_key(), b.internal_key())
+    }
+}
+
+/// Outcome of looking up a user key in a MemTable.
+#[derive(Debug, PartialEq, Eq)]
+pub enum LookupResult {
+    /// Latest visible entry for the key is a value.
+    Found(Vec<u8>),
+    /// Latest visible entry for the key is a deletion.
+    Deleted,
+    /// MemTable has no visible entry for the key.
+    NotFound,
+    /// Latest visible entries for the key are merge operands, ordered
+    /// newest first, but the value they apply to is not in the MemTable.
+    MergeInProgress(Vec<Vec<u8>>),
+}
+
+/// In-memory write buffer. Every write is recorded as an internal key
+/// (user key, sequence number, value type) along with its value, so
+/// overwrites and deletes of the same user key are appended as newer
+/// entries instead of modifying existing ones. Range deletions are kept
+/// in a separate SkipList, keyed by the start of the range, and fragmented
+/// for lookups when range deletions were added since the last lookup.
+/// Same as SkipList, writes need to be synchronized but reads are lock free,
+/// except for the lookups rebuilding the fragmented range deletions.
+pub struct MemTable {
+    comparator_: InternalKeyComparator,
+    table_: SkipList<MemTableKey, MemTableKeyComparator>,
+    range_del_table_: SkipList<MemTableKey, MemTableKeyComparator>,
+    /// Number of entries in 'range_del_table_', updated after each insert.
+    num_range_deletions_: AtomicUsize,
+    /// Range deletions fragmented by the last lookup which needed them.
+    range_del_fragments_: RwLock<Arc<FragmentedRangeTombstones>>,
+    merge_operator_: Option<Arc<dyn MergeOperator>>,
+}
+
+impl MemTable {
+    pub fn new(comparator: InternalKeyComparator) -> MemTable {
+        MemTable {
+            comparator_: comparator.clone(),
+            // Head of the SkipList is never compared, but it still needs
+            // a decodable entry.
+            table_: SkipList::with_comparator(
+                MemTableKey::new(EMPTY_ENTRY.as_ptr()),
+                MemTableKeyComparator { comparator_: comparator.clone() }),
+            range_del_table_: SkipList::with_comparator(
+                MemTableKey::new(EMPTY_ENTRY.as_ptr()),
+                MemTableKeyComparator { comparator_: comparator.clone() }),
+            num_range_deletions_: AtomicUsize::new(0),
+            range_del_fragments_: RwLock::new(Arc::new(FragmentedRangeTombstones::new(
+                comparator.user_comparator().clone(), []).unwrap())),
+            merge_operator_: None,
+        }
+    }
+
+    /// Creates a MemTable which can hold merge operands, combined by
+    /// 'merge_operator' on lookups.
+    pub fn with_merge_operator(comparator: InternalKeyComparator,
+        merge_operator: Arc<dyn MergeOperator>) -> MemTable {
+        let mut memtable = MemTable::new(comparator);
+        memtable.merge_operator_ = Some(merge_operator);
+        return memtable;
+    }
+
+    #[inline(always)]
+    pub fn merge_operator(&self) -> Option<&Arc<dyn MergeOperator>> {
+        self.merge_operator_.as_ref()
+    }
+
+    /// Whether no entry nor range deletion was added.
+    pub fn is_empty(&self) -> bool {
+        let mut iter = self.iter();
+        iter.seek_to_first();
+        let mut range_del_iter = self.range_del_iter();
+        range_del_iter.seek_to_first();
+        return !iter.valid() && !range_del_iter.valid();
+    }
+
+    /// Approximate memory used by the entries and the SkipList nodes.
+    /// Used to decide when the MemTable is full and should be flushed.
+    pub fn approximate_memory_usage(&self) -> usize {
+        self.table_.approximate_memory_usage()
+            + self.range_del_table_.approximate_memory_usage()
+    }
+
+    /// Adds an entry mapping 'key' to 'value' at 'sequence'. 'value' is
+    /// ignored by readers when 'value_type' is ValueType::Deletion. For
+    /// ValueType::RangeDeletion, 'key' is the inclusive start and 'value'
+    /// the exclusive end of the deleted range. ValueType::Merge entries
+    /// need a MemTable created with a merge operator.
+    pub fn add(&self, sequence: SequenceNumber, value_type: ValueType,
+        key: &[u8], value: &[u8]) {
+        assert!(value_type != ValueType::Merge || self.merge_operator_.is_some(),
+            "Merge operands need a merge operator");
+        let table = if value_type == ValueType::RangeDeletion {
+            &self.range_del_table_
+        } else {
+            &self.table_
+        };
+        let internal_key_size = key.len() + TAG_SIZE;
+        let encoded_len = varint_length(internal_key_size) + internal_key_size
+            + varint_length(value.len()) + value.len();
+        let buf = table.arena().allocate(encoded_len);
+        let mut writable = unsafe { std::slice::from_raw_parts_mut(buf, encoded_len) };
+        leb128::write::unsigned(&mut writable, internal_key_size as u64).unwrap();
+        writable.write_all(key).unwrap();
+        writable.write_all(
+            &pack_sequence_and_type(sequence, value_type).to_le_bytes()).unwrap();
+        leb128::write::unsigned(&mut writable, value.len() as u64).unwrap();
+        writable.write_all(value).unwrap();
+        debug_assert!(writable.is_empty());
+        table.insert(MemTableKey::new(buf));
+        if value_type == ValueType::RangeDeletion {
+            self.num_range_deletions_.fetch_add(1, atomic::Ordering::Release);
+        }
+    }
+
+    /// Looks up the newest entry for the user key of 'key' with a
+    /// sequence number not greater than the one in 'key'. Merge operands
+    /// are combined with the entry they apply to.
+    pub fn get(&self, key: &LookupKey) -> LookupResult {
+        let sequence = parse_internal_key(key.internal_key())
+            .expect("Lookup key is malformed").sequence;
+        let user_key = key.user_key();
+        let tombstone = self.max_covering_tombstone_sequence(user_key, sequence);
+        let mut operands = vec!();
+        let mut iter = skiplist::Iterator::new(&self.table_);
+        iter.seek(&MemTableKey::new(key.memtable_key().as_ptr()));
+        while iter.has_next() {
+            let entry = *iter.key();
+            let parsed = parse_internal_key(entry.internal_key())
+                .expect("MemTable entry has a malformed internal key");
+            if self.comparator_.user_comparator()
+                .compare(parsed.user_key, user_key) != Ordering::Equal {
+                break;
+            }
+            if tombstone.is_some_and(|tombstone| parsed.sequence < tombstone) {
+                // Older entries are deleted by the range deletion.
+                break;
+            }
+            match parsed.value_type {
+                ValueType::Value => {
+                    return self.merge(user_key, Some(entry.value()), operands);
+                }
+                ValueType::Deletion => return self.merge(user_key, None, operands),
+                ValueType::Merge => operands.push(entry.value().to_vec()),
+                ValueType::RangeDeletion => unreachable!(),
+            }
+            iter.next();
+        }
+        if tombstone.is_some() {
+            return self.merge(user_key, None, operands);
+        }
+        if operands.is_empty() {
+            return LookupResult::NotFound;
+        }
+        return LookupResult::MergeInProgress(operands);
+    }
+
+    /// Returns the result of applying 'operands', ordered newest first, to
+    /// the value of 'user_key', or to its deletion if 'value' is None.
+    fn merge(&self, user_key: &[u8], value: Option<&[u8]>, operands: Vec<Vec<u8>>)
+        -> LookupResult {
+        if operands.is_empty() {
+            return match value {
+                Some(value) => LookupResult::Found(value.to_vec()),
+                None => LookupResult::Deleted,
+            };
+        }
+        let operands: Vec<&[u8]> = operands.iter().rev().map(|operand| &operand[..])
+            .collect();
+        let merge_operator = self.merge_operator_.as_ref()
+            .expect("MemTable holds merge operands without a merge operator");
+        return LookupResult::Found(merge_operator.full_merge(user_key, value, &operands));
+    }
+
+    /// Returns the newest sequence number, not greater than 'sequence', of
+    /// the range deletions covering 'user_key'.
+    pub fn max_covering_tombstone_sequence(&self, user_key: &[u8], sequence: SequenceNumber)
+        -> Option<SequenceNumber> {
+        let num_range_deletions = self.num_range_deletions_.load(atomic::Ordering::Acquire);
+        if num_range_deletions == 0 {
+            return None;
+        }
+        let fragments = self.range_del_fragments_.read().unwrap().clone();
+        let fragments = if fragments.num_tombstones() >= num_range_deletions {
+            fragments
+        } else {
+            self.fragment_range_deletions(num_range_deletions)
+        };
+        return fragments.max_covering_tombstone_sequence(user_key, sequence);
+    }
+
+    /// Fragments the range deletions again unless another lookup already
+    /// fragmented at least 'num_range_deletions' of them.
+    fn fragment_range_deletions(&self, num_range_deletions: usize)
+        -> Arc<FragmentedRangeTombstones> {
+        let mut fragments = self.range_del_fragments_.write().unwrap();
+        if fragments.num_tombstones() < num_range_deletions {
+            let mut iter = self.range_del_iter();
+            iter.seek_to_first();
+            let mut tombstones = vec!();
+            while iter.valid() {
+                tombstones.push((iter.key().to_vec(), iter.value().to_vec()));
+                iter.next();
+            }
+            *fragments = Arc::new(FragmentedRangeTombstones::new(
+                self.comparator_.user_comparator().clone(),
+                tombstones.iter().map(|(begin, end)| (&begin[..], &end[..])))
+                .expect("MemTable holds a malformed range deletion"));
+        }
+        return fragments.clone();
+    }
+
+    /// Iterator over the entries of the MemTable, except range deletions.
+    pub fn iter(&self) -> MemTableIterator<'_> {
+        MemTableIterator {
+            iter_: skiplist::Iterator::new(&self.table_),
+        }
+    }
+
+    /// Iterator over the range deletions of the MemTable. Keys are the
+    /// internal keys of the start of the ranges, values their ends.
+    pub fn range_del_iter(&self) -> MemTableIterator<'_> {
+        MemTableIterator {
+            iter_: skiplist::Iterator::new(&self.range_del_table_),
+        }
+    }
+}
+
+impl Default for MemTable {
+    fn default() -> Self {
+        Self::new(InternalKeyComparator::new(Arc::new(BytewiseComparator)))
+    }
+}
+
+/// Iterates over the entries of a MemTable in internal key order.
+pub struct MemTableIterator<'a> {
+    iter_: skiplist::Iterator<'a, MemTableKey, MemTableKeyComparator>,
+}
+
+impl MemTableIterator<'_> {
+    #[inline(always)]
+    pub fn valid(&self) -> bool {
+        self.iter_.has_next()
+    }
+
+    #[inline(always)]
+    pub fn seek_to_first(&mut self) {
+        self.iter_.seek_to_first();
+    }
+
+    /// Positions at the first entry with internal key at or
+    /// after 'internal_key'.
+    pub fn seek(&mut self, internal_key: &[u8]) {
+        let mut data = Vec::with_capacity(internal_key.len() + 5);
+        leb128::write::unsigned(&mut data, internal_key.len() as u64).unwrap();
+        data.extend_from_slice(internal_key);
+        self.iter_.seek(&MemTableKey::new(data.as_ptr()));
+    }
+
+    #[inline(always)]
+    pub fn seek_to_last(&mut self) {
+        self.iter_.seek_to_last();
+    }
+
+    /// Positions at the last entry with internal key at or
+    /// before 'internal_key'.
+    pub fn seek_for_prev(&mut self, internal_key: &[u8]) {
+        let mut data = Vec::with_capacity(internal_key.len() + 5);
+        leb128::write::unsigned(&mut data, internal_key.len() as u64).unwrap();
+        data.extend_from_slice(internal_key);
+        self.iter_.seek_for_prev(&MemTableKey::new(data.as_ptr()));
+    }
+
+    #[inline(always)]
+    pub fn next(&mut self) {
+        self.iter_.next();
+    }
+
+    #[inline(always)]
+    pub fn prev(&mut self) {
+        self.iter_.prev();
+    }
+
+    /// Internal key of the current entry.
+    #[inline(always)]
+    pub fn key(&self) -> &[u8] {
+        self.iter_.key().internal_key()
+    }
+
+    #[inline(always)]
+    pub fn value(&self) -> &[u8] {
+        self.iter_.key().value()
+    }
+}
+
+impl InternalIterator for MemTableIterator<'_> {
+    fn valid(&self) -> bool {
+        MemTableIterator::valid(self)
+    }
+
+    fn seek_to_first(&mut self) -> Result<(), DataStoreError> {
+        MemTableIterator::seek_to_first(self);
+        return Ok(());
+    }
+
+    fn seek(&mut self, target: &[u8]) -> Result<(), DataStoreError> {
+        MemTableIterator::seek(self, target);
+        return Ok(());
+    }
+
+    fn next(&mut self) -> Result<(), DataStoreError> {
+        MemTableIterator::next(self);
+        return Ok(());
+    }
+
+    fn key(&self) -> &[u8] {
+        MemTableIterator::key(self)
+    }
+
+    fn value(&self) -> &[u8] {
+        MemTableIterator::value(self)
+    }
+}
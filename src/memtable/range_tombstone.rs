@@ -0,0 +1,262 @@
+use std::cmp::Ordering;
+use std::sync::Arc;
+use crate::comparator::Comparator;
+use crate::memtable::internal_key::{parse_internal_key, SequenceNumber};
+use crate::sst::lsm_error::DataStoreError;
+
+/// Part of the key space covered by the same range tombstones.
+#[derive(Debug)]
+struct Fragment {
+    begin: Vec<u8>,
+    end: Vec<u8>,
+    /// Sequence numbers of the tombstones covering the fragment, newest
+    /// first.
+    sequences: Vec<SequenceNumber>,
+}
+
+/// Range tombstones split at every begin and end key into non overlapping
+/// fragments, kept in key order. The tombstones covering a key are found
+/// by a binary search, instead of a scan of all the tombstones.
+pub struct FragmentedRangeTombstones {
+    comparator_: Arc<dyn Comparator>,
+    fragments_: Vec<Fragment>,
+    num_tombstones_: usize,
+}
+
+impl FragmentedRangeTombstones {
+    /// Fragments the range deletions of 'tombstones', given as the internal
+    /// key of their start along with their end user key. User keys are
+    /// ordered by 'comparator'. Fails with DataStoreError::MalformedBlock if
+    /// the start of a tombstone is not an internal key.
+    pub fn new<'a>(comparator: Arc<dyn Comparator>,
+        tombstones: impl IntoIterator<Item = (&'a [u8], &'a [u8])>)
+        -> Result<FragmentedRangeTombstones, DataStoreError> {
+        let mut num_tombstones = 0;
+        // (begin, end, sequence) of the non empty ranges.
+        let mut ranges: Vec<(&[u8], &[u8], SequenceNumber)> = vec!();
+        for (begin, end) in tombstones {
+            num_tombstones += 1;
+            let parsed = parse_internal_key(begin).ok_or_else(||
+                DataStoreError::MalformedBlock {
+                    offset: 0,
+                    reason: "malformed range deletion key".to_string(),
+                })?;
+            if comparator.compare(parsed.user_key, end) == Ordering::Less {
+                ranges.push((parsed.user_key, end, parsed.sequence));
+            }
+        }
+        ranges.sort_by(|a, b| comparator.compare(a.0, b.0));
+        let mut points: Vec<&[u8]> = ranges.iter()
+            .flat_map(|(begin, end, _)| [*begin, *end])
+            .collect();
+        points.sort_by(|a, b| comparator.compare(a, b));
+        points.dedup_by(|a, b| comparator.compare(a, b) == Ordering::Equal);
+
+        let mut fragments = vec!();
+        // Tombstones started before the current fragment, which may cover
+        // it.
+        let mut active: Vec<(&[u8], &[u8], SequenceNumber)> = vec!();
+        let mut next = 0;
+        for bounds in points.windows(2) {
+            let (begin, end) = (bounds[0], bounds[1]);
+            while next < ranges.len()
+                && comparator.compare(ranges[next].0, begin) != Ordering::Greater {
+                active.push(ranges[next]);
+                next += 1;
+            }
+            // No tombstone ends inside a fragment, so a tombstone ending after
+            // 'begin' covers the whole fragment.
+            active.retain(|(_, tombstone_end, _)| comparator.compare(tombstone_end, begin)
+                == Ordering::Greater);
+            if active.is_empty() {
+                continue;
+            }
+            let mut sequences: Vec<SequenceNumber> = active.iter()
+                .map(|(_, _, sequence)| *sequence)
+                .collect();
+            sequences.sort_unstable_by(|a, b| b.cmp(a));
+            sequences.dedup();
+            fragments.push(Fragment {
+                begin: begin.to_vec(),
+                end: end.to_vec(),
+                sequences,
+            });
+        }
+        return Ok(FragmentedRangeTombstones {
+            comparator_: comparator,
+            fragments_: fragments,
+            num_tombstones_: num_tombstones,
+        });
+    }
+
+    /// Number of tombstones the fragments were built from.
+    #[inline(always)]
+    pub fn num_tombstones(&self) -> usize {
+        self.num_tombstones_
+    }
+
+    /// Whether no key is covered, as all the tombstones are empty ranges.
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.fragments_.is_empty()
+    }
+
+    /// Returns the fragment at 'index' if it covers 'user_key', which must
+    /// be before its end.
+    fn covering(&self, index: usize, user_key: &[u8]) -> Option<&Fragment> {
+        return self.fragments_.get(index).filter(|fragment|
+            self.comparator_.compare(&fragment.begin, user_key) != Ordering::Greater);
+    }
+
+    /// Returns the newest sequence number, not greater than 'sequence', of
+    /// the tombstones covering 'user_key'.
+    pub fn max_covering_tombstone_sequence(&self, user_key: &[u8], sequence: SequenceNumber)
+        -> Option<SequenceNumber> {
+        let index = self.fragments_.partition_point(|fragment|
+            self.comparator_.compare(&fragment.end, user_key) != Ordering::Greater);
+        return self.covering(index, user_key)?.sequences.iter()
+            .find(|covering| **covering <= sequence)
+            .copied();
+    }
+
+    /// Fragments covering keys from 'lower', inclusive, to 'upper',
+    /// exclusive, clipped to these bounds, as (begin, end, sequences) in
+    /// key order. Sequence numbers are newest first. Missing bounds are
+    /// unbounded.
+    pub fn clipped<'b>(&'b self, lower: Option<&'b [u8]>, upper: Option<&'b [u8]>)
+        -> impl Iterator<Item = (&'b [u8], &'b [u8], &'b [SequenceNumber])> + 'b {
+        let comparator = self.comparator_.as_ref();
+        let start = lower.map_or(0, |lower| self.fragments_.partition_point(|fragment|
+            comparator.compare(&fragment.end, lower) != Ordering::Greater));
+        return self.fragments_[start..].iter()
+            .take_while(move |fragment| upper.is_none_or(|upper|
+                comparator.compare(&fragment.begin, upper) == Ordering::Less))
+            .map(move |fragment| {
+                let begin = match lower {
+                    Some(lower) if comparator.compare(&fragment.begin, lower)
+                        == Ordering::Less => lower,
+                    _ => &fragment.begin[..],
+                };
+                let end = match upper {
+                    Some(upper) if comparator.compare(upper, &fragment.end)
+                        == Ordering::Less => upper,
+                    _ => &fragment.end[..],
+                };
+                (begin, end, &fragment.sequences[..])
+            });
+    }
+
+    /// Cursor looking up increasing user keys.
+    pub fn cursor(&self) -> RangeTombstoneCursor<'_> {
+        RangeTombstoneCursor {
+            tombstones_: self,
+            index_: 0,
+        }
+    }
+}
+
+/// Looks up the tombstones covering user keys given in increasing order,
+/// moving forward through the fragments instead of searching all of them.
+pub struct RangeTombstoneCursor<'a> {
+    tombstones_: &'a FragmentedRangeTombstones,
+    /// First fragment ending after the last key looked up.
+    index_: usize,
+}
+
+impl RangeTombstoneCursor<'_> {
+    /// Returns the newest sequence number of the tombstones covering
+    /// 'user_key', which must not be less than the keys looked up before.
+    pub fn max_covering_tombstone_sequence(&mut self, user_key: &[u8])
+        -> Option<SequenceNumber> {
+        let tombstones = self.tombstones_;
+        // Fragments are usually few and skipped one at a time.
+        while self.index_ < tombstones.fragments_.len() && tombstones.comparator_.compare(
+            &tombstones.fragments_[self.index_].end, user_key) != Ordering::Greater {
+            self.index_ += 1;
+        }
+        return tombstones.covering(self.index_, user_key)
+            .map(|fragment| fragment.sequences[0]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::comparator::BytewiseComparator;
+    use crate::memtable::internal_key::{InternalKey, ValueType};
+    use crate::memtable::range_tombstone::FragmentedRangeTombstones;
+    use crate::sst::lsm_error::DataStoreError;
+
+    fn tombstone(begin: &str, end: &str, sequence: u64) -> (Vec<u8>, Vec<u8>) {
+        let begin = InternalKey::new(begin.as_bytes(), sequence, ValueType::RangeDeletion);
+        return (begin.encode().to_vec(), end.as_bytes().to_vec());
+    }
+
+    fn fragment(tombstones: &[(Vec<u8>, Vec<u8>)]) -> FragmentedRangeTombstones {
+        return FragmentedRangeTombstones::new(Arc::new(BytewiseComparator),
+            tombstones.iter().map(|(begin, end)| (&begin[..], &end[..]))).unwrap();
+    }
+
+    #[test]
+    fn test_overlapping_tombstones() {
+        let tombstones = fragment(&[tombstone("d", "k", 5), tombstone("a", "f", 10),
+            tombstone("h", "j", 20), tombstone("x", "x", 30)]);
+        assert_eq!(tombstones.num_tombstones(), 4);
+        let lookup = |key: &str, sequence| tombstones.max_covering_tombstone_sequence(
+            key.as_bytes(), sequence);
+        assert_eq!(lookup("0", 100), None);
+        assert_eq!(lookup("a", 100), Some(10));
+        assert_eq!(lookup("e", 100), Some(10));
+        assert_eq!(lookup("e", 9), Some(5));
+        assert_eq!(lookup("e", 4), None);
+        assert_eq!(lookup("f", 100), Some(5));
+        assert_eq!(lookup("h", 100), Some(20));
+        assert_eq!(lookup("j", 100), Some(5));
+        assert_eq!(lookup("k", 100), None);
+        // Empty ranges cover nothing.
+        assert_eq!(lookup("x", 100), None);
+
+        let mut cursor = tombstones.cursor();
+        let expected = [("0", None), ("a", Some(10)), ("b", Some(10)), ("e", Some(10)),
+            ("g", Some(5)), ("i", Some(20)), ("j", Some(5)), ("z", None)];
+        for (key, sequence) in expected {
+            assert_eq!(cursor.max_covering_tombstone_sequence(key.as_bytes()), sequence,
+                "{}", key);
+        }
+    }
+
+    #[test]
+    fn test_no_tombstones() {
+        let tombstones = fragment(&[]);
+        assert!(tombstones.is_empty());
+        assert_eq!(tombstones.max_covering_tombstone_sequence(b"a", 100), None);
+        assert_eq!(tombstones.cursor().max_covering_tombstone_sequence(b"a"), None);
+        assert!(fragment(&[tombstone("b", "a", 10)]).is_empty());
+    }
+
+    #[test]
+    fn test_clipped() {
+        let tombstones = fragment(&[tombstone("d", "k", 5), tombstone("a", "f", 10)]);
+        let clipped = |lower: Option<&'static str>, upper: Option<&'static str>| tombstones
+            .clipped(lower.map(str::as_bytes), upper.map(str::as_bytes))
+            .map(|(begin, end, sequences)| (begin.to_vec(), end.to_vec(), sequences.to_vec()))
+            .collect::<Vec<_>>();
+        let fragment = |begin: &str, end: &str, sequences: &[u64]|
+            (begin.as_bytes().to_vec(), end.as_bytes().to_vec(), sequences.to_vec());
+        assert_eq!(clipped(None, None), vec!(fragment("a", "d", &[10]),
+            fragment("d", "f", &[10, 5]), fragment("f", "k", &[5])));
+        assert_eq!(clipped(Some("b"), Some("e")), vec!(fragment("b", "d", &[10]),
+            fragment("d", "e", &[10, 5])));
+        assert_eq!(clipped(Some("f"), None), vec!(fragment("f", "k", &[5])));
+        assert_eq!(clipped(None, Some("a")), vec!());
+        assert_eq!(clipped(Some("k"), None), vec!());
+    }
+
+    #[test]
+    fn test_malformed_tombstone() {
+        let tombstones = [(b"a".to_vec(), b"b".to_vec())];
+        assert!(matches!(FragmentedRangeTombstones::new(Arc::new(BytewiseComparator),
+            tombstones.iter().map(|(begin, end)| (&begin[..], &end[..]))),
+            Err(DataStoreError::MalformedBlock { .. })));
+    }
+}
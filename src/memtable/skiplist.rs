@@ -1,18 +1,25 @@
 use std::sync::atomic::{AtomicUsize, AtomicPtr, Ordering};
 use rand::{thread_rng, Rng};
+use crate::memtable::arena::Arena;
 
 // SkipList Thread Safety
 // ----------------------
 // This implementation of SkipList is inspired by LevelDB implementation.
-// Writes have to be synchronized between threads, but reads are lock free.
+// Writes through 'insert' have to be synchronized between threads, but
+// reads are lock free. Writes through 'insert_concurrently' link the new
+// node level by level using compare-and-swap, so multiple writers can
+// insert at the same time without any external synchronization.
 // Writes are appends only i.e., there is no delete or update supported.
-// The memory for every node in the list is kept until SkipList is
-// not destroyed.
+// Every node in the list is allocated from the Arena owned by the
+// SkipList with only as many next pointers as its random height, and
+// the memory is kept until SkipList is not destroyed.
+// Keys are dropped and the Arena is freed when the SkipList is dropped.
 // Caveats:
 // 1. Same key cannot be inserted twice.
 // 2. No update or deletes supported.
-// 3. Writes need to be synchronized, but reads are lock free.
-// TODO: Free up the memory
+// 3. Writes through 'insert' need to be synchronized, but reads are lock
+//    free. 'insert' and 'insert_concurrently' must not be mixed while
+//    another writer is active.
 
 // Maximum height of the skiplist.
 const MAX_HEIGHT: usize = 12;
@@ -20,116 +27,254 @@ const MAX_HEIGHT: usize = 12;
 // Check the function 'RandomHeight'
 const BRANCHING: usize = 5;
 
+/// Node of the list. Only the next pointer for level 0 is declared;
+/// a node of height 'h' is allocated with 'h' more pointers laid out
+/// right after it, so nodes only pay for the levels they are linked in.
+#[repr(C)]
 struct Node<Key> {
     key: Key,
-    nodes_: [AtomicPtr<Node<Key>>; MAX_HEIGHT],
+    nodes_: [AtomicPtr<Node<Key>>; 1],
 }
 
 impl<Key> Node<Key> {
-    fn new(key: Key) -> Box<Node<Key>> {
-        Box::new(Node {
-            key : key,
-            nodes_: Default::default()
-        })
+    /// Bytes needed for a node linked in levels 0 to 'height'.
+    #[inline(always)]
+    fn allocation_size(height: usize) -> usize {
+        std::mem::size_of::<Node<Key>>()
+            + height * std::mem::size_of::<AtomicPtr<Node<Key>>>()
+    }
+
+    fn new(arena: &Arena, key: Key, height: usize) -> *mut Node<Key> {
+        assert!(height < MAX_HEIGHT);
+        let node = arena.allocate_aligned(Self::allocation_size(height),
+            std::mem::align_of::<Node<Key>>()) as *mut Node<Key>;
+        unsafe {
+            std::ptr::write(node, Node {
+                key,
+                nodes_: Default::default()
+            });
+            let links = std::ptr::addr_of_mut!((*node).nodes_) as *mut AtomicPtr<Node<Key>>;
+            for level in 1..height + 1 {
+                std::ptr::write(links.add(level), AtomicPtr::default());
+            }
+        }
+        return node;
     }
 
-    fn head(dummy_key: Key) -> Box<Node<Key>> {
-        Box::new(Node {
-            key : dummy_key,
-            nodes_: Default::default()
-        })
+    fn head(arena: &Arena, dummy_key: Key) -> *mut Node<Key> {
+        Self::new(arena, dummy_key, MAX_HEIGHT - 1)
     }
 
+    /// Next pointer at 'level' of 'node'. Links past the first one lie
+    /// outside of the declared array, so 'node' must be derived from the
+    /// allocation of the node, never from a reference to it. Caller must
+    /// also ensure the node was allocated with a height of at least
+    /// 'level'.
     #[inline(always)]
-    fn set_next(&self, level: usize, node: &mut Node<Key>) {
-        self.nodes_[level].store(node, Ordering::Release);
+    unsafe fn link<'a>(node: *const Node<Key>, level: usize) -> &'a AtomicPtr<Node<Key>> {
+        let links = std::ptr::addr_of!((*node).nodes_) as *const AtomicPtr<Node<Key>>;
+        &*links.add(level)
     }
 
     #[inline(always)]
-    fn set_next_no_barrier(&self, level: usize, node: &mut Node<Key>) {
-        self.nodes_[level].store(node, Ordering::Relaxed);
+    unsafe fn set_next(node: *const Node<Key>, level: usize, next: *mut Node<Key>) {
+        Self::link(node, level).store(next, Ordering::Release);
     }
 
-    fn next(&self, level: usize) -> Option<&Node<Key>> {
-        let node = self.nodes_[level].load(Ordering::Acquire);
-        if node.is_null() {
-            return Option::None;
-        } else {
-            unsafe {
-                return Option::from(&*node);
-            }
-        }
+    #[inline(always)]
+    unsafe fn set_next_no_barrier(node: *const Node<Key>, level: usize, next: *mut Node<Key>) {
+        Self::link(node, level).store(next, Ordering::Relaxed);
     }
 
+    /// Sets next pointer at 'level' to 'next' only if it still points to
+    /// 'expected'. Returns false if another writer got there first.
     #[inline(always)]
-    fn next_no_barrier(&self, level: usize) -> Option<&mut Node<Key>> {
-        let node = self.nodes_[level].load(Ordering::Relaxed);
-        if node.is_null() {
-            return Option::None;
-        } else {
-            unsafe {
-                return Option::from(&mut *node);
-            }
-        }
+    unsafe fn cas_next(node: *const Node<Key>, level: usize, expected: *mut Node<Key>,
+        next: *mut Node<Key>) -> bool {
+        Self::link(node, level)
+            .compare_exchange(expected, next, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+
+    /// Next node at 'level', null at the end of the list.
+    #[inline(always)]
+    unsafe fn next(node: *const Node<Key>, level: usize) -> *mut Node<Key> {
+        Self::link(node, level).load(Ordering::Acquire)
+    }
+
+    #[inline(always)]
+    unsafe fn next_no_barrier(node: *const Node<Key>, level: usize) -> *mut Node<Key> {
+        Self::link(node, level).load(Ordering::Relaxed)
     }
 }
 
-pub struct SkipList<Key>
-where Key: std::cmp::Ord {
-    head_: Box<Node<Key>>,
+/// Orders the keys of a SkipList.
+pub trait KeyComparator<Key>: Send + Sync {
+    fn compare(&self, a: &Key, b: &Key) -> std::cmp::Ordering;
+}
+
+/// Orders keys by their 'Ord' implementation.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OrdComparator;
+
+impl<Key: std::cmp::Ord> KeyComparator<Key> for OrdComparator {
+    #[inline(always)]
+    fn compare(&self, a: &Key, b: &Key) -> std::cmp::Ordering {
+        a.cmp(b)
+    }
+}
+
+pub struct SkipList<Key, Cmp = OrdComparator>
+where Cmp: KeyComparator<Key> {
+    comparator_: Cmp,
+    /// Memory for all the nodes including head.
+    arena_: Arena,
+    head_: *mut Node<Key>,
     max_height_: AtomicUsize
 }
 
+// Nodes are only reachable through the SkipList and are published with
+// atomic stores, so the list can be shared as long as keys can be.
+unsafe impl<Key: Send + Sync, Cmp: KeyComparator<Key>> Send for SkipList<Key, Cmp> {}
+unsafe impl<Key: Send + Sync, Cmp: KeyComparator<Key>> Sync for SkipList<Key, Cmp> {}
+
 impl<Key> SkipList<Key>
 where Key: std::cmp::Ord {
     pub fn new(dummy_key: Key) -> SkipList<Key> {
+        Self::with_comparator(dummy_key, OrdComparator)
+    }
+}
+
+impl<Key, Cmp> SkipList<Key, Cmp>
+where Cmp: KeyComparator<Key> {
+    pub fn with_comparator(dummy_key: Key, comparator: Cmp) -> SkipList<Key, Cmp> {
+        let arena = Arena::new();
+        let head = Node::head(&arena, dummy_key);
         SkipList {
-            head_ : Node::head(dummy_key),
+            comparator_: comparator,
+            arena_: arena,
+            head_ : head,
             max_height_: AtomicUsize::new(0),
         }
     }
 
+    /// Arena backing the list. Callers may allocate memory for data
+    /// referred by keys from it, so it lives as long as the list.
+    #[inline(always)]
+    pub fn arena(&self) -> &Arena {
+        &self.arena_
+    }
+
+    /// Approximate memory used by the list, including memory allocated
+    /// by callers through 'arena'.
+    pub fn approximate_memory_usage(&self) -> usize {
+        self.arena_.memory_usage()
+    }
+
+    /// Bytes allocated for a node linked in levels 0 to 'height'.
+    #[cfg(test)]
+    pub(crate) fn node_size(height: usize) -> usize {
+        Node::<Key>::allocation_size(height)
+    }
+
     #[inline(always)]
-    fn equal(a: &Key, b: &Key) -> bool {
-        a.cmp(b) == std::cmp::Ordering::Equal
+    fn equal(&self, a: &Key, b: &Key) -> bool {
+        self.comparator_.compare(a, b) == std::cmp::Ordering::Equal
     }
 
     pub fn insert(&self, key: Key) {
         let mut prev :[*const Node<Key>; MAX_HEIGHT] = [std::ptr::null(); MAX_HEIGHT];
         let node = self.find_greater_or_equal::<true>(&key, &mut prev);
-        assert!(node.is_none() || !Self::equal(&node.unwrap().key, &key));
+        assert!(node.is_null() || !self.equal(unsafe { &(*node).key }, &key));
         let height = self.random_height();
         let max_height = self.max_height_.load(Ordering::Acquire);
         if height > max_height {
-            for i in max_height+1..height+1 {
-                prev[i] = &*self.head_;
+            for p in prev.iter_mut().take(height + 1).skip(max_height + 1) {
+                *p = self.head_;
             }
             self.max_height_.store(height, Ordering::Release);
         }
-        let x = Box::into_raw(Box::new(Node::new(key)));
-        for i in 0..height+1 {
-            // Avoiding Barriers as we will apply Barrier
-            // in the next statement.
-            let next_node = unsafe {
-                (*prev[i]).next_no_barrier(i)
-            };         
+        let x = Node::new(&self.arena_, key, height);
+        for (i, p) in prev.iter().enumerate().take(height + 1) {
             unsafe {
-                if next_node.is_some() {
-                    x.as_ref().unwrap().set_next_no_barrier(i, next_node.unwrap())
-                };
-                (*prev[i]).set_next(i, &mut *x);
+                // Avoiding Barriers as we will apply Barrier
+                // in the next statement.
+                let next_node = Node::next_no_barrier(*p, i);
+                Node::set_next_no_barrier(x, i, next_node);
+                Node::set_next(*p, i, x);
+            }
+        }
+    }
+
+    /// Inserts 'key' without requiring writers to be serialized. The
+    /// new node is linked from the bottom level upwards, and each level is
+    /// published with a compare-and-swap. If the swap fails, another writer
+    /// has linked a node at the same position, so the splice for that level
+    /// is searched again starting from the previous node.
+    pub fn insert_concurrently(&self, key: Key) {
+        let height = self.random_height();
+        let mut max_height = self.max_height_.load(Ordering::Relaxed);
+        while height > max_height {
+            match self.max_height_.compare_exchange_weak(max_height, height,
+                Ordering::AcqRel, Ordering::Relaxed) {
+                Ok(_) => break,
+                Err(current) => max_height = current,
+            }
+        }
+        let x = Node::new(&self.arena_, key, height);
+        let key = unsafe { &(*x).key };
+        let mut prev: [*const Node<Key>; MAX_HEIGHT] = [self.head_; MAX_HEIGHT];
+        let mut next: [*mut Node<Key>; MAX_HEIGHT] = [std::ptr::null_mut(); MAX_HEIGHT];
+        let mut before: *const Node<Key> = self.head_;
+        for level in (0..self.get_max_level() + 1).rev() {
+            let (p, n) = self.find_splice_for_level(key, before, level);
+            prev[level] = p;
+            next[level] = n;
+            before = p;
+        }
+        for level in 0..height + 1 {
+            loop {
+                if level == 0 {
+                    assert!(next[0].is_null()
+                        || !self.equal(unsafe { &(*next[0]).key }, key));
+                }
+                unsafe {
+                    Node::set_next_no_barrier(x, level, next[level]);
+                    if Node::cas_next(prev[level], level, next[level], x) {
+                        break;
+                    }
+                }
+                let (p, n) = self.find_splice_for_level(key, prev[level], level);
+                prev[level] = p;
+                next[level] = n;
             }
         }
     }
 
+    /// Finds the nodes between which 'key' belongs at 'level', starting
+    /// the search at 'before', which must sort before 'key'.
+    fn find_splice_for_level(&self, key: &Key, before: *const Node<Key>,
+        level: usize) -> (*const Node<Key>, *mut Node<Key>) {
+        let mut x = before;
+        loop {
+            let next = unsafe { Node::next(x, level) };
+            if next.is_null() || !self.key_greater_than_node(key, next) {
+                return (x, next);
+            }
+            x = next;
+        }
+    }
+
+    /// Returns the first node with a key greater than or equal to 'key',
+    /// or null if there is no such node.
     fn find_greater_or_equal<const PREV: bool>(&self, key: &Key,
-        prev: &mut [*const Node<Key>]) -> Option<&Node<Key>> {
-        let mut x = &*self.head_;
+        prev: &mut [*const Node<Key>]) -> *const Node<Key> {
+        let mut x: *const Node<Key> = self.head_;
         let mut level = self.get_max_level();
         loop {
-            let next = x.next(level);
-            if !next.is_none() && self.key_greater_than_node(key, next.unwrap()) {
-                x = next.unwrap();
+            let next = unsafe { Node::next(x, level) };
+            if !next.is_null() && self.key_greater_than_node(key, next) {
+                x = next;
             } else {
                 if PREV {
                     prev[level] = x;
@@ -142,14 +287,54 @@ where Key: std::cmp::Ord {
         }
     }
 
+    /// Returns the last node with a key less than 'key', or null if there
+    /// is no such node.
+    fn find_less_than(&self, key: &Key) -> *const Node<Key> {
+        let mut x: *const Node<Key> = self.head_;
+        let mut level = self.get_max_level();
+        loop {
+            let next = unsafe { Node::next(x, level) };
+            if !next.is_null() && self.key_greater_than_node(key, next) {
+                x = next;
+            } else {
+                if level == 0 {
+                    return self.node_unless_head(x);
+                }
+                level -= 1;
+            }
+        }
+    }
+
+    /// Returns the last node in the list, or null if the list is empty.
+    fn find_last(&self) -> *const Node<Key> {
+        let mut x: *const Node<Key> = self.head_;
+        let mut level = self.get_max_level();
+        loop {
+            let next = unsafe { Node::next(x, level) };
+            if !next.is_null() {
+                x = next;
+            } else {
+                if level == 0 {
+                    return self.node_unless_head(x);
+                }
+                level -= 1;
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn node_unless_head(&self, x: *const Node<Key>) -> *const Node<Key> {
+        if std::ptr::eq(x, self.head_) { std::ptr::null() } else { x }
+    }
+
     #[inline(always)]
-    fn key_greater_than_node(&self, key: &Key, x: &Node<Key>) -> bool {
-        key.cmp(&x.key) == std::cmp::Ordering::Greater
+    fn key_greater_than_node(&self, key: &Key, x: *const Node<Key>) -> bool {
+        self.comparator_.compare(key, unsafe { &(*x).key }) == std::cmp::Ordering::Greater
     }
 
     pub fn contains(&self, key: &Key) -> bool {
         let node = self.find_greater_or_equal::<false>(key, Default::default());
-        node.is_some() && Self::equal(key, &node.unwrap().key)
+        !node.is_null() && self.equal(key, unsafe { &(*node).key })
     }
 
     #[inline(always)]
@@ -161,40 +346,63 @@ where Key: std::cmp::Ord {
         let mut height = 0;
         while height < MAX_HEIGHT - 1
             && (thread_rng().gen_range(0..BRANCHING) == 0) {
-            height = height + 1;
+            height += 1;
         }
         return height;
     }
 }
 
-pub struct Iterator<'a, Key>
-where Key: std::cmp::Ord {
-    skiplist_: &'a SkipList<Key>,
-    node_: Option<&'a Node<Key>>
+impl<Key, Cmp> Drop for SkipList<Key, Cmp>
+where Cmp: KeyComparator<Key> {
+    fn drop(&mut self) {
+        if !std::mem::needs_drop::<Key>() {
+            return;
+        }
+        // Every node is linked at level 0, so walking it visits all keys.
+        let mut x = self.head_;
+        while !x.is_null() {
+            unsafe {
+                let next = Node::next_no_barrier(x, 0);
+                std::ptr::drop_in_place(&mut (*x).key);
+                x = next;
+            }
+        }
+    }
 }
 
-impl<'a, Key> Iterator<'a, Key>
-where Key: std::cmp::Ord {
-    pub fn new(skiplist: &SkipList<Key>) -> Iterator<Key> {
+pub struct Iterator<'a, Key, Cmp = OrdComparator>
+where Cmp: KeyComparator<Key> {
+    skiplist_: &'a SkipList<Key, Cmp>,
+    /// Current node, null when the iterator is not positioned.
+    node_: *const Node<Key>
+}
+
+// Nodes are kept as long as the SkipList the iterator borrows.
+unsafe impl<Key: Send + Sync, Cmp: KeyComparator<Key>> Send for Iterator<'_, Key, Cmp> {}
+unsafe impl<Key: Send + Sync, Cmp: KeyComparator<Key>> Sync for Iterator<'_, Key, Cmp> {}
+
+impl<'a, Key, Cmp> Iterator<'a, Key, Cmp>
+where Cmp: KeyComparator<Key> {
+    pub fn new(skiplist: &SkipList<Key, Cmp>) -> Iterator<'_, Key, Cmp> {
         Iterator {
             skiplist_: skiplist,
-            node_: skiplist.head_.next(0)
+            node_: unsafe { Node::next(skiplist.head_, 0) }
         }
     }
 
     #[inline(always)]
-    pub fn has_next(&self) -> bool { self.node_.is_some() }
+    pub fn has_next(&self) -> bool { !self.node_.is_null() }
 
     #[inline(always)]
     pub fn next(&mut self) {
         assert!(self.has_next());
-        self.node_ = self.node_.unwrap().next(0);
+        self.node_ = unsafe { Node::next(self.node_, 0) };
     }
 
     #[inline(always)]
     pub fn key(&self) -> &Key {
         assert!(self.has_next());
-        return &self.node_.unwrap().key;
+        return unsafe { &(*self.node_).key };
     }
 
     #[inline(always)]
@@ -205,6 +413,30 @@ where Key: std::cmp::Ord {
 
     #[inline(always)]
     pub fn seek_to_first(&mut self) {
-        self.node_ = self.skiplist_.head_.next(0);
+        self.node_ = unsafe { Node::next(self.skiplist_.head_, 0) };
+    }
+
+    /// Moves to the previous key. Nodes only link forward, so this
+    /// searches for the last key less than the current one.
+    #[inline(always)]
+    pub fn prev(&mut self) {
+        assert!(self.has_next());
+        self.node_ = self.skiplist_.find_less_than(self.key());
+    }
+
+    #[inline(always)]
+    pub fn seek_to_last(&mut self) {
+        self.node_ = self.skiplist_.find_last();
+    }
+
+    /// Positions at the last key less than or equal to 'target'.
+    pub fn seek_for_prev(&mut self, target: &Key) {
+        self.seek(target);
+        if !self.has_next() {
+            self.seek_to_last();
+        } else if self.skiplist_.comparator_.compare(self.key(), target)
+            == std::cmp::Ordering::Greater {
+            self.prev();
+        }
     }
 }
\ No newline at end of file
@@ -2,13 +2,15 @@
 mod skiplist_test {
     use crate::memtable::skiplist::{Iterator, SkipList};
     use std::sync::{Arc, Mutex};
+    use rand::seq::SliceRandom;
+    use rand::thread_rng;
 
     #[test]
     fn test_empty_list() {
         let s = SkipList::<i32>::new(0);
-        assert_eq!(s.contains(&0), false);
+        assert!(!s.contains(&0));
         let iter = Iterator::new(&s);
-        assert_eq!(iter.has_next(), false);
+        assert!(!iter.has_next());
     }
 
     #[test]
@@ -45,7 +47,7 @@ mod skiplist_test {
             assert_eq!(iter.key(), &i);
             iter.next();
         }
-        assert_eq!(iter.has_next(), false);
+        assert!(!iter.has_next());
         iter.seek_to_first();
         assert_eq!(iter.key(), &1);
         // Seek to existing key
@@ -53,7 +55,65 @@ mod skiplist_test {
         assert_eq!(iter.key(), &800);
         // Seek to non-existing key
         iter.seek(&5000);
-        assert_eq!(iter.has_next(), false);
+        assert!(!iter.has_next());
+    }
+
+    #[test]
+    fn test_insert_reverse_iteration_onethread() {
+        let s = SkipList::<i32>::new(Default::default());
+        let mut expected_list = vec!();
+        // Insert in order
+        for i in 1..1000 {
+            s.insert(i);
+            expected_list.push(i);
+        }
+        let expected_len = expected_list.len();
+        // Insert in reverse Order
+        for i in (3000..4001).rev() {
+            s.insert(i);
+            expected_list.insert(expected_len, i);
+        }
+        // Reverse Iterator tests
+        let mut iter = Iterator::new(&s);
+        iter.seek_to_last();
+        for i in expected_list.iter().rev() {
+            assert!(iter.has_next());
+            assert_eq!(iter.key(), i);
+            iter.prev();
+        }
+        assert!(!iter.has_next());
+        iter.seek_to_last();
+        assert_eq!(iter.key(), &4000);
+        // Seek for prev to existing key
+        iter.seek_for_prev(&800);
+        assert_eq!(iter.key(), &800);
+        iter.prev();
+        assert_eq!(iter.key(), &799);
+        // Seek for prev to non-existing key in the gap
+        iter.seek_for_prev(&2500);
+        assert_eq!(iter.key(), &999);
+        // Seek for prev beyond the last key
+        iter.seek_for_prev(&5000);
+        assert_eq!(iter.key(), &4000);
+        // Seek for prev before the first key
+        iter.seek_for_prev(&0);
+        assert!(!iter.has_next());
+        // Mixing directions
+        iter.seek(&3000);
+        iter.prev();
+        assert_eq!(iter.key(), &999);
+        iter.next();
+        assert_eq!(iter.key(), &3000);
+    }
+
+    #[test]
+    fn test_reverse_iteration_empty_list() {
+        let s = SkipList::<i32>::new(0);
+        let mut iter = Iterator::new(&s);
+        iter.seek_to_last();
+        assert!(!iter.has_next());
+        iter.seek_for_prev(&10);
+        assert!(!iter.has_next());
     }
 
     #[test]
@@ -95,4 +155,415 @@ mod skiplist_test {
             }
         });
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_insert_concurrently_multiwriter() {
+        const WRITERS: u64 = 8;
+        const KEYS_PER_WRITER: u64 = 2000;
+        let s = SkipList::<u64>::new(0);
+        // Use OS threads rather than the rayon pool, so writers are
+        // preempted mid-insert even on a single core.
+        std::thread::scope(|thread_scope| {
+            for writer in 0..WRITERS {
+                let s = &s;
+                thread_scope.spawn(move || {
+                    // Interleave keys across writers and shuffle them so
+                    // that writers keep racing on the same splices.
+                    let mut keys: Vec<u64> = (0..KEYS_PER_WRITER)
+                        .map(|i| 1 + i * WRITERS + writer)
+                        .collect();
+                    keys.shuffle(&mut thread_rng());
+                    for key in keys {
+                        s.insert_concurrently(key);
+                    }
+                });
+            }
+            // Readers run lock free alongside writers and must always
+            // observe keys in order.
+            thread_scope.spawn(|| {
+                for _ in 0..20 {
+                    let mut iter = Iterator::new(&s);
+                    let mut last = 0;
+                    while iter.has_next() {
+                        assert!(*iter.key() > last);
+                        last = *iter.key();
+                        iter.next();
+                    }
+                }
+            });
+        });
+        let total = WRITERS * KEYS_PER_WRITER;
+        for key in 1..total + 1 {
+            assert!(s.contains(&key), "key {} is missing", key);
+        }
+        assert!(!s.contains(&(total + 1)));
+        let mut iter = Iterator::new(&s);
+        for key in 1..total + 1 {
+            assert!(iter.has_next());
+            assert_eq!(iter.key(), &key);
+            iter.next();
+        }
+        assert!(!iter.has_next());
+    }
+
+    #[test]
+    fn test_drop_releases_keys() {
+        let key = Arc::new(0);
+        let s = SkipList::new(key.clone());
+        for i in 1..100 {
+            s.insert(Arc::new(i));
+        }
+        s.insert(key.clone());
+        assert_eq!(Arc::strong_count(&key), 3);
+        drop(s);
+        assert_eq!(Arc::strong_count(&key), 1);
+    }
+
+    #[test]
+    fn test_node_size() {
+        let ptr_size = std::mem::size_of::<usize>();
+        // Nodes only carry pointers for the levels they are linked in.
+        assert_eq!(SkipList::<u64>::node_size(0), 8 + ptr_size);
+        assert_eq!(SkipList::<u64>::node_size(1), 8 + 2 * ptr_size);
+        assert_eq!(SkipList::<u64>::node_size(11), 8 + 12 * ptr_size);
+    }
+
+    #[test]
+    fn test_memory_footprint() {
+        let s = SkipList::<u64>::new(0);
+        let initial = s.approximate_memory_usage();
+        let count = 10000;
+        for i in 1..count + 1 {
+            s.insert(i);
+        }
+        let used = (s.approximate_memory_usage() - initial) as u64;
+        let ptr_size = std::mem::size_of::<usize>() as u64;
+        // With BRANCHING = 5 a node has 1.25 pointers on average, so the
+        // list is far below what nodes with 12 pointers each would need.
+        assert!(used >= count * (8 + ptr_size));
+        assert!(used < count * (8 + 2 * ptr_size));
+        assert!(used * 4 < count * (8 + 12 * ptr_size));
+        let mut iter = Iterator::new(&s);
+        for i in 1..count + 1 {
+            assert_eq!(iter.key(), &i);
+            iter.next();
+        }
+        assert!(!iter.has_next());
+    }
+}
+
+#[cfg(test)]
+mod arena_test {
+    use crate::memtable::arena::Arena;
+
+    #[test]
+    fn test_empty_arena() {
+        let arena = Arena::new();
+        assert_eq!(arena.memory_usage(), 0);
+    }
+
+    #[test]
+    fn test_allocations_are_aligned_and_disjoint() {
+        let arena = Arena::new();
+        let mut allocated: Vec<(*mut u8, usize, u8)> = vec!();
+        for i in 1..2000usize {
+            // Mix of small, odd-sized and large allocations.
+            let size = if i % 100 == 0 { 6000 } else { i % 37 + 1 };
+            let ptr = if i % 2 == 0 {
+                arena.allocate_aligned(size, 8)
+            } else {
+                arena.allocate(size)
+            };
+            if i % 2 == 0 {
+                assert_eq!(ptr as usize % 8, 0);
+            }
+            let fill = (i % 256) as u8;
+            unsafe { std::ptr::write_bytes(ptr, fill, size) };
+            allocated.push((ptr, size, fill));
+        }
+        // Nothing got overwritten by later allocations.
+        for (ptr, size, fill) in allocated {
+            let bytes = unsafe { std::slice::from_raw_parts(ptr, size) };
+            assert!(bytes.iter().all(|b| *b == fill));
+        }
+    }
+
+    #[test]
+    fn test_memory_usage() {
+        let arena = Arena::new();
+        let mut bytes = 0;
+        for i in 0..1000 {
+            let size = i % 100 + 1;
+            arena.allocate(size);
+            bytes += size;
+            assert!(arena.memory_usage() >= bytes);
+        }
+        // Small allocations share blocks, so waste stays bounded.
+        assert!(arena.memory_usage() <= bytes + bytes / 10 + 4096);
+        // Large allocations get their own block.
+        let before = arena.memory_usage();
+        arena.allocate(100 * 1024);
+        assert!(arena.memory_usage() >= before + 100 * 1024);
+    }
+}
+
+#[cfg(test)]
+mod memtable_test {
+    use crate::memtable::{LookupResult, MemTable};
+    use crate::memtable::internal_key::{parse_internal_key, InternalKey,
+        InternalKeyComparator, LookupKey, ValueType, MAX_SEQUENCE_NUMBER,
+        VALUE_TYPE_FOR_SEEK};
+    use crate::comparator::{BytewiseComparator, Comparator, ReverseComparator};
+    use crate::memtable::internal_key::InternalFilterPolicy;
+    use crate::merge_operator::StringAppendOperator;
+    use crate::sst::filter::{BloomFilterPolicy, FilterPolicy};
+    use std::cmp::Ordering;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_empty_memtable() {
+        let m = MemTable::default();
+        assert_eq!(m.get(&LookupKey::new(b"key", 100)), LookupResult::NotFound);
+        let iter = m.iter();
+        assert!(!iter.valid());
+    }
+
+    #[test]
+    fn test_overwrite_and_delete() {
+        let m = MemTable::default();
+        m.add(1, ValueType::Value, b"key", b"value1");
+        m.add(2, ValueType::Value, b"key", b"value2");
+        m.add(3, ValueType::Deletion, b"key", b"");
+        m.add(4, ValueType::Value, b"key", b"value4");
+        m.add(2, ValueType::Value, b"other", b"other2");
+        // Newest entry visible at each sequence number wins.
+        assert_eq!(m.get(&LookupKey::new(b"key", 0)), LookupResult::NotFound);
+        assert_eq!(m.get(&LookupKey::new(b"key", 1)),
+            LookupResult::Found(b"value1".to_vec()));
+        assert_eq!(m.get(&LookupKey::new(b"key", 2)),
+            LookupResult::Found(b"value2".to_vec()));
+        assert_eq!(m.get(&LookupKey::new(b"key", 3)), LookupResult::Deleted);
+        assert_eq!(m.get(&LookupKey::new(b"key", 100)),
+            LookupResult::Found(b"value4".to_vec()));
+        assert_eq!(m.get(&LookupKey::new(b"other", 100)),
+            LookupResult::Found(b"other2".to_vec()));
+        // Keys sharing a prefix are different user keys.
+        assert_eq!(m.get(&LookupKey::new(b"ke", 100)), LookupResult::NotFound);
+        assert_eq!(m.get(&LookupKey::new(b"key1", 100)), LookupResult::NotFound);
+    }
+
+    #[test]
+    fn test_merge_and_range_deletion() {
+        let m = MemTable::with_merge_operator(
+            InternalKeyComparator::new(Arc::new(BytewiseComparator)),
+            Arc::new(StringAppendOperator::new(b'+')));
+        m.add(1, ValueType::Merge, b"key", b"m1");
+        m.add(2, ValueType::Merge, b"key", b"m2");
+        // Operands without a value in the MemTable are returned newest first.
+        assert_eq!(m.get(&LookupKey::new(b"key", 2)),
+            LookupResult::MergeInProgress(vec!(b"m2".to_vec(), b"m1".to_vec())));
+        m.add(3, ValueType::Value, b"key", b"v3");
+        m.add(4, ValueType::Merge, b"key", b"m4");
+        assert_eq!(m.get(&LookupKey::new(b"key", 4)),
+            LookupResult::Found(b"v3+m4".to_vec()));
+        m.add(5, ValueType::Value, b"a", b"a5");
+        m.add(6, ValueType::RangeDeletion, b"a", b"key");
+        m.add(7, ValueType::RangeDeletion, b"key", b"kez");
+        m.add(8, ValueType::Merge, b"key", b"m8");
+        // End of the range is exclusive.
+        assert_eq!(m.get(&LookupKey::new(b"a", 100)), LookupResult::Deleted);
+        assert_eq!(m.get(&LookupKey::new(b"a", 5)), LookupResult::Found(b"a5".to_vec()));
+        assert_eq!(m.get(&LookupKey::new(b"key", 6)),
+            LookupResult::Found(b"v3+m4".to_vec()));
+        assert_eq!(m.get(&LookupKey::new(b"key", 7)), LookupResult::Deleted);
+        assert_eq!(m.get(&LookupKey::new(b"key", 8)), LookupResult::Found(b"m8".to_vec()));
+        assert_eq!(m.get(&LookupKey::new(b"kez", 100)), LookupResult::NotFound);
+        // Range deletions are not part of the point entries.
+        let mut iter = m.iter();
+        iter.seek_to_first();
+        assert_eq!(parse_internal_key(iter.key()).unwrap().user_key, b"a");
+        assert_eq!(parse_internal_key(iter.key()).unwrap().value_type, ValueType::Value);
+    }
+
+    #[test]
+    fn test_range_deletions_added_after_lookup() {
+        let m = MemTable::default();
+        m.add(1, ValueType::Value, b"b", b"b1");
+        m.add(2, ValueType::Value, b"d", b"d2");
+        m.add(3, ValueType::RangeDeletion, b"a", b"c");
+        assert_eq!(m.get(&LookupKey::new(b"b", 100)), LookupResult::Deleted);
+        assert_eq!(m.get(&LookupKey::new(b"d", 100)), LookupResult::Found(b"d2".to_vec()));
+        // Lookups see the range deletions added after the previous ones.
+        m.add(4, ValueType::RangeDeletion, b"b", b"e");
+        assert_eq!(m.get(&LookupKey::new(b"d", 100)), LookupResult::Deleted);
+        assert_eq!(m.max_covering_tombstone_sequence(b"b", 100), Some(4));
+        assert_eq!(m.max_covering_tombstone_sequence(b"b", 3), Some(3));
+        assert_eq!(m.max_covering_tombstone_sequence(b"e", 100), None);
+    }
+
+    #[test]
+    fn test_iterator_order() {
+        let m = MemTable::default();
+        m.add(1, ValueType::Value, b"b", b"b1");
+        m.add(2, ValueType::Value, b"a", b"a2");
+        m.add(3, ValueType::Deletion, b"b", b"");
+        m.add(4, ValueType::Value, b"a", b"a4");
+        // User keys ascending, sequence numbers descending.
+        let expected = [(&b"a"[..], 4, ValueType::Value, &b"a4"[..]),
+            (b"a", 2, ValueType::Value, b"a2"),
+            (b"b", 3, ValueType::Deletion, b""),
+            (b"b", 1, ValueType::Value, b"b1")];
+        let mut iter = m.iter();
+        for (user_key, sequence, value_type, value) in expected {
+            assert!(iter.valid());
+            let parsed = parse_internal_key(iter.key()).unwrap();
+            assert_eq!(parsed.user_key, user_key);
+            assert_eq!(parsed.sequence, sequence);
+            assert_eq!(parsed.value_type, value_type);
+            assert_eq!(iter.value(), value);
+            iter.next();
+        }
+        assert!(!iter.valid());
+        // Seek lands on the newest entry visible at the sequence number.
+        iter.seek(LookupKey::new(b"a", 3).internal_key());
+        assert_eq!(iter.value(), b"a2");
+        iter.seek(LookupKey::new(b"ab", 100).internal_key());
+        assert_eq!(parse_internal_key(iter.key()).unwrap().sequence, 3);
+    }
+
+    #[test]
+    fn test_approximate_memory_usage() {
+        let m = MemTable::default();
+        let initial = m.approximate_memory_usage();
+        let value = [b'v'; 100];
+        for i in 0..1000 {
+            m.add(i + 1, ValueType::Value, format!("key{:05}", i).as_bytes(),
+                &value);
+        }
+        let used = m.approximate_memory_usage() - initial;
+        // Every entry needs at least its key, tag and value bytes.
+        assert!(used >= 1000 * (8 + 8 + 100));
+        assert_eq!(m.get(&LookupKey::new(b"key00999", 2000)),
+            LookupResult::Found(value.to_vec()));
+    }
+
+    #[test]
+    fn test_reverse_iteration() {
+        let m = MemTable::default();
+        for i in 0..100u64 {
+            m.add(i + 1, ValueType::Value, format!("key{:03}", i).as_bytes(),
+                format!("value{}", i).as_bytes());
+        }
+        let mut iter = m.iter();
+        iter.seek_to_last();
+        for i in (0..100).rev() {
+            assert!(iter.valid());
+            assert_eq!(iter.value(), format!("value{}", i).as_bytes());
+            iter.prev();
+        }
+        assert!(!iter.valid());
+        // Latest 5 entries before key050. Versions of key050 sort after
+        // the lookup key with the maximum sequence number.
+        iter.seek_for_prev(
+            LookupKey::new(b"key050", MAX_SEQUENCE_NUMBER).internal_key());
+        let mut latest = vec!();
+        while iter.valid() && latest.len() < 5 {
+            latest.push(parse_internal_key(iter.key()).unwrap().user_key.to_vec());
+            iter.prev();
+        }
+        assert_eq!(latest, vec!(b"key049".to_vec(), b"key048".to_vec(),
+            b"key047".to_vec(), b"key046".to_vec(), b"key045".to_vec()));
+    }
+
+    /// Orders ASCII keys ignoring case.
+    struct CaseInsensitiveComparator;
+
+    impl Comparator for CaseInsensitiveComparator {
+        fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+            a.to_ascii_lowercase().cmp(&b.to_ascii_lowercase())
+        }
+
+        fn name(&self) -> &str {
+            "test.CaseInsensitiveComparator"
+        }
+
+        fn find_shortest_separator(&self, _start: &mut Vec<u8>, _limit: &[u8]) {}
+
+        fn find_short_successor(&self, _key: &mut Vec<u8>) {}
+    }
+
+    #[test]
+    fn test_case_insensitive_comparator() {
+        let m = MemTable::new(InternalKeyComparator::new(
+            Arc::new(CaseInsensitiveComparator)));
+        m.add(1, ValueType::Value, b"Name", b"value1");
+        m.add(2, ValueType::Value, b"NAME", b"value2");
+        // Both writes are versions of the same user key.
+        assert_eq!(m.get(&LookupKey::new(b"name", 1)),
+            LookupResult::Found(b"value1".to_vec()));
+        assert_eq!(m.get(&LookupKey::new(b"nAmE", 2)),
+            LookupResult::Found(b"value2".to_vec()));
+        m.add(3, ValueType::Deletion, b"name", b"");
+        assert_eq!(m.get(&LookupKey::new(b"NaMe", 3)), LookupResult::Deleted);
+    }
+
+    #[test]
+    fn test_reverse_comparator() {
+        let m = MemTable::new(InternalKeyComparator::new(
+            Arc::new(ReverseComparator)));
+        // Reverse ordered timestamps make the latest event sort first.
+        for timestamp in 0..10u64 {
+            m.add(timestamp + 1, ValueType::Value, &timestamp.to_be_bytes(),
+                b"event");
+        }
+        let mut iter = m.iter();
+        for timestamp in (0..10u64).rev() {
+            assert!(iter.valid());
+            assert_eq!(parse_internal_key(iter.key()).unwrap().user_key,
+                &timestamp.to_be_bytes());
+            iter.next();
+        }
+        assert!(!iter.valid());
+    }
+
+    #[test]
+    fn test_internal_filter_policy() {
+        let policy = InternalFilterPolicy::new(Arc::new(BloomFilterPolicy::new(10)));
+        assert_eq!(policy.name(), BloomFilterPolicy::new(10).name());
+        let keys = [InternalKey::new(b"foo", 1, ValueType::Value),
+            InternalKey::new(b"bar", 2, ValueType::Deletion)];
+        let filter = policy.create_filter(&keys.iter().map(|key| key.encode())
+            .collect::<Vec<_>>());
+        // Every version of the user keys matches.
+        for (user_key, sequence) in [(&b"foo"[..], 100), (b"foo", 1), (b"bar", 7)] {
+            let key = LookupKey::new(user_key, sequence);
+            assert!(policy.key_may_match(key.internal_key(), &filter));
+        }
+        assert!(!policy.key_may_match(LookupKey::new(b"baz", 1).internal_key(), &filter));
+    }
+
+    #[test]
+    fn test_internal_key_separator() {
+        let c = InternalKeyComparator::new(Arc::new(BytewiseComparator));
+        let ikey = |user_key: &[u8], sequence| {
+            InternalKey::new(user_key, sequence, ValueType::Value)
+                .encode().to_vec()
+        };
+        // Shortened user key gets the earliest possible tag.
+        let mut start = ikey(b"foo", 100);
+        c.find_shortest_separator(&mut start, &ikey(b"hello", 200));
+        let earliest = InternalKey::new(b"g", MAX_SEQUENCE_NUMBER, VALUE_TYPE_FOR_SEEK);
+        assert_eq!(start, earliest.encode());
+        // Same user keys are not shortened.
+        let mut start = ikey(b"foo", 100);
+        c.find_shortest_separator(&mut start, &ikey(b"foo", 99));
+        assert_eq!(start, ikey(b"foo", 100));
+        // User key is a prefix of the limit.
+        let mut start = ikey(b"foo", 100);
+        c.find_shortest_separator(&mut start, &ikey(b"foobar", 200));
+        assert_eq!(start, ikey(b"foo", 100));
+        let mut key = ikey(b"foo", 100);
+        c.find_short_successor(&mut key);
+        assert_eq!(key, earliest.encode());
+    }
+}
@@ -0,0 +1,61 @@
+/// Combines the merge operands written for a key with its existing value,
+/// so read-modify-write updates can be recorded without reading the value.
+/// Must be thread safe as it is shared by readers.
+pub trait MergeOperator: Send + Sync {
+    /// Returns the value of 'key' after applying 'operands', ordered
+    /// oldest first, to 'existing_value'. 'existing_value' is None if the
+    /// key has no value or was deleted.
+    fn full_merge(&self, key: &[u8], existing_value: Option<&[u8]>,
+        operands: &[&[u8]]) -> Vec<u8>;
+
+    /// Name of the operator. Data written with merge operands must be read
+    /// with an operator of the same name: a DB records it and fails to open
+    /// with another one.
+    fn name(&self) -> &str;
+}
+
+/// Appends the operands to the existing value, separated by a delimiter.
+pub struct StringAppendOperator {
+    delimiter_: u8,
+}
+
+impl StringAppendOperator {
+    pub fn new(delimiter: u8) -> StringAppendOperator {
+        StringAppendOperator { delimiter_: delimiter }
+    }
+}
+
+impl MergeOperator for StringAppendOperator {
+    fn full_merge(&self, _key: &[u8], existing_value: Option<&[u8]>,
+        operands: &[&[u8]]) -> Vec<u8> {
+        let mut result = existing_value.map(|value| value.to_vec());
+        for operand in operands {
+            match result.as_mut() {
+                Some(value) => {
+                    value.push(self.delimiter_);
+                    value.extend_from_slice(operand);
+                }
+                None => result = Some(operand.to_vec()),
+            }
+        }
+        return result.unwrap_or_default();
+    }
+
+    fn name(&self) -> &str {
+        "lsm.StringAppendOperator"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::merge_operator::{MergeOperator, StringAppendOperator};
+
+    #[test]
+    fn test_string_append() {
+        let op = StringAppendOperator::new(b',');
+        assert_eq!(op.full_merge(b"k", Some(b"a"), &[b"b", b"c"]), b"a,b,c");
+        assert_eq!(op.full_merge(b"k", None, &[b"b", b"c"]), b"b,c");
+        assert_eq!(op.full_merge(b"k", Some(b""), &[b"b"]), b",b");
+        assert_eq!(op.full_merge(b"k", Some(b"a"), &[]), b"a");
+    }
+}
@@ -1,18 +1,30 @@
-use leb128;
+use std::sync::Arc;
+use crate::coding::varint_length;
+use crate::comparator::Comparator;
 use crate::sst::lsm_error::DataStoreError;
+use crate::sst::format::block_trailer;
+use crate::sst::options::{ChecksumType, CompressionType, TableOptions};
 use crate::filesystem::Path;
-/// Block Size defined for 128 KB.
-/// TODO: need to make it configurable 
-/// and experiment to set optimal size
-static BLOCK_SIZE: usize = 128 * 1024;
 /// Block has 'data_' which is contigous block
 /// of bytes consisting of contiguous entry.
+/// Keys are prefix compressed: an entry only stores the part of its key
+/// that differs from the key of the previous entry. Every
+/// 'restart_interval' entries the full key is stored again, which is
+/// called a restart point.
 /// Format of an entry is concatenation of:
-///  key_size     : leb128 encoding of key size
-///  value_size   : leb128 encoding of value size
-///  key bytes    : char[key_size]
-///  value bytes  : char[value_size]
-struct Block {
+///  shared_size     : leb128 encoding of bytes shared with previous key
+///  non_shared_size : leb128 encoding of remaining key size
+///  value_size      : leb128 encoding of value size
+///  key delta       : char[non_shared_size]
+///  value bytes     : char[value_size]
+/// The entries are followed by the trailer:
+///  restarts        : fixed32[num_restarts] offsets of restart points
+///  num_restarts    : fixed32
+/// Entries must be added in increasing key order as per the comparator.
+/// An entry that would grow the finished block beyond the configured
+/// block size is rejected with DataStoreError::BlockFull, unless the block
+/// is empty, so a single larger entry still gets a block of its own.
+pub struct Block {
     /// Block data. Check the comment at struct Block
     /// level for the detail about format. It would be
     /// written to the Disk in the same format.
@@ -20,71 +32,184 @@ struct Block {
     /// Length of data written to 'data_'.
     pub current_pos_: usize,
     /// Whether Block is closed or not.
-    pub finished_: bool
+    pub finished_: bool,
+    /// Orders the keys added to the block.
+    comparator_: Arc<dyn Comparator>,
+    /// Size the finished block should not exceed.
+    block_size_: usize,
+    /// Number of entries between restart points.
+    restart_interval_: usize,
+    /// Offsets of restart points.
+    restarts_: Vec<u32>,
+    /// Number of entries added since the last restart point.
+    counter_: usize,
+    /// Last key added to the block.
+    last_key_: Vec<u8>,
+    /// Number of entries added to the block.
+    num_entries_: usize
 }
 
 impl Block {
-    fn new() -> Block {
+    pub fn new() -> Block {
+        Self::with_options(&TableOptions::default())
+    }
+
+    /// Creates a block using the comparator, block size and restart
+    /// interval of 'options'. 'data_' grows as entries are added.
+    pub fn with_options(options: &TableOptions) -> Block {
+        assert!(options.block_restart_interval >= 1,
+            "Restart interval must be positive");
         Block {
-            data_ : vec![0; BLOCK_SIZE],
+            data_ : vec!(),
             current_pos_ : 0,
-            finished_ : false
+            finished_ : false,
+            comparator_: options.comparator.clone(),
+            block_size_: options.block_size,
+            restart_interval_: options.block_restart_interval,
+            // First entry is always a restart point.
+            restarts_: vec![0],
+            counter_: 0,
+            last_key_: vec!(),
+            num_entries_: 0
+        }
+    }
+
+    #[inline(always)]
+    pub fn num_entries(&self) -> usize {
+        self.num_entries_
+    }
+
+    #[inline(always)]
+    pub fn block_size(&self) -> usize {
+        self.block_size_
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.num_entries_ == 0
+    }
+
+    /// Size of the block if it was finished now.
+    pub fn current_size_estimate(&self) -> usize {
+        self.current_pos_ + (self.restarts_.len() + 1) * 4
+    }
+
+    /// Size of the block if it was finished after adding 'key' and 'value'.
+    pub fn estimated_size_after(&self, key: &[u8], value: &[u8]) -> usize {
+        let (shared, restart) = self.shared_prefix(key);
+        let non_shared = key.len() - shared;
+        let entry_size = varint_length(shared) + varint_length(non_shared)
+            + varint_length(value.len()) + non_shared + value.len();
+        let num_restarts = self.restarts_.len() + usize::from(restart);
+        return self.current_pos_ + entry_size + (num_restarts + 1) * 4;
+    }
+
+    /// Returns the number of bytes 'key' can share with the previous key,
+    /// and whether 'key' has to start a new restart point.
+    fn shared_prefix(&self, key: &[u8]) -> (usize, bool) {
+        if self.counter_ >= self.restart_interval_ {
+            return (0, true);
         }
+        let shared = self.last_key_.iter().zip(key.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        return (shared, false);
     }
 
-    fn add(&mut self, key: &[u8], value: &[u8])
+    /// Adds an entry to the block. Fails with DataStoreError::BlockFull
+    /// if the entry does not fit, in which case the block is unchanged.
+    pub fn add(&mut self, key: &[u8], value: &[u8])
         -> Result<(), DataStoreError> {
         debug_assert!(!self.finished_, "Block is already closed");
-        let key_len = key.len();
+        debug_assert!(self.num_entries_ == 0
+            || self.comparator_.compare(&self.last_key_, key)
+                == std::cmp::Ordering::Less,
+            "Keys must be added in increasing order");
+        let required = self.estimated_size_after(key, value);
+        if required > self.block_size_ && !self.is_empty() {
+            return Err(DataStoreError::BlockFull {
+                block_size: self.block_size_,
+                required,
+            });
+        }
+        let (shared, restart) = self.shared_prefix(key);
+        if restart {
+            // Restart compression with the full key.
+            self.restarts_.push(self.current_pos_.try_into().unwrap());
+            self.counter_ = 0;
+        }
+        let non_shared = key.len() - shared;
         let value_len = value.len();
-        let mut writable = &mut self.data_[self.current_pos_..];
-        let key_len_bytes =
-            leb128::write::unsigned(&mut writable,
-                key_len.try_into().unwrap())
-            .unwrap();
-        self.current_pos_ += key_len_bytes;
-        writable = &mut self.data_[self.current_pos_..];
-        let value_len_bytes =
-            leb128::write::unsigned(&mut writable,
-                value_len.try_into().unwrap())
-            .unwrap();
-        self.current_pos_ += value_len_bytes;
-        self.data_[self.current_pos_..self.current_pos_ + key_len]
-            .copy_from_slice(key);
-        self.current_pos_ += key_len;
-        self.data_[self.current_pos_..self.current_pos_ + value_len]
-            .copy_from_slice(value);
+        for len in [shared, non_shared, value_len] {
+            self.current_pos_ += leb128::write::unsigned(&mut self.data_,
+                len.try_into().unwrap())
+                .unwrap();
+        }
+        self.data_.extend_from_slice(&key[shared..]);
+        self.current_pos_ += non_shared;
+        self.data_.extend_from_slice(value);
         self.current_pos_ += value_len;
+        self.last_key_.truncate(shared);
+        self.last_key_.extend_from_slice(&key[shared..]);
+        self.counter_ += 1;
+        self.num_entries_ += 1;
         return Ok(());
     }
 
-    fn finish(&mut self) -> &[u8] {
+    /// Appends the restart points and closes the block.
+    pub fn finish(&mut self) -> &[u8] {
+        debug_assert!(!self.finished_, "Block is already closed");
+        let num_restarts: u32 = self.restarts_.len().try_into().unwrap();
+        for offset in self.restarts_.iter().chain(std::iter::once(&num_restarts)) {
+            self.data_.extend_from_slice(&offset.to_le_bytes());
+            self.current_pos_ += 4;
+        }
         self.finished_ = true;
         return &self.data_[0..self.current_pos_];
     }
 
-    fn write_to_sst(&self, path: &dyn Path) -> Result<(), DataStoreError> {
+    /// Appends the block followed by its trailer, holding the 'checksum'
+    /// of the block, to 'path'. Check 'sst::format' for the trailer.
+    pub fn write_to_sst(&self, path: &dyn Path, checksum: ChecksumType)
+        -> Result<(), DataStoreError> {
         assert!(self.finished_, "Write cannot be called
             before closing the block");
         assert!(self.current_pos_ > 0, "Empty block cannot be written");
-        path.get_file_system().append(path, &self.data_[0..self.current_pos_])?;
+        let contents = &self.data_[0..self.current_pos_];
+        let trailer = block_trailer(contents, CompressionType::NoCompression, checksum);
+        path.get_file_system().append(path, &[contents, &trailer].concat())?;
         return Ok(());
     }
 }
 
+impl Default for Block {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::sst::block::Block;
+    use crate::sst::block_reader::BlockReader;
+    use crate::sst::lsm_error::DataStoreError;
+    use crate::sst::format::{block_trailer, read_block, BlockHandle};
+    use crate::sst::options::{ChecksumType, CompressionType, TableOptions,
+        DEFAULT_BLOCK_SIZE};
     use tempfile::TempDir;
     use crate::filesystem::{Path, LocalPath};
+    use crate::comparator::{BytewiseComparator, ReverseComparator};
+    use std::sync::Arc;
     #[test]
     fn insert_one_key_value() {
         let mut b = Block::new();
         assert!(b.add(b"key", b"value").is_ok());
         b.finish();
-        assert_eq!(b.current_pos_, 10);
-        let expected_bytes =  [&[0x03 as u8, 0x05 as u8] as &[u8],
-            b"keyvalue"].concat();
+        assert_eq!(b.current_pos_, 19);
+        let expected_bytes =  [&[0x00_u8, 0x03_u8, 0x05_u8] as &[u8],
+            b"keyvalue",
+            &[0x00_u8, 0x00, 0x00, 0x00] /*restart offset*/,
+            &[0x01_u8, 0x00, 0x00, 0x00] /*num restarts*/].concat();
         let actual_bytes: &[u8] = &(b.data_)[0..b.current_pos_];
         assert_eq!(expected_bytes, actual_bytes);
     }
@@ -99,21 +224,26 @@ mod tests {
         let value = test_str_129.as_bytes(); 
         assert!(b.add(b"key", value).is_ok());
         let mut expected_len = 3 /*key chars*/ + 129 /*value chars*/
-            + 1 /*keylen in leb128*/ + 2 /*valuelen in leb128*/;
+            + 1 /*shared len*/ + 1 /*keylen in leb128*/
+            + 2 /*valuelen in leb128*/;
         assert_eq!(b.current_pos_, expected_len);
-        let mut expected_bytes = [&[0x03 as u8, 0x81 as u8, 0x01 as u8],
-            b"key", value].concat();
+        let mut expected_bytes = [&[0x00_u8, 0x03_u8, 0x81_u8, 0x01_u8]
+            as &[u8], b"key", value].concat();
         let mut actual_bytes = &(b.data_)[0..b.current_pos_];
         assert_eq!(expected_bytes, actual_bytes);
-        // insert 129 characters key now.
-        let key = test_str_129.as_bytes();
+        // insert 129 characters key now. Keys must be added in
+        // increasing order, so it needs to sort after "key".
+        let key_str_129 = ["k"; 129].join("");
+        let key = key_str_129.as_bytes();
         assert!(b.add(key, value).is_ok());
-        expected_len += 129 /*key chars*/ + 129 /*value chars*/
-        + 2 + 2 /*both valuelen and keylen in leb128*/;
+        // "k" is shared with the previous key, so only 128 key
+        // characters are written.
+        expected_len += 128 /*key chars*/ + 129 /*value chars*/
+        + 1 /*shared len*/ + 2 + 2 /*both valuelen and keylen in leb128*/;
         assert_eq!(b.current_pos_, expected_len);
         expected_bytes = [&expected_bytes[0..],
-        &[0x81 as u8, 0x01 as u8, 0x81 as u8, 0x01 as u8],
-        key, value].concat();
+        &[0x01_u8, 0x80_u8, 0x01_u8, 0x81_u8, 0x01_u8],
+        &key[1..], value].concat();
         actual_bytes = &(b.data_)[0..b.current_pos_];
         assert_eq!(expected_bytes, actual_bytes);
     }
@@ -129,15 +259,163 @@ mod tests {
         let local_path = LocalPath::from_std_path(file_path.as_path());
         let filesystem = local_path.get_file_system();
         assert!(filesystem.create(&local_path).is_ok());
-        assert!(b.write_to_sst(&local_path).is_ok());
-        let mut buf: [u8; 20] = [0; 20];
-        let expected_bytes =  [&[0x03 as u8, 0x05 as u8] as &[u8],
-            b"keyvalue"].concat();
+        assert!(b.write_to_sst(&local_path, ChecksumType::Crc32c).is_ok());
+        let mut buf: [u8; 32] = [0; 32];
+        let contents =  [&[0x00_u8, 0x03_u8, 0x05_u8] as &[u8],
+            b"keyvalue", &[0x00_u8, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00]]
+            .concat();
+        let trailer = block_trailer(&contents, CompressionType::NoCompression,
+            ChecksumType::Crc32c);
+        let expected_bytes = [&contents[..], &trailer].concat();
         let expected_len = expected_bytes.len();
         let actual_len = filesystem.read(&local_path, &mut buf).unwrap();
         assert_eq!(actual_len, expected_len,
             "Expected len is {}, but actual len got is {}", actual_len, expected_len);
         assert_eq!(buf[0..expected_len], expected_bytes,
             "Bytes written to SST file differs than expected");
+        let handle = BlockHandle::new(0, contents.len() as u64);
+        assert_eq!(read_block(&local_path, &handle, expected_len as u64, ChecksumType::Crc32c,
+            true).unwrap(), contents);
+    }
+
+    #[test]
+    fn insert_with_comparator() {
+        let mut b = Block::with_options(&TableOptions {
+            comparator: Arc::new(ReverseComparator),
+            ..TableOptions::default()
+        });
+        assert!(b.add(b"key2", b"value2").is_ok());
+        assert!(b.add(b"key1", b"value1").is_ok());
+        assert_eq!(b.current_pos_, (3 + 4 + 6) + (3 + 1 + 6));
+    }
+
+    #[test]
+    fn insert_with_restart_points() {
+        let mut b = Block::with_options(&TableOptions {
+            block_restart_interval: 2,
+            ..TableOptions::default()
+        });
+        let mut restarts = vec!();
+        for i in 0..5 {
+            if i % 2 == 0 {
+                restarts.push(b.current_pos_ as u32);
+            }
+            assert!(b.add(format!("prefix{}", i).as_bytes(), b"v").is_ok());
+        }
+        // Restart points store the full key, others only the last byte.
+        assert_eq!(b.current_pos_, 3 * (3 + 7 + 1) + 2 * (3 + 1 + 1));
+        let entries_len = b.current_pos_;
+        let data = b.finish().to_vec();
+        let mut expected_trailer = vec!();
+        for offset in restarts.iter().chain([3u32].iter()) {
+            expected_trailer.extend_from_slice(&offset.to_le_bytes());
+        }
+        assert_eq!(&data[entries_len..], &expected_trailer[..]);
+        assert_eq!(&data[0..3], &[0x00, 0x07, 0x01]);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Keys must be added in increasing order")]
+    fn insert_out_of_order() {
+        let mut b = Block::new();
+        assert!(b.add(b"key2", b"value2").is_ok());
+        let _ = b.add(b"key1", b"value1");
+    }
+
+    #[test]
+    fn estimated_size_matches_finished_size() {
+        let mut b = Block::with_options(&TableOptions {
+            block_restart_interval: 3,
+            block_size: 64 * 1024,
+            ..TableOptions::default()
+        });
+        assert_eq!(b.current_size_estimate(), 8);
+        for i in 0..20 {
+            let key = format!("key{:03}", i);
+            let value = ["v"; 200].join("");
+            let expected = b.estimated_size_after(key.as_bytes(), value.as_bytes());
+            assert!(b.add(key.as_bytes(), value.as_bytes()).is_ok());
+            assert_eq!(b.current_size_estimate(), expected);
+        }
+        let expected = b.current_size_estimate();
+        assert_eq!(b.finish().len(), expected);
+    }
+
+    #[test]
+    fn add_to_full_block() {
+        let mut b = Block::new();
+        assert_eq!(b.block_size(), DEFAULT_BLOCK_SIZE);
+        let value = ["v"; 1000].join("");
+        let mut i = 0;
+        loop {
+            let key = format!("key{:06}", i);
+            match b.add(key.as_bytes(), value.as_bytes()) {
+                Ok(()) => i += 1,
+                Err(DataStoreError::BlockFull { block_size, required }) => {
+                    assert_eq!(block_size, DEFAULT_BLOCK_SIZE);
+                    assert!(required > DEFAULT_BLOCK_SIZE);
+                    assert_eq!(required, b.estimated_size_after(key.as_bytes(),
+                        value.as_bytes()));
+                    break;
+                }
+                Err(e) => panic!("Unexpected error {}", e),
+            }
+        }
+        assert_eq!(b.num_entries(), i);
+        assert!(b.current_size_estimate() <= DEFAULT_BLOCK_SIZE);
+        // Rejected entry left the block untouched.
+        let size = b.current_size_estimate();
+        let data = b.finish().to_vec();
+        assert_eq!(data.len(), size);
+        let reader = BlockReader::new(data, Arc::new(BytewiseComparator)).unwrap();
+        let mut iter = reader.iter();
+        assert!(iter.seek_to_first().is_ok());
+        for j in 0..i {
+            assert_eq!(iter.key(), format!("key{:06}", j).as_bytes());
+            assert!(iter.next().is_ok());
+        }
+        assert!(!iter.valid());
+    }
+
+    #[test]
+    fn add_oversized_entry() {
+        let value = vec![b'v'; 2 * DEFAULT_BLOCK_SIZE];
+        let mut b = Block::new();
+        // Oversized entry is accepted by an empty block.
+        assert!(b.add(b"key1", &value).is_ok());
+        assert!(b.current_size_estimate() > DEFAULT_BLOCK_SIZE);
+        // But nothing fits after it.
+        assert!(matches!(b.add(b"key2", b"value"),
+            Err(DataStoreError::BlockFull { .. })));
+        let data = b.finish().to_vec();
+        let reader = BlockReader::new(data, Arc::new(BytewiseComparator)).unwrap();
+        let mut iter = reader.iter();
+        assert!(iter.seek(b"key1").is_ok());
+        assert_eq!(iter.value(), &value[..]);
+        // Entry too big for a block is rejected by a non empty block.
+        let mut b = Block::new();
+        assert!(b.add(b"key1", b"value").is_ok());
+        assert!(matches!(b.add(b"key2", &value),
+            Err(DataStoreError::BlockFull { .. })));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn configured_block_size() {
+        let options = TableOptions {
+            block_size: 64 * 1024,
+            ..TableOptions::default()
+        };
+        let mut b = Block::with_options(&options);
+        // Nothing is allocated up front.
+        assert_eq!(b.data_.capacity(), 0);
+        let value = ["v"; 1000].join("");
+        let mut i = 0;
+        while b.add(format!("key{:06}", i).as_bytes(), value.as_bytes()).is_ok() {
+            i += 1;
+        }
+        assert!(b.current_size_estimate() > DEFAULT_BLOCK_SIZE);
+        assert!(b.current_size_estimate() <= 64 * 1024);
+        assert_eq!(b.num_entries(), i);
+    }
+}
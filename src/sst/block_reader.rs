@@ -0,0 +1,424 @@
+use std::cmp::Ordering;
+use std::sync::Arc;
+use crate::coding::get_varint;
+use crate::comparator::Comparator;
+use crate::filesystem::Path;
+use crate::sst::format::{read_block, BlockHandle};
+use crate::sst::lsm_error::DataStoreError;
+use crate::sst::options::ChecksumType;
+
+/// Size of the encoding of a restart point and of their count.
+const U32_SIZE: usize = std::mem::size_of::<u32>();
+
+#[inline(always)]
+fn decode_fixed32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + U32_SIZE].try_into().unwrap())
+}
+
+/// Read side of a Block. Holds the bytes of a block written by
+/// 'Block::finish' and hands out iterators over its entries.
+/// Check the comment at 'sst::block::Block' for the format.
+pub struct BlockReader {
+    data_: Arc<Vec<u8>>,
+    comparator_: Arc<dyn Comparator>,
+    /// Offset of the restart array, which is also the end of entries.
+    restarts_offset_: usize,
+    num_restarts_: usize,
+}
+
+impl BlockReader {
+    /// Validates the restart trailer of 'data'.
+    pub fn new(data: Vec<u8>, comparator: Arc<dyn Comparator>)
+        -> Result<BlockReader, DataStoreError> {
+        if data.len() < U32_SIZE {
+            return Err(DataStoreError::MalformedBlock {
+                offset: 0,
+                reason: "block is too small for the restart trailer".to_string(),
+            });
+        }
+        let num_restarts = decode_fixed32(&data, data.len() - U32_SIZE) as usize;
+        let max_restarts = (data.len() - U32_SIZE) / U32_SIZE;
+        if num_restarts == 0 || num_restarts > max_restarts {
+            return Err(DataStoreError::MalformedBlock {
+                offset: data.len() - U32_SIZE,
+                reason: format!("bad number of restart points {}", num_restarts),
+            });
+        }
+        let restarts_offset = data.len() - (1 + num_restarts) * U32_SIZE;
+        return Ok(BlockReader {
+            data_: Arc::new(data),
+            comparator_: comparator,
+            restarts_offset_: restarts_offset,
+            num_restarts_: num_restarts,
+        });
+    }
+
+    /// Reads the block at 'handle' in 'path', a file of 'file_size' bytes,
+    /// verifying its checksum if 'verify_checksums' is set. Check
+    /// 'sst::format::read_block'.
+    pub fn read_from(path: &dyn Path, handle: &BlockHandle, file_size: u64,
+        checksum: ChecksumType, verify_checksums: bool, comparator: Arc<dyn Comparator>)
+        -> Result<BlockReader, DataStoreError> {
+        let data = read_block(path, handle, file_size, checksum, verify_checksums)?;
+        return Self::new(data, comparator);
+    }
+
+    #[inline(always)]
+    pub fn size(&self) -> usize {
+        self.data_.len()
+    }
+
+    pub fn iter(&self) -> BlockIter {
+        BlockIter {
+            data_: self.data_.clone(),
+            comparator_: self.comparator_.clone(),
+            restarts_offset_: self.restarts_offset_,
+            num_restarts_: self.num_restarts_,
+            current_: self.restarts_offset_,
+            next_: 0,
+            key_: vec!(),
+            value_: (0, 0),
+        }
+    }
+}
+
+/// Iterates over the entries of a block in the order they were added.
+/// Movement fails with DataStoreError::MalformedBlock if an entry cannot
+/// be decoded, after which the iterator is no longer valid.
+pub struct BlockIter {
+    data_: Arc<Vec<u8>>,
+    comparator_: Arc<dyn Comparator>,
+    /// End of the entries. Check BlockReader.
+    restarts_offset_: usize,
+    num_restarts_: usize,
+    /// Offset of the current entry. Equal to 'restarts_offset_' when
+    /// invalid.
+    current_: usize,
+    /// Offset of the entry following the current one.
+    next_: usize,
+    /// Current key. Keys are prefix compressed, so it is rebuilt from
+    /// the previous key while moving forward.
+    key_: Vec<u8>,
+    /// Start and end offsets of the current value.
+    value_: (usize, usize),
+}
+
+impl BlockIter {
+    #[inline(always)]
+    pub fn valid(&self) -> bool {
+        self.current_ < self.restarts_offset_
+    }
+
+    pub fn seek_to_first(&mut self) -> Result<(), DataStoreError> {
+        self.seek_to_restart_point(0);
+        return self.parse_next_entry();
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<(), DataStoreError> {
+        assert!(self.valid());
+        return self.parse_next_entry();
+    }
+
+    /// Positions at the first entry with key at or after 'target'.
+    /// Binary searches the restart points for the last one with a key
+    /// before 'target', and scans forward from there.
+    pub fn seek(&mut self, target: &[u8]) -> Result<(), DataStoreError> {
+        let mut left = 0;
+        let mut right = self.num_restarts_ - 1;
+        while left < right {
+            let mid = (left + right).div_ceil(2);
+            let (key_start, key_end) = self.restart_key(mid)?;
+            if self.comparator_.compare(&self.data_[key_start..key_end], target)
+                == Ordering::Less {
+                // Key at "mid" is smaller than "target". Therefore all
+                // restart points before "mid" are uninteresting.
+                left = mid;
+            } else {
+                // Key at "mid" is >= "target". Therefore all restart
+                // points at or after "mid" are uninteresting.
+                right = mid - 1;
+            }
+        }
+        self.seek_to_restart_point(left);
+        loop {
+            self.parse_next_entry()?;
+            if !self.valid()
+                || self.comparator_.compare(&self.key_, target) != Ordering::Less {
+                return Ok(());
+            }
+        }
+    }
+
+    #[inline(always)]
+    pub fn key(&self) -> &[u8] {
+        assert!(self.valid());
+        &self.key_
+    }
+
+    #[inline(always)]
+    pub fn value(&self) -> &[u8] {
+        assert!(self.valid());
+        &self.data_[self.value_.0..self.value_.1]
+    }
+
+    #[inline(always)]
+    fn restart_point(&self, index: usize) -> usize {
+        decode_fixed32(&self.data_, self.restarts_offset_ + index * U32_SIZE) as usize
+    }
+
+    fn seek_to_restart_point(&mut self, index: usize) {
+        self.key_.clear();
+        self.next_ = self.restart_point(index);
+    }
+
+    /// Start and end offsets of the key stored at restart point 'index'.
+    /// Restart points store their keys in full, so no previous key is
+    /// needed.
+    fn restart_key(&mut self, index: usize) -> Result<(usize, usize), DataStoreError> {
+        let offset = self.restart_point(index);
+        match self.decode_entry(offset) {
+            Some((0, non_shared, _, key_start)) => Ok((key_start, key_start + non_shared)),
+            _ => Err(self.corruption(offset, "bad restart point")),
+        }
+    }
+
+    /// Decodes the header of the entry at 'offset' and returns
+    /// (shared, non_shared, value_size, key delta offset), or None if the
+    /// entry is malformed.
+    fn decode_entry(&self, offset: usize) -> Option<(usize, usize, usize, usize)> {
+        if offset >= self.restarts_offset_ {
+            return None;
+        }
+        let mut input = &self.data_[offset..self.restarts_offset_];
+        let shared = get_varint(&mut input)?;
+        let non_shared = get_varint(&mut input)?;
+        let value_len = get_varint(&mut input)?;
+        if non_shared.saturating_add(value_len) > input.len() as u64 {
+            return None;
+        }
+        let key_start = self.restarts_offset_ - input.len();
+        return Some((shared.try_into().ok()?, non_shared as usize,
+            value_len as usize, key_start));
+    }
+
+    fn parse_next_entry(&mut self) -> Result<(), DataStoreError> {
+        let offset = self.next_;
+        if offset > self.restarts_offset_ {
+            return Err(self.corruption(offset, "bad restart point"));
+        }
+        self.current_ = offset;
+        if offset == self.restarts_offset_ {
+            // No more entries.
+            return Ok(());
+        }
+        let (shared, non_shared, value_len, key_start) = match self.decode_entry(offset) {
+            Some(entry) if entry.0 <= self.key_.len() => entry,
+            _ => return Err(self.corruption(offset, "bad entry")),
+        };
+        self.key_.truncate(shared);
+        self.key_.extend_from_slice(&self.data_[key_start..key_start + non_shared]);
+        let value_start = key_start + non_shared;
+        self.value_ = (value_start, value_start + value_len);
+        self.next_ = self.value_.1;
+        return Ok(());
+    }
+
+    fn corruption(&mut self, offset: usize, reason: &str) -> DataStoreError {
+        self.current_ = self.restarts_offset_;
+        self.next_ = self.restarts_offset_;
+        self.key_.clear();
+        DataStoreError::MalformedBlock {
+            offset,
+            reason: reason.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use tempfile::TempDir;
+    use crate::comparator::BytewiseComparator;
+    use crate::filesystem::{LocalPath, Path};
+    use crate::sst::block::Block;
+    use crate::sst::block_reader::BlockReader;
+    use crate::sst::format::{block_trailer, BlockHandle, BLOCK_TRAILER_SIZE};
+    use crate::sst::lsm_error::DataStoreError;
+    use crate::sst::options::{ChecksumType, CompressionType, TableOptions};
+
+    fn build_block(entries: &[(Vec<u8>, Vec<u8>)], restart_interval: usize) -> Vec<u8> {
+        let mut b = Block::with_options(&TableOptions {
+            block_restart_interval: restart_interval,
+            block_size: usize::MAX,
+            ..TableOptions::default()
+        });
+        for (key, value) in entries {
+            assert!(b.add(key, value).is_ok());
+        }
+        return b.finish().to_vec();
+    }
+
+    fn test_entries() -> Vec<(Vec<u8>, Vec<u8>)> {
+        (0..200).map(|i| (format!("key{:04}", i * 2).into_bytes(),
+            ["v"; 150].join("").into_bytes().into_iter()
+                .chain(i.to_string().into_bytes()).collect()))
+            .collect()
+    }
+
+    /// Trailer of a block with a single restart point at offset 0.
+    const SINGLE_RESTART_TRAILER: [u8; 8] = [0, 0, 0, 0, 1, 0, 0, 0];
+
+    #[test]
+    fn test_empty_block() {
+        let reader = BlockReader::new(build_block(&[], 16),
+            Arc::new(BytewiseComparator)).unwrap();
+        let mut iter = reader.iter();
+        assert!(!iter.valid());
+        assert!(iter.seek_to_first().is_ok());
+        assert!(!iter.valid());
+        assert!(iter.seek(b"key").is_ok());
+        assert!(!iter.valid());
+    }
+
+    #[test]
+    fn test_iterate_entries() {
+        let entries = test_entries();
+        for restart_interval in [1, 2, 16, 1000] {
+            let reader = BlockReader::new(build_block(&entries, restart_interval),
+                Arc::new(BytewiseComparator)).unwrap();
+            let mut iter = reader.iter();
+            assert!(iter.seek_to_first().is_ok());
+            for (key, value) in &entries {
+                assert!(iter.valid());
+                assert_eq!(iter.key(), &key[..]);
+                assert_eq!(iter.value(), &value[..]);
+                assert!(iter.next().is_ok());
+            }
+            assert!(!iter.valid());
+        }
+    }
+
+    #[test]
+    fn test_prefix_compression() {
+        let entries = test_entries();
+        let uncompressed = build_block(&entries, 1);
+        let compressed = build_block(&entries, 16);
+        // Keys share "key0" or "key01" prefixes.
+        assert!(compressed.len() + entries.len() * 3 < uncompressed.len());
+    }
+
+    #[test]
+    fn test_seek() {
+        let entries = test_entries();
+        for restart_interval in [1, 3, 16, 1000] {
+            let reader = BlockReader::new(build_block(&entries, restart_interval),
+                Arc::new(BytewiseComparator)).unwrap();
+            let mut iter = reader.iter();
+            for i in 0..399 {
+                let target = format!("key{:04}", i);
+                assert!(iter.seek(target.as_bytes()).is_ok());
+                // Odd keys are missing and land on the next even key.
+                let expected = format!("key{:04}", (i + 1) / 2 * 2);
+                assert_eq!(iter.key(), expected.as_bytes());
+            }
+            assert!(iter.seek(b"").is_ok());
+            assert_eq!(iter.key(), b"key0000");
+            // Seek beyond the last key
+            assert!(iter.seek(b"key0399").is_ok());
+            assert!(!iter.valid());
+        }
+    }
+
+    #[test]
+    fn test_truncated_entry() {
+        let entry1 = [&[0x00, 0x04, 0x06], &b"key1value1"[..]].concat();
+        let entry2 = [&[0x03, 0x01, 0x06], &b"2value"[..]].concat();
+        let data = [&entry1[..], &entry2, &SINGLE_RESTART_TRAILER].concat();
+        let reader = BlockReader::new(data, Arc::new(BytewiseComparator)).unwrap();
+        let mut iter = reader.iter();
+        assert!(iter.seek_to_first().is_ok());
+        assert_eq!(iter.key(), b"key1");
+        match iter.next() {
+            Err(DataStoreError::MalformedBlock { offset, .. }) => {
+                assert_eq!(offset, entry1.len());
+            }
+            _ => panic!("Expected MalformedBlock error"),
+        }
+        assert!(!iter.valid());
+        // Seeking past the truncated entry fails as well.
+        assert!(iter.seek(b"key2").is_err());
+    }
+
+    #[test]
+    fn test_bad_shared_prefix() {
+        // First entry claims to share bytes with a non-existing key.
+        let data = [&[0x02, 0x02, 0x01], &b"y1v"[..], &SINGLE_RESTART_TRAILER].concat();
+        let reader = BlockReader::new(data, Arc::new(BytewiseComparator)).unwrap();
+        assert!(reader.iter().seek_to_first().is_err());
+        assert!(reader.iter().seek(b"key1").is_err());
+    }
+
+    #[test]
+    fn test_malformed_varint() {
+        // Shared size varint with continuation bit set on every byte.
+        let data = [&[0xff; 12][..], &SINGLE_RESTART_TRAILER].concat();
+        let reader = BlockReader::new(data, Arc::new(BytewiseComparator)).unwrap();
+        let mut iter = reader.iter();
+        assert!(matches!(iter.seek_to_first(),
+            Err(DataStoreError::MalformedBlock { offset: 0, .. })));
+        assert!(!iter.valid());
+        // Huge sizes must not overflow.
+        let mut data = vec![0x00];
+        crate::coding::put_varint(&mut data, u64::MAX);
+        crate::coding::put_varint(&mut data, u64::MAX);
+        data.extend_from_slice(&SINGLE_RESTART_TRAILER);
+        let reader = BlockReader::new(data, Arc::new(BytewiseComparator)).unwrap();
+        assert!(reader.iter().seek_to_first().is_err());
+    }
+
+    #[test]
+    fn test_bad_restart_trailer() {
+        assert!(BlockReader::new(vec![0x01, 0x00],
+            Arc::new(BytewiseComparator)).is_err());
+        // No restart points.
+        assert!(BlockReader::new(vec![0x00; 4],
+            Arc::new(BytewiseComparator)).is_err());
+        // More restart points than fit in the block.
+        assert!(BlockReader::new(vec![0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00],
+            Arc::new(BytewiseComparator)).is_err());
+        // Restart point beyond the entries.
+        let data = [&[0x00, 0x01, 0x01], &b"ab"[..],
+            &[0x40, 0x00, 0x00, 0x00], &[0x01, 0x00, 0x00, 0x00]].concat();
+        let reader = BlockReader::new(data, Arc::new(BytewiseComparator)).unwrap();
+        assert!(reader.iter().seek_to_first().is_err());
+    }
+
+    #[test]
+    fn test_read_from_file() {
+        let first = build_block(&[(b"a".to_vec(), b"1".to_vec())], 16);
+        let second = build_block(&test_entries(), 16);
+        let tmp_dir = TempDir::new().unwrap();
+        let file_path = tmp_dir.path().join("test.sst");
+        let local_path = LocalPath::from_std_path(file_path.as_path());
+        let filesystem = local_path.get_file_system();
+        assert!(filesystem.create(&local_path).is_ok());
+        for block in [&first, &second] {
+            assert!(filesystem.append(&local_path, block).is_ok());
+            assert!(filesystem.append(&local_path, &block_trailer(block,
+                CompressionType::NoCompression, ChecksumType::Crc32c)).is_ok());
+        }
+        let handle = BlockHandle::new((first.len() + BLOCK_TRAILER_SIZE) as u64,
+            second.len() as u64);
+        let file_size = filesystem.file_size(&local_path).unwrap();
+        let reader = BlockReader::read_from(&local_path, &handle, file_size,
+            ChecksumType::Crc32c, true, Arc::new(BytewiseComparator)).unwrap();
+        let mut iter = reader.iter();
+        assert!(iter.seek(b"key0398").is_ok());
+        assert!(iter.value().ends_with(b"199"));
+        // Reading past the end of file is reported.
+        let handle = BlockHandle::new(handle.offset, handle.size + 1);
+        assert!(BlockReader::read_from(&local_path, &handle, file_size,
+            ChecksumType::Crc32c, false, Arc::new(BytewiseComparator)).is_err());
+    }
+}
@@ -0,0 +1,163 @@
+use crate::sst::options::CompressionType;
+
+/// Compressed blocks are only kept if they are at least 12.5% smaller
+/// than the raw block.
+#[inline(always)]
+pub fn good_compression_ratio(compressed_size: usize, raw_size: usize) -> bool {
+    compressed_size < raw_size - raw_size / 8
+}
+
+/// Compresses 'data' with 'compression'. Returns None if the codec fails.
+pub fn compress(compression: CompressionType, data: &[u8]) -> Option<Vec<u8>> {
+    match compression {
+        CompressionType::NoCompression => Some(data.to_vec()),
+        CompressionType::Snappy => snap::raw::Encoder::new().compress_vec(data).ok(),
+        CompressionType::Lz4 => Some(lz4_flex::block::compress_prepend_size(data)),
+        CompressionType::Zstd => zstd::bulk::compress(data, zstd::DEFAULT_COMPRESSION_LEVEL)
+            .ok(),
+    }
+}
+
+/// Returns the compressed form of 'data' and its compression type to be
+/// stored in a table. Falls back to the raw 'data' if the codec fails or
+/// does not save enough space.
+pub fn compress_block(compression: CompressionType, data: &[u8])
+    -> (CompressionType, Vec<u8>) {
+    if compression != CompressionType::NoCompression {
+        if let Some(compressed) = compress(compression, data) {
+            if good_compression_ratio(compressed.len(), data.len()) {
+                return (compression, compressed);
+            }
+        }
+    }
+    return (CompressionType::NoCompression, data.to_vec());
+}
+
+/// Largest size 'compressed_size' bytes compressed with 'compression' can
+/// decompress to, from the best ratio each format can encode. A block
+/// claiming a larger size is corrupt.
+fn max_decompressed_size(compression: CompressionType, compressed_size: usize) -> usize {
+    let ratio = match compression {
+        CompressionType::NoCompression => 1,
+        // A 3 byte copy produces at most 64 bytes.
+        CompressionType::Snappy => 22,
+        // Every extra byte of a match length adds at most 255 bytes.
+        CompressionType::Lz4 => 255,
+        // A 4 byte RLE block produces at most 128KiB.
+        CompressionType::Zstd => 32 * 1024,
+    };
+    return compressed_size.saturating_mul(ratio);
+}
+
+/// Decompresses 'data' compressed with 'compression'. Fails with the
+/// reason reported by the codec. The decompressed size stored in 'data'
+/// is checked before anything is allocated, so a corrupted block does not
+/// lead to a huge allocation.
+pub fn decompress(compression: CompressionType, data: &[u8]) -> Result<Vec<u8>, String> {
+    let check_size = |size: usize| {
+        let max_size = max_decompressed_size(compression, data.len());
+        if size > max_size {
+            return Err(format!("decompressed size {} larger than the maximum {}",
+                size, max_size));
+        }
+        return Ok(());
+    };
+    match compression {
+        CompressionType::NoCompression => Ok(data.to_vec()),
+        CompressionType::Snappy => {
+            check_size(snap::raw::decompress_len(data).map_err(|e| e.to_string())?)?;
+            snap::raw::Decoder::new().decompress_vec(data).map_err(|e| e.to_string())
+        }
+        CompressionType::Lz4 => {
+            let size = data.get(..4).ok_or_else(|| "missing lz4 size".to_string())?;
+            check_size(u32::from_le_bytes(size.try_into().unwrap()) as usize)?;
+            lz4_flex::block::decompress_size_prepended(data).map_err(|e| e.to_string())
+        }
+        CompressionType::Zstd => {
+            let size = zstd::zstd_safe::get_frame_content_size(data)
+                .map_err(|e| e.to_string())?
+                .ok_or_else(|| "unknown zstd content size".to_string())?;
+            let size = usize::try_from(size).map_err(|e| e.to_string())?;
+            check_size(size)?;
+            zstd::bulk::decompress(data, size).map_err(|e| e.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sst::compression::{compress, compress_block, decompress,
+        good_compression_ratio};
+    use crate::sst::options::CompressionType;
+
+    const CODECS: [CompressionType; 3] = [CompressionType::Snappy,
+        CompressionType::Lz4, CompressionType::Zstd];
+
+    fn compressible_data() -> Vec<u8> {
+        (0..200).map(|i| format!("{{\"id\": {}, \"name\": \"user{}\"}}", i, i % 10))
+            .collect::<String>().into_bytes()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data = compressible_data();
+        for codec in CODECS {
+            let compressed = compress(codec, &data).unwrap();
+            assert!(compressed.len() < data.len() / 2, "{:?} did not compress", codec);
+            assert_eq!(decompress(codec, &compressed).unwrap(), data);
+        }
+        assert_eq!(decompress(CompressionType::Zstd,
+            &compress(CompressionType::Zstd, b"").unwrap()).unwrap(), b"");
+    }
+
+    #[test]
+    fn test_incompressible_data_stored_raw() {
+        use rand::RngCore;
+        let mut data = vec![0; 4096];
+        rand::thread_rng().fill_bytes(&mut data);
+        for codec in CODECS {
+            let (compression, stored) = compress_block(codec, &data);
+            assert_eq!(compression, CompressionType::NoCompression);
+            assert_eq!(stored, data);
+        }
+        let data = compressible_data();
+        for codec in CODECS {
+            assert_eq!(compress_block(codec, &data).0, codec);
+        }
+    }
+
+    #[test]
+    fn test_compression_ratio_threshold() {
+        assert!(good_compression_ratio(87, 100));
+        assert!(!good_compression_ratio(88, 100));
+        assert!(!good_compression_ratio(100, 100));
+    }
+
+    #[test]
+    fn test_malformed_input() {
+        let data = compressible_data();
+        for codec in CODECS {
+            let mut compressed = compress(codec, &data).unwrap();
+            compressed.truncate(compressed.len() / 2);
+            assert!(decompress(codec, &compressed).is_err(), "{:?}", codec);
+        }
+    }
+
+    #[test]
+    fn test_corrupted_size() {
+        let data = compressible_data();
+        let mut compressed = compress(CompressionType::Lz4, &data).unwrap();
+        compressed[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(decompress(CompressionType::Lz4, &compressed).is_err());
+        // Replaces the varint length of the Snappy block.
+        let compressed = compress(CompressionType::Snappy, &data).unwrap();
+        let mut corrupted = vec!(0xff, 0xff, 0xff, 0xff, 0x0f);
+        corrupted.extend_from_slice(&compressed[2..]);
+        assert!(decompress(CompressionType::Snappy, &corrupted).is_err());
+        // Highly compressible blocks are within the bounds.
+        let zeros = vec![0; 1024 * 1024];
+        for codec in CODECS {
+            assert_eq!(decompress(codec, &compress(codec, &zeros).unwrap()).unwrap(), zeros);
+        }
+    }
+}
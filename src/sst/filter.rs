@@ -0,0 +1,166 @@
+use crate::checksum::xxhash64;
+
+/// Builds compact summaries of a set of keys, which can answer whether a
+/// key may be in the set without reading the set. Tables store a filter
+/// next to their data so that lookups of missing keys skip data blocks.
+/// The name of the policy is recorded in the table; a table whose filter
+/// was built by a policy with a different name ignores the filter.
+pub trait FilterPolicy: Send + Sync {
+    /// Name of the policy. Changing the encoding of an existing policy
+    /// requires changing its name.
+    fn name(&self) -> &str;
+
+    /// Returns a filter summarizing 'keys'. 'keys' may contain duplicates.
+    fn create_filter(&self, keys: &[&[u8]]) -> Vec<u8>;
+
+    /// Returns false only if 'key' was not among the keys 'filter' was
+    /// created from. May return true for keys that were not.
+    fn key_may_match(&self, key: &[u8], filter: &[u8]) -> bool;
+}
+
+/// Name of BloomFilterPolicy.
+const BLOOM_FILTER_POLICY_NAME: &str = "lsm.BuiltinBloomFilter";
+
+/// Bloom filter with a configurable number of bits per key. Same encoding
+/// as LevelDB: the bit array followed by a byte holding the number of
+/// probes. Probes use double hashing of a single 32 bit hash.
+pub struct BloomFilterPolicy {
+    bits_per_key_: usize,
+    /// Number of probes per key.
+    k_: usize,
+}
+
+impl BloomFilterPolicy {
+    /// 10 bits per key yield a false positive rate of about 1%.
+    pub fn new(bits_per_key: usize) -> BloomFilterPolicy {
+        // Round down to reduce probing cost a little bit.
+        // 0.69 =~ ln(2) minimizes the false positive rate.
+        let k = ((bits_per_key as f64) * 0.69) as usize;
+        BloomFilterPolicy {
+            bits_per_key_: bits_per_key,
+            k_: k.clamp(1, 30),
+        }
+    }
+}
+
+#[inline(always)]
+fn bloom_hash(key: &[u8]) -> u32 {
+    xxhash64(key) as u32
+}
+
+impl FilterPolicy for BloomFilterPolicy {
+    fn name(&self) -> &str {
+        BLOOM_FILTER_POLICY_NAME
+    }
+
+    fn create_filter(&self, keys: &[&[u8]]) -> Vec<u8> {
+        // For small n, we can see a very high false positive rate. Fix it
+        // by enforcing a minimum bloom filter length.
+        let bits = (keys.len() * self.bits_per_key_).max(64);
+        let bytes = bits.div_ceil(8);
+        let bits = bytes * 8;
+        let mut filter = vec![0; bytes + 1];
+        for key in keys {
+            let mut h = bloom_hash(key);
+            let delta = h.rotate_left(15);
+            for _ in 0..self.k_ {
+                let bitpos = (h as usize) % bits;
+                filter[bitpos / 8] |= 1 << (bitpos % 8);
+                h = h.wrapping_add(delta);
+            }
+        }
+        // Remember the number of probes in the filter.
+        filter[bytes] = self.k_ as u8;
+        return filter;
+    }
+
+    fn key_may_match(&self, key: &[u8], filter: &[u8]) -> bool {
+        if filter.len() < 2 {
+            return false;
+        }
+        let bits = (filter.len() - 1) * 8;
+        // Use the encoded k so that filters built with different
+        // parameters can be read.
+        let k = filter[filter.len() - 1];
+        if k > 30 {
+            // Reserved for potentially new encodings of short bloom
+            // filters. Consider it a match.
+            return true;
+        }
+        let mut h = bloom_hash(key);
+        let delta = h.rotate_left(15);
+        for _ in 0..k {
+            let bitpos = (h as usize) % bits;
+            if filter[bitpos / 8] & (1 << (bitpos % 8)) == 0 {
+                return false;
+            }
+            h = h.wrapping_add(delta);
+        }
+        return true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sst::filter::{BloomFilterPolicy, FilterPolicy};
+
+    fn key(i: u32) -> [u8; 4] {
+        i.to_le_bytes()
+    }
+
+    fn build(policy: &BloomFilterPolicy, n: u32) -> Vec<u8> {
+        let keys: Vec<[u8; 4]> = (0..n).map(key).collect();
+        let keys: Vec<&[u8]> = keys.iter().map(|k| &k[..]).collect();
+        return policy.create_filter(&keys);
+    }
+
+    #[test]
+    fn test_empty_filter() {
+        let policy = BloomFilterPolicy::new(10);
+        let filter = policy.create_filter(&[]);
+        assert!(!policy.key_may_match(b"hello", &filter));
+        assert!(!policy.key_may_match(b"world", &filter));
+        assert!(!policy.key_may_match(b"hello", &[]));
+    }
+
+    #[test]
+    fn test_small_filter() {
+        let policy = BloomFilterPolicy::new(10);
+        let filter = policy.create_filter(&[b"hello", b"world"]);
+        assert!(policy.key_may_match(b"hello", &filter));
+        assert!(policy.key_may_match(b"world", &filter));
+        assert!(!policy.key_may_match(b"x", &filter));
+        assert!(!policy.key_may_match(b"foo", &filter));
+    }
+
+    #[test]
+    fn test_false_positive_rate() {
+        let policy = BloomFilterPolicy::new(10);
+        for n in [1, 10, 100, 1000, 10000] {
+            let filter = build(&policy, n);
+            assert!(filter.len() <= (n as usize * 10 / 8) + 40);
+            // All added keys must match.
+            for i in 0..n {
+                assert!(policy.key_may_match(&key(i), &filter));
+            }
+            // Check false positive rate.
+            let false_positives = (0..10000)
+                .filter(|i| policy.key_may_match(&key(i + 1_000_000_000), &filter))
+                .count();
+            assert!(false_positives <= 200,
+                "{} false positives for {} keys", false_positives, n);
+        }
+    }
+
+    #[test]
+    fn test_bits_per_key() {
+        // More bits per key give fewer false positives.
+        let count = |bits_per_key| {
+            let policy = BloomFilterPolicy::new(bits_per_key);
+            let filter = build(&policy, 1000);
+            (0..10000).filter(|i| policy.key_may_match(&key(i + 1_000_000_000),
+                &filter)).count()
+        };
+        assert!(count(20) < count(5));
+    }
+}
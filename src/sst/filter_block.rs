@@ -0,0 +1,300 @@
+use std::sync::Arc;
+use crate::sst::filter::FilterPolicy;
+
+/// Generate a new filter every 2KB of data blocks.
+pub const FILTER_BASE_LG: u8 = 11;
+/// Base lg recorded for a filter block with a single filter covering the
+/// whole table.
+pub const WHOLE_TABLE_FILTER_BASE_LG: u8 = 0xff;
+
+const U32_SIZE: usize = std::mem::size_of::<u32>();
+
+/// Builds the filter block of a table. Same layout as LevelDB: filter i
+/// covers the keys of the data blocks starting at offsets in
+/// [i * base, (i + 1) * base), where base is 2^base_lg. A base_lg of
+/// WHOLE_TABLE_FILTER_BASE_LG means a single filter covers every key.
+/// Format is concatenation of:
+///  filters        : filter[num_filters]
+///  filter offsets : fixed32[num_filters], offset of every filter
+///  array offset   : fixed32, offset of the filter offsets
+///  base_lg        : u8
+/// The sequence of calls must match the regex:
+///  (start_block add_key*)* finish
+pub struct FilterBlockBuilder {
+    policy_: Arc<dyn FilterPolicy>,
+    base_lg_: u8,
+    /// Keys of the current filter, flattened.
+    keys_: Vec<u8>,
+    /// Start offset of every key in 'keys_'.
+    starts_: Vec<usize>,
+    /// Filters built so far.
+    result_: Vec<u8>,
+    filter_offsets_: Vec<u32>,
+}
+
+impl FilterBlockBuilder {
+    /// Builds a filter per 2^'base_lg' bytes of data blocks, or a single
+    /// filter if 'base_lg' is WHOLE_TABLE_FILTER_BASE_LG.
+    pub fn new(policy: Arc<dyn FilterPolicy>, base_lg: u8) -> FilterBlockBuilder {
+        assert!(base_lg < 64 || base_lg == WHOLE_TABLE_FILTER_BASE_LG,
+            "Filter base lg out of range");
+        FilterBlockBuilder {
+            policy_: policy,
+            base_lg_: base_lg,
+            keys_: vec!(),
+            starts_: vec!(),
+            result_: vec!(),
+            filter_offsets_: vec!(),
+        }
+    }
+
+    /// Called before adding the keys of the data block at 'block_offset'.
+    pub fn start_block(&mut self, block_offset: u64) {
+        if self.base_lg_ == WHOLE_TABLE_FILTER_BASE_LG {
+            return;
+        }
+        let filter_index = (block_offset >> self.base_lg_) as usize;
+        assert!(filter_index >= self.filter_offsets_.len());
+        while filter_index > self.filter_offsets_.len() {
+            self.generate_filter();
+        }
+    }
+
+    pub fn add_key(&mut self, key: &[u8]) {
+        self.starts_.push(self.keys_.len());
+        self.keys_.extend_from_slice(key);
+    }
+
+    /// Returns the contents of the filter block.
+    pub fn finish(&mut self) -> &[u8] {
+        if !self.starts_.is_empty() || self.base_lg_ == WHOLE_TABLE_FILTER_BASE_LG {
+            self.generate_filter();
+        }
+        // Append array of per-filter offsets.
+        let array_offset = self.result_.len() as u32;
+        for offset in self.filter_offsets_.iter().chain(std::iter::once(&array_offset)) {
+            self.result_.extend_from_slice(&offset.to_le_bytes());
+        }
+        self.result_.push(self.base_lg_);
+        return &self.result_;
+    }
+
+    fn generate_filter(&mut self) {
+        self.filter_offsets_.push(self.result_.len() as u32);
+        if self.starts_.is_empty() {
+            // Fast path if there are no keys for this filter.
+            return;
+        }
+        let ends = self.starts_.iter().skip(1).copied()
+            .chain(std::iter::once(self.keys_.len()));
+        let keys: Vec<&[u8]> = self.starts_.iter().zip(ends)
+            .map(|(start, end)| &self.keys_[*start..end])
+            .collect();
+        let filter = self.policy_.create_filter(&keys);
+        self.result_.extend_from_slice(&filter);
+        self.keys_.clear();
+        self.starts_.clear();
+    }
+}
+
+/// Read side of a filter block. Check FilterBlockBuilder for the format.
+/// A malformed filter block matches every key.
+pub struct FilterBlockReader {
+    policy_: Arc<dyn FilterPolicy>,
+    data_: Vec<u8>,
+    /// Offset of the filter offsets.
+    offset_: usize,
+    num_filters_: usize,
+    base_lg_: u8,
+}
+
+impl FilterBlockReader {
+    pub fn new(policy: Arc<dyn FilterPolicy>, data: Vec<u8>) -> FilterBlockReader {
+        let mut reader = FilterBlockReader {
+            policy_: policy,
+            data_: data,
+            offset_: 0,
+            num_filters_: 0,
+            base_lg_: 0,
+        };
+        let n = reader.data_.len();
+        // 1 byte for base_lg and 4 for the start of the offset array.
+        if n < 1 + U32_SIZE {
+            return reader;
+        }
+        let array_offset = reader.decode_fixed32(n - 1 - U32_SIZE) as usize;
+        if array_offset > n - 1 - U32_SIZE {
+            return reader;
+        }
+        reader.base_lg_ = reader.data_[n - 1];
+        reader.offset_ = array_offset;
+        reader.num_filters_ = (n - 1 - U32_SIZE - array_offset) / U32_SIZE;
+        return reader;
+    }
+
+    #[inline(always)]
+    fn decode_fixed32(&self, offset: usize) -> u32 {
+        u32::from_le_bytes(self.data_[offset..offset + U32_SIZE].try_into().unwrap())
+    }
+
+    /// Returns false only if 'key' was not added to the data block at
+    /// 'block_offset'.
+    pub fn key_may_match(&self, block_offset: u64, key: &[u8]) -> bool {
+        let index = if self.base_lg_ == WHOLE_TABLE_FILTER_BASE_LG {
+            0
+        } else if self.base_lg_ >= 64 {
+            return true;
+        } else {
+            (block_offset >> self.base_lg_) as usize
+        };
+        if index >= self.num_filters_ {
+            // Errors are treated as potential matches.
+            return true;
+        }
+        // Offset of the following filter, or the array offset for the last
+        // filter, which is stored right after the filter offsets.
+        let start = self.decode_fixed32(self.offset_ + index * U32_SIZE) as usize;
+        let limit = self.decode_fixed32(self.offset_ + (index + 1) * U32_SIZE) as usize;
+        if start < limit && limit <= self.offset_ {
+            return self.policy_.key_may_match(key, &self.data_[start..limit]);
+        } else if start == limit {
+            // Empty filters do not match any keys.
+            return false;
+        }
+        return true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::sst::filter::{BloomFilterPolicy, FilterPolicy};
+    use crate::sst::filter_block::{FilterBlockBuilder, FilterBlockReader,
+        FILTER_BASE_LG, WHOLE_TABLE_FILTER_BASE_LG};
+
+    /// Filter listing the keys it was built from, to check exactly which
+    /// keys went into which filter.
+    struct TestHashFilter;
+
+    impl FilterPolicy for TestHashFilter {
+        fn name(&self) -> &str {
+            "TestHashFilter"
+        }
+
+        fn create_filter(&self, keys: &[&[u8]]) -> Vec<u8> {
+            let mut filter = vec!();
+            for key in keys {
+                filter.push(key.len() as u8);
+                filter.extend_from_slice(key);
+            }
+            return filter;
+        }
+
+        fn key_may_match(&self, key: &[u8], mut filter: &[u8]) -> bool {
+            while let Some((len, rest)) = filter.split_first() {
+                let (candidate, rest) = rest.split_at(*len as usize);
+                if candidate == key {
+                    return true;
+                }
+                filter = rest;
+            }
+            return false;
+        }
+    }
+
+    #[test]
+    fn test_empty_builder() {
+        let policy = Arc::new(TestHashFilter);
+        let mut builder = FilterBlockBuilder::new(policy.clone(), FILTER_BASE_LG);
+        let block = builder.finish().to_vec();
+        assert_eq!(block, [0, 0, 0, 0, FILTER_BASE_LG]);
+        let reader = FilterBlockReader::new(policy, block);
+        assert!(reader.key_may_match(0, b"foo"));
+        assert!(reader.key_may_match(100000, b"foo"));
+    }
+
+    #[test]
+    fn test_single_chunk() {
+        let policy = Arc::new(TestHashFilter);
+        let mut builder = FilterBlockBuilder::new(policy.clone(), FILTER_BASE_LG);
+        builder.start_block(100);
+        builder.add_key(b"foo");
+        builder.add_key(b"bar");
+        builder.add_key(b"box");
+        builder.start_block(200);
+        builder.add_key(b"box");
+        builder.start_block(300);
+        builder.add_key(b"hello");
+        let reader = FilterBlockReader::new(policy, builder.finish().to_vec());
+        for key in [&b"foo"[..], b"bar", b"box", b"hello"] {
+            assert!(reader.key_may_match(100, key));
+        }
+        assert!(!reader.key_may_match(100, b"missing"));
+        assert!(!reader.key_may_match(100, b"other"));
+    }
+
+    #[test]
+    fn test_multi_chunk() {
+        let policy = Arc::new(TestHashFilter);
+        let mut builder = FilterBlockBuilder::new(policy.clone(), FILTER_BASE_LG);
+        // First filter
+        builder.start_block(0);
+        builder.add_key(b"foo");
+        builder.start_block(2000);
+        builder.add_key(b"bar");
+        // Second filter
+        builder.start_block(3100);
+        builder.add_key(b"box");
+        // Third filter is empty
+        // Last filter
+        builder.start_block(9000);
+        builder.add_key(b"box");
+        builder.add_key(b"hello");
+        let reader = FilterBlockReader::new(policy, builder.finish().to_vec());
+        // Check first filter
+        assert!(reader.key_may_match(0, b"foo"));
+        assert!(reader.key_may_match(2000, b"bar"));
+        assert!(!reader.key_may_match(0, b"box"));
+        assert!(!reader.key_may_match(0, b"hello"));
+        // Check second filter
+        assert!(reader.key_may_match(3100, b"box"));
+        assert!(!reader.key_may_match(3100, b"foo"));
+        assert!(!reader.key_may_match(3100, b"bar"));
+        assert!(!reader.key_may_match(3100, b"hello"));
+        // Check third filter (empty)
+        assert!(!reader.key_may_match(4100, b"foo"));
+        assert!(!reader.key_may_match(4100, b"box"));
+        // Check last filter
+        assert!(reader.key_may_match(9000, b"box"));
+        assert!(reader.key_may_match(9000, b"hello"));
+        assert!(!reader.key_may_match(9000, b"foo"));
+        assert!(!reader.key_may_match(9000, b"bar"));
+    }
+
+    #[test]
+    fn test_whole_table_filter() {
+        let policy = Arc::new(BloomFilterPolicy::new(10));
+        let mut builder = FilterBlockBuilder::new(policy.clone(),
+            WHOLE_TABLE_FILTER_BASE_LG);
+        for i in 0..100u64 {
+            builder.start_block(i * 4096);
+            builder.add_key(format!("key{}", i).as_bytes());
+        }
+        let reader = FilterBlockReader::new(policy, builder.finish().to_vec());
+        for i in 0..100u64 {
+            // Block offset does not matter.
+            assert!(reader.key_may_match(0, format!("key{}", i).as_bytes()));
+            assert!(reader.key_may_match(i * 4096, format!("key{}", i).as_bytes()));
+        }
+        assert!(!reader.key_may_match(0, b"missing"));
+    }
+
+    #[test]
+    fn test_malformed_block() {
+        let policy = Arc::new(TestHashFilter);
+        for data in [vec!(), vec![1, 2, 3], vec![0xff, 0xff, 0xff, 0xff, FILTER_BASE_LG]] {
+            let reader = FilterBlockReader::new(policy.clone(), data);
+            assert!(reader.key_may_match(0, b"foo"));
+        }
+    }
+}
@@ -0,0 +1,387 @@
+use crate::checksum::{crc32c, crc32c_extend, mask_crc, xxhash64};
+use crate::coding::{get_varint, put_varint};
+use crate::filesystem::Path;
+use crate::sst::compression::decompress;
+use crate::sst::lsm_error::DataStoreError;
+use crate::sst::options::{ChecksumType, CompressionType};
+
+// Table Format
+// ------------
+// A table is a sequence of blocks followed by a fixed size footer:
+//  data block 1
+//  ...
+//  data block N
+//  meta block 1
+//  ...
+//  meta block K
+//  metaindex block
+//  index block
+//  footer
+// Every block is followed by a trailer:
+//  compression type : u8, CompressionType of the block contents
+//  checksum         : fixed32 over the contents and the compression type
+// BlockHandle sizes do not include the trailer.
+// Data blocks hold the entries of the table in key order, cut once they
+// reach the configured block size. The index block has an entry per data
+// block, whose key is at or after the last key of that data block and
+// before the first key of the next one, and whose value is the handle of
+// the data block. The metaindex block maps the names of meta blocks, such
+// as the filter block, to their handles. Check struct Footer for its format.
+
+/// Magic number at the end of every table. Spells "lsmrust1".
+pub const TABLE_MAGIC_NUMBER: u64 = 0x6c73_6d72_7573_7431;
+
+/// Size of the trailer following every block.
+pub const BLOCK_TRAILER_SIZE: usize = 1 + 4;
+
+/// Prefix of the name of the filter block, followed by the name of the
+/// filter policy.
+pub const FILTER_BLOCK_NAME_PREFIX: &str = "filter.";
+
+/// Name of the meta block holding the range deletions of the table, in
+/// key order. Keys are the starts of the ranges, values their ends.
+pub const RANGE_DEL_BLOCK_NAME: &str = "lsm.range_del";
+
+/// Name of the meta block holding the TableSettings of the table.
+pub const TABLE_SETTINGS_BLOCK_NAME: &str = "lsm.table_settings";
+
+/// Location of a block within a table.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BlockHandle {
+    pub offset: u64,
+    pub size: u64,
+}
+
+impl BlockHandle {
+    /// Maximum encoding length of a BlockHandle.
+    pub const MAX_ENCODED_LENGTH: usize = 10 + 10;
+
+    pub fn new(offset: u64, size: u64) -> BlockHandle {
+        BlockHandle { offset, size }
+    }
+
+    /// Appends the leb128 encoded offset and size to 'dst'.
+    pub fn encode_to(&self, dst: &mut Vec<u8>) {
+        put_varint(dst, self.offset);
+        put_varint(dst, self.size);
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut dst = Vec::with_capacity(Self::MAX_ENCODED_LENGTH);
+        self.encode_to(&mut dst);
+        return dst;
+    }
+
+    /// Decodes a handle from the start of 'input' and advances it past
+    /// the handle.
+    pub fn decode_from(input: &mut &[u8]) -> Result<BlockHandle, DataStoreError> {
+        match (get_varint(input), get_varint(input)) {
+            (Some(offset), Some(size)) => Ok(BlockHandle { offset, size }),
+            _ => Err(DataStoreError::MalformedBlock {
+                offset: 0,
+                reason: "bad block handle".to_string(),
+            }),
+        }
+    }
+}
+
+/// Checksum stored in the trailer of a block with 'contents' and
+/// 'compression' type. CRC32C is masked; xxHash64 is truncated to its
+/// lower 32 bits.
+pub fn block_checksum(checksum: ChecksumType, contents: &[u8],
+    compression: CompressionType) -> u32 {
+    match checksum {
+        ChecksumType::NoChecksum => 0,
+        ChecksumType::Crc32c =>
+            mask_crc(crc32c_extend(crc32c(contents), &[compression as u8])),
+        ChecksumType::XxHash64 => {
+            let mut data = Vec::with_capacity(contents.len() + 1);
+            data.extend_from_slice(contents);
+            data.push(compression as u8);
+            xxhash64(&data) as u32
+        }
+    }
+}
+
+/// Trailer to be written after a block with 'contents'.
+pub fn block_trailer(contents: &[u8], compression: CompressionType,
+    checksum: ChecksumType) -> [u8; BLOCK_TRAILER_SIZE] {
+    let mut trailer = [0; BLOCK_TRAILER_SIZE];
+    trailer[0] = compression as u8;
+    trailer[1..].copy_from_slice(
+        &block_checksum(checksum, contents, compression).to_le_bytes());
+    return trailer;
+}
+
+/// Reads the contents of the block at 'handle' in 'path', a file of
+/// 'file_size' bytes, checks its trailer and decompresses it. The checksum
+/// is only verified if 'verify_checksums' is set. Fails with
+/// DataStoreError::Corruption if the block does not fit in the file or is
+/// truncated, the checksum does not match, the compression type is unknown
+/// or the block cannot be decompressed.
+pub fn read_block(path: &dyn Path, handle: &BlockHandle, file_size: u64,
+    checksum: ChecksumType, verify_checksums: bool) -> Result<Vec<u8>, DataStoreError> {
+    let corruption = |reason: String| DataStoreError::Corruption {
+        file: path.to_str().unwrap_or("").to_string(),
+        offset: handle.offset,
+        reason,
+    };
+    // Handles are read before the checksum of the block they point to can
+    // be verified, so a corrupted size must not be allocated.
+    let end = handle.offset.checked_add(handle.size)
+        .and_then(|end| end.checked_add(BLOCK_TRAILER_SIZE as u64));
+    if end.is_none_or(|end| end > file_size) {
+        return Err(corruption(format!("block of {} bytes past the end of the file of {} bytes",
+            handle.size, file_size)));
+    }
+    let size = usize::try_from(handle.size)
+        .map_err(|_| corruption(format!("bad block size {}", handle.size)))?;
+    let mut data = vec![0; size + BLOCK_TRAILER_SIZE];
+    let bytes = path.get_file_system().seek_read(path, handle.offset, &mut data)?;
+    if bytes != data.len() {
+        return Err(corruption(format!("truncated block read, expected {} bytes, found {}",
+            data.len(), bytes)));
+    }
+    let compression = CompressionType::from_u8(data[size]).ok_or_else(||
+        corruption(format!("unknown compression type {}", data[size])))?;
+    if verify_checksums {
+        let expected = u32::from_le_bytes(data[size + 1..].try_into().unwrap());
+        let actual = block_checksum(checksum, &data[..size], compression);
+        if actual != expected {
+            return Err(corruption(format!("block checksum mismatch, expected {:#x}, found {:#x}",
+                expected, actual)));
+        }
+    }
+    data.truncate(size);
+    if compression == CompressionType::NoCompression {
+        return Ok(data);
+    }
+    return decompress(compression, &data).map_err(|reason|
+        corruption(format!("bad {:?} compressed block: {}", compression, reason)));
+}
+
+/// Fixed size footer at the end of every table.
+/// Format is concatenation of:
+///  checksum type    : u8, ChecksumType of the block trailers
+///  metaindex handle : BlockHandle
+///  index handle     : BlockHandle
+///  padding          : zeroes up to 1 + 2 * BlockHandle::MAX_ENCODED_LENGTH
+///  magic            : fixed64 TABLE_MAGIC_NUMBER
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Footer {
+    pub checksum: ChecksumType,
+    pub metaindex_handle: BlockHandle,
+    pub index_handle: BlockHandle,
+}
+
+impl Footer {
+    /// Length of the encoded footer.
+    pub const ENCODED_LENGTH: usize = 1 + 2 * BlockHandle::MAX_ENCODED_LENGTH + 8;
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut dst = Vec::with_capacity(Self::ENCODED_LENGTH);
+        dst.push(self.checksum as u8);
+        self.metaindex_handle.encode_to(&mut dst);
+        self.index_handle.encode_to(&mut dst);
+        dst.resize(Self::ENCODED_LENGTH - 8, 0);
+        dst.extend_from_slice(&TABLE_MAGIC_NUMBER.to_le_bytes());
+        debug_assert!(dst.len() == Self::ENCODED_LENGTH);
+        return dst;
+    }
+
+    /// Decodes the footer in 'input', which must be the last
+    /// ENCODED_LENGTH bytes of a table. A wrong magic number means the
+    /// file is not a table and fails with DataStoreError::InvalidHeader.
+    pub fn decode_from(input: &[u8]) -> Result<Footer, DataStoreError> {
+        if input.len() != Self::ENCODED_LENGTH {
+            return Err(DataStoreError::MalformedBlock {
+                offset: 0,
+                reason: format!("footer must be {} bytes, found {}",
+                    Self::ENCODED_LENGTH, input.len()),
+            });
+        }
+        let magic_start = Self::ENCODED_LENGTH - 8;
+        let magic = u64::from_le_bytes(input[magic_start..].try_into().unwrap());
+        if magic != TABLE_MAGIC_NUMBER {
+            return Err(DataStoreError::InvalidHeader {
+                expected: format!("table magic number {:#x}", TABLE_MAGIC_NUMBER),
+                found: format!("{:#x}", magic),
+            });
+        }
+        let checksum = ChecksumType::from_u8(input[0]).ok_or_else(||
+            DataStoreError::InvalidHeader {
+                expected: "known checksum type".to_string(),
+                found: format!("checksum type {}", input[0]),
+            })?;
+        let mut handles = &input[1..magic_start];
+        let metaindex_handle = BlockHandle::decode_from(&mut handles)?;
+        let index_handle = BlockHandle::decode_from(&mut handles)?;
+        return Ok(Footer { checksum, metaindex_handle, index_handle });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+    use crate::filesystem::{LocalPath, Path};
+    use crate::sst::format::{block_trailer, read_block, BlockHandle, Footer,
+        BLOCK_TRAILER_SIZE};
+    use crate::sst::lsm_error::DataStoreError;
+    use crate::sst::options::{ChecksumType, CompressionType};
+
+    fn default_footer() -> Footer {
+        Footer {
+            checksum: ChecksumType::Crc32c,
+            metaindex_handle: BlockHandle::default(),
+            index_handle: BlockHandle::default(),
+        }
+    }
+
+    #[test]
+    fn test_block_handle_round_trip() {
+        for handle in [BlockHandle::new(0, 0), BlockHandle::new(300, 4096),
+            BlockHandle::new(u64::MAX, u64::MAX)] {
+            let encoded = handle.encode();
+            assert!(encoded.len() <= BlockHandle::MAX_ENCODED_LENGTH);
+            let mut input = &encoded[..];
+            assert_eq!(BlockHandle::decode_from(&mut input).unwrap(), handle);
+            assert!(input.is_empty());
+        }
+        assert!(BlockHandle::decode_from(&mut &[0x80u8][..]).is_err());
+    }
+
+    #[test]
+    fn test_footer_round_trip() {
+        let footer = Footer {
+            checksum: ChecksumType::XxHash64,
+            metaindex_handle: BlockHandle::new(1 << 40, 123),
+            index_handle: BlockHandle::new((1 << 40) + 123, u64::MAX),
+        };
+        let encoded = footer.encode();
+        assert_eq!(encoded.len(), Footer::ENCODED_LENGTH);
+        assert_eq!(Footer::decode_from(&encoded).unwrap(), footer);
+    }
+
+    #[test]
+    fn test_footer_bad_magic() {
+        let mut encoded = default_footer().encode();
+        let last = encoded.len() - 1;
+        encoded[last] ^= 0xff;
+        assert!(matches!(Footer::decode_from(&encoded),
+            Err(DataStoreError::InvalidHeader { .. })));
+        assert!(Footer::decode_from(&encoded[1..]).is_err());
+    }
+
+    #[test]
+    fn test_footer_bad_checksum_type() {
+        let mut encoded = default_footer().encode();
+        encoded[0] = 0x7f;
+        assert!(matches!(Footer::decode_from(&encoded),
+            Err(DataStoreError::InvalidHeader { .. })));
+    }
+
+    /// Writes blocks with 'contents' and every checksum type to 'path'
+    /// and returns their handles.
+    fn write_blocks(path: &dyn Path, contents: &[u8]) -> Vec<(ChecksumType, BlockHandle)> {
+        let filesystem = path.get_file_system();
+        assert!(filesystem.create(path).is_ok());
+        let mut handles = vec!();
+        let mut offset = 0;
+        for checksum in [ChecksumType::NoChecksum, ChecksumType::Crc32c,
+            ChecksumType::XxHash64] {
+            assert!(filesystem.append(path, contents).is_ok());
+            let trailer = block_trailer(contents, CompressionType::NoCompression, checksum);
+            assert!(filesystem.append(path, &trailer).is_ok());
+            handles.push((checksum, BlockHandle::new(offset, contents.len() as u64)));
+            offset += (contents.len() + BLOCK_TRAILER_SIZE) as u64;
+        }
+        return handles;
+    }
+
+    #[test]
+    fn test_read_block() {
+        let tmp_dir = TempDir::new().unwrap();
+        let file_path = tmp_dir.path().join("blocks");
+        let path = LocalPath::from_std_path(file_path.as_path());
+        let handles = write_blocks(&path, b"block contents");
+        let file_size = std::fs::metadata(&file_path).unwrap().len();
+        for (checksum, handle) in handles {
+            assert_eq!(read_block(&path, &handle, file_size, checksum, true).unwrap(),
+                b"block contents");
+        }
+    }
+
+    #[test]
+    fn test_read_corrupted_block() {
+        let tmp_dir = TempDir::new().unwrap();
+        let file_path = tmp_dir.path().join("blocks");
+        let path = LocalPath::from_std_path(file_path.as_path());
+        let handles = write_blocks(&path, b"block contents");
+        let mut data = std::fs::read(&file_path).unwrap();
+        for (_, handle) in handles.iter() {
+            // Flip a bit of the contents.
+            data[handle.offset as usize + 3] ^= 0x10;
+        }
+        std::fs::write(&file_path, &data).unwrap();
+        let file_size = data.len() as u64;
+        for (checksum, handle) in handles.iter() {
+            let result = read_block(&path, handle, file_size, *checksum, true);
+            if *checksum == ChecksumType::NoChecksum {
+                assert!(result.is_ok());
+                continue;
+            }
+            match result {
+                Err(DataStoreError::Corruption { file, offset, .. }) => {
+                    assert_eq!(file, path.to_str().unwrap());
+                    assert_eq!(offset, handle.offset);
+                }
+                _ => panic!("Corruption is not detected"),
+            }
+            // Not detected unless checksums are verified.
+            assert!(read_block(&path, handle, file_size, *checksum, false).is_ok());
+        }
+        // Truncated block, and corrupted sizes which would not fit in memory
+        // or overflow the end offset.
+        let (checksum, handle) = handles[2];
+        for size in [handle.size + 1, 1 << 40, u64::MAX - 1] {
+            let corrupted = BlockHandle::new(handle.offset, size);
+            assert!(matches!(read_block(&path, &corrupted, file_size, checksum, false),
+                Err(DataStoreError::Corruption { .. })));
+        }
+        // A file shorter than expected.
+        let truncated = BlockHandle::new(handle.offset, handle.size + 1);
+        assert!(matches!(read_block(&path, &truncated, file_size + 1, checksum, false),
+            Err(DataStoreError::Corruption { .. })));
+    }
+
+    #[test]
+    fn test_read_compressed_block() {
+        use crate::sst::compression::compress;
+        let tmp_dir = TempDir::new().unwrap();
+        let file_path = tmp_dir.path().join("blocks");
+        let path = LocalPath::from_std_path(file_path.as_path());
+        let filesystem = path.get_file_system();
+        assert!(filesystem.create(&path).is_ok());
+        let contents = ["block contents"; 100].concat().into_bytes();
+        let compressed = compress(CompressionType::Snappy, &contents).unwrap();
+        let trailer = block_trailer(&compressed, CompressionType::Snappy,
+            ChecksumType::Crc32c);
+        assert!(filesystem.append(&path, &compressed).is_ok());
+        assert!(filesystem.append(&path, &trailer).is_ok());
+        let handle = BlockHandle::new(0, compressed.len() as u64);
+        let file_size = filesystem.file_size(&path).unwrap();
+        assert_eq!(read_block(&path, &handle, file_size, ChecksumType::Crc32c, true).unwrap(),
+            contents);
+        // Garbage passing the checksum fails to decompress.
+        let garbage = vec![0xff; 16];
+        let trailer = block_trailer(&garbage, CompressionType::Snappy,
+            ChecksumType::Crc32c);
+        assert!(filesystem.append(&path, &garbage).is_ok());
+        assert!(filesystem.append(&path, &trailer).is_ok());
+        let handle = BlockHandle::new((compressed.len() + BLOCK_TRAILER_SIZE) as u64,
+            garbage.len() as u64);
+        let file_size = filesystem.file_size(&path).unwrap();
+        assert!(matches!(read_block(&path, &handle, file_size, ChecksumType::Crc32c, true),
+            Err(DataStoreError::Corruption { .. })));
+    }
+}
@@ -8,6 +8,32 @@ pub enum DataStoreError {
         expected: String,
         found: String,
     },
+    #[error("malformed block at offset {offset}: {reason}")]
+    MalformedBlock {
+        offset: usize,
+        reason: String,
+    },
+    #[error("block is full (block size {block_size}, size after entry {required})")]
+    BlockFull {
+        block_size: usize,
+        required: usize,
+    },
+    #[error("corruption in {file} at offset {offset}: {reason}")]
+    Corruption {
+        file: String,
+        offset: u64,
+        reason: String,
+    },
+    #[error("malformed write batch: {0}")]
+    MalformedWriteBatch(String),
+    #[error("malformed version edit: {0}")]
+    MalformedVersionEdit(String),
+    #[error("invalid argument: {0}")]
+    InvalidArgument(String),
+    #[error("background error: {0}")]
+    BackgroundError(String),
+    #[error("not supported: {0}")]
+    NotSupported(String),
     /// Represents all other cases of `std::io::Error`.
     #[error(transparent)]
     IOError(#[from] std::io::Error),
@@ -1,2 +1,11 @@
 pub mod lsm_error;
-pub mod block;
\ No newline at end of file
+pub mod block;
+pub mod block_reader;
+pub mod compression;
+pub mod filter;
+pub mod filter_block;
+pub mod format;
+pub mod options;
+pub mod properties;
+pub mod table;
+pub mod table_builder;
@@ -0,0 +1,329 @@
+use std::sync::Arc;
+use crate::coding::{get_varint, put_varint};
+use crate::comparator::{BytewiseComparator, Comparator};
+use crate::sst::filter::FilterPolicy;
+use crate::sst::lsm_error::DataStoreError;
+use crate::sst::properties::TablePropertiesCollectorFactory;
+
+/// Default approximate size of a block. Same as LevelDB.
+pub const DEFAULT_BLOCK_SIZE: usize = 4 * 1024;
+/// Default number of entries between restart points.
+pub const DEFAULT_RESTART_INTERVAL: usize = 16;
+
+/// Checksum stored with every block to detect corruption.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ChecksumType {
+    NoChecksum = 0x0,
+    Crc32c = 0x1,
+    XxHash64 = 0x2,
+}
+
+impl ChecksumType {
+    pub fn from_u8(value: u8) -> Option<ChecksumType> {
+        match value {
+            0x0 => Some(ChecksumType::NoChecksum),
+            0x1 => Some(ChecksumType::Crc32c),
+            0x2 => Some(ChecksumType::XxHash64),
+            _ => None,
+        }
+    }
+}
+
+/// Compression applied to blocks before they are written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum CompressionType {
+    NoCompression = 0x0,
+    Snappy = 0x1,
+    Lz4 = 0x2,
+    Zstd = 0x3,
+}
+
+impl CompressionType {
+    pub fn from_u8(value: u8) -> Option<CompressionType> {
+        match value {
+            0x0 => Some(CompressionType::NoCompression),
+            0x1 => Some(CompressionType::Snappy),
+            0x2 => Some(CompressionType::Lz4),
+            0x3 => Some(CompressionType::Zstd),
+            _ => None,
+        }
+    }
+}
+
+/// Options used to build blocks and tables.
+#[derive(Clone)]
+pub struct TableOptions {
+    /// Orders the keys of the table.
+    pub comparator: Arc<dyn Comparator>,
+    /// Whether the keys are internal keys, whose sequence numbers and
+    /// deletions are recorded in the properties of the table.
+    pub internal_keys: bool,
+    /// Approximate size of uncompressed data packed per block. A block
+    /// only exceeds it when a single entry is larger.
+    pub block_size: usize,
+    /// Number of entries between restart points for prefix compression.
+    pub block_restart_interval: usize,
+    /// Checksum stored with every block.
+    pub checksum: ChecksumType,
+    /// Compression applied to every block. Blocks which do not shrink
+    /// by at least 12.5% are stored uncompressed.
+    pub compression: CompressionType,
+    /// Compression applied to the tables of the bottommost level, which
+    /// hold most of the data. 'compression' is used if None.
+    pub bottommost_compression: Option<CompressionType>,
+    /// Builds the filter stored with the table, if any.
+    pub filter_policy: Option<Arc<dyn FilterPolicy>>,
+    /// Whether a single filter covers the whole table, instead of a
+    /// filter per 2KB range of data blocks.
+    pub whole_table_filter: bool,
+    /// Record custom properties of every table built.
+    pub properties_collectors: Vec<Arc<dyn TablePropertiesCollectorFactory>>,
+}
+
+impl Default for TableOptions {
+    fn default() -> Self {
+        TableOptions {
+            comparator: Arc::new(BytewiseComparator),
+            internal_keys: false,
+            block_size: DEFAULT_BLOCK_SIZE,
+            block_restart_interval: DEFAULT_RESTART_INTERVAL,
+            checksum: ChecksumType::Crc32c,
+            compression: CompressionType::NoCompression,
+            bottommost_compression: None,
+            filter_policy: None,
+            whole_table_filter: false,
+            properties_collectors: vec!(),
+        }
+    }
+}
+
+impl TableOptions {
+    /// Options for tables of the bottommost level.
+    pub fn for_bottommost_level(&self) -> TableOptions {
+        TableOptions {
+            compression: self.bottommost_compression.unwrap_or(self.compression),
+            ..self.clone()
+        }
+    }
+
+    /// Settings to be recorded in a table built with these options.
+    pub fn settings(&self) -> TableSettings {
+        TableSettings {
+            comparator_name: self.comparator.name().to_string(),
+            block_size: self.block_size,
+            block_restart_interval: self.block_restart_interval,
+            checksum: self.checksum,
+            compression: self.compression,
+            filter_policy_name: self.filter_policy.as_ref()
+                .map(|policy| policy.name().to_string()),
+        }
+    }
+}
+
+/// Options controlling reads from tables.
+#[derive(Clone, Copy, Debug)]
+pub struct ReadOptions {
+    /// Whether the checksums of the blocks read are verified. Blocks of
+    /// table metadata are always verified.
+    pub verify_checksums: bool,
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        ReadOptions { verify_checksums: true }
+    }
+}
+
+/// Version of the TableSettings encoding.
+const TABLE_SETTINGS_VERSION: u8 = 1;
+
+/// TableOptions a table was built with, as recorded in the table, so
+/// readers do not need to be told them.
+/// Format is concatenation of:
+///  version                 : u8
+///  comparator_name_size    : leb128
+///  comparator name         : char[comparator_name_size]
+///  block_size              : leb128
+///  block_restart_interval  : leb128
+///  checksum                : u8
+///  compression             : u8
+///  filter_policy_name_size : leb128, 0 if there is no filter
+///  filter policy name      : char[filter_policy_name_size]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TableSettings {
+    pub comparator_name: String,
+    pub block_size: usize,
+    pub block_restart_interval: usize,
+    pub checksum: ChecksumType,
+    pub compression: CompressionType,
+    pub filter_policy_name: Option<String>,
+}
+
+impl TableSettings {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut dst = vec![TABLE_SETTINGS_VERSION];
+        put_varint(&mut dst, self.comparator_name.len() as u64);
+        dst.extend_from_slice(self.comparator_name.as_bytes());
+        put_varint(&mut dst, self.block_size as u64);
+        put_varint(&mut dst, self.block_restart_interval as u64);
+        dst.push(self.checksum as u8);
+        dst.push(self.compression as u8);
+        let filter_policy_name = self.filter_policy_name.as_deref().unwrap_or("");
+        put_varint(&mut dst, filter_policy_name.len() as u64);
+        dst.extend_from_slice(filter_policy_name.as_bytes());
+        return dst;
+    }
+
+    pub fn decode(mut input: &[u8]) -> Result<TableSettings, DataStoreError> {
+        let input = &mut input;
+        let version = get_u8(input)?;
+        if version != TABLE_SETTINGS_VERSION {
+            return Err(DataStoreError::InvalidHeader {
+                expected: format!("table settings version {}", TABLE_SETTINGS_VERSION),
+                found: format!("table settings version {}", version),
+            });
+        }
+        let comparator_name = get_string(input)?;
+        let block_size = get_usize(input)?;
+        let block_restart_interval = get_usize(input)?;
+        let checksum = ChecksumType::from_u8(get_u8(input)?)
+            .ok_or_else(|| bad_settings("unknown checksum type"))?;
+        let compression = CompressionType::from_u8(get_u8(input)?)
+            .ok_or_else(|| bad_settings("unknown compression type"))?;
+        let filter_policy_name = get_string(input)?;
+        return Ok(TableSettings {
+            comparator_name,
+            block_size,
+            block_restart_interval,
+            checksum,
+            compression,
+            filter_policy_name: if filter_policy_name.is_empty() {
+                None
+            } else {
+                Some(filter_policy_name)
+            },
+        });
+    }
+}
+
+fn bad_settings(reason: &str) -> DataStoreError {
+    DataStoreError::InvalidHeader {
+        expected: "valid table settings".to_string(),
+        found: reason.to_string(),
+    }
+}
+
+fn get_u8(input: &mut &[u8]) -> Result<u8, DataStoreError> {
+    let (first, rest) = input.split_first()
+        .ok_or_else(|| bad_settings("truncated table settings"))?;
+    *input = rest;
+    return Ok(*first);
+}
+
+fn get_usize(input: &mut &[u8]) -> Result<usize, DataStoreError> {
+    get_varint(input)
+        .and_then(|value| usize::try_from(value).ok())
+        .ok_or_else(|| bad_settings("bad varint in table settings"))
+}
+
+fn get_string(input: &mut &[u8]) -> Result<String, DataStoreError> {
+    let len = get_usize(input)?;
+    if len > input.len() {
+        return Err(bad_settings("truncated table settings"));
+    }
+    let (name, rest) = input.split_at(len);
+    *input = rest;
+    return String::from_utf8(name.to_vec())
+        .map_err(|_| bad_settings("name is not valid UTF-8"));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::sst::filter::FilterPolicy;
+    use crate::sst::options::{ChecksumType, CompressionType, TableOptions,
+        TableSettings, DEFAULT_BLOCK_SIZE};
+
+    struct TestFilterPolicy;
+
+    impl FilterPolicy for TestFilterPolicy {
+        fn name(&self) -> &str {
+            "test.FilterPolicy"
+        }
+
+        fn create_filter(&self, _keys: &[&[u8]]) -> Vec<u8> {
+            vec!()
+        }
+
+        fn key_may_match(&self, _key: &[u8], _filter: &[u8]) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_default_settings() {
+        let settings = TableOptions::default().settings();
+        assert_eq!(settings.comparator_name, "lsm.BytewiseComparator");
+        assert_eq!(settings.block_size, DEFAULT_BLOCK_SIZE);
+        assert_eq!(settings.filter_policy_name, None);
+        assert_eq!(TableSettings::decode(&settings.encode()).unwrap(), settings);
+    }
+
+    #[test]
+    fn test_settings_round_trip() {
+        let options = TableOptions {
+            block_size: 64 * 1024,
+            block_restart_interval: 4,
+            checksum: ChecksumType::XxHash64,
+            compression: CompressionType::Zstd,
+            filter_policy: Some(Arc::new(TestFilterPolicy)),
+            ..TableOptions::default()
+        };
+        let settings = options.settings();
+        let decoded = TableSettings::decode(&settings.encode()).unwrap();
+        assert_eq!(decoded, settings);
+        assert_eq!(decoded.block_size, 64 * 1024);
+        assert_eq!(decoded.block_restart_interval, 4);
+        assert_eq!(decoded.checksum, ChecksumType::XxHash64);
+        assert_eq!(decoded.compression, CompressionType::Zstd);
+        assert_eq!(decoded.filter_policy_name.as_deref(), Some("test.FilterPolicy"));
+    }
+
+    #[test]
+    fn test_malformed_settings() {
+        let encoded = TableOptions::default().settings().encode();
+        // Every truncation is detected.
+        for len in 0..encoded.len() {
+            assert!(TableSettings::decode(&encoded[..len]).is_err());
+        }
+        // Unknown version.
+        let mut bad = encoded.clone();
+        bad[0] = 0xff;
+        assert!(TableSettings::decode(&bad).is_err());
+        // Unknown checksum type.
+        let mut bad = encoded.clone();
+        let checksum_pos = encoded.len() - 3;
+        assert_eq!(bad[checksum_pos], ChecksumType::Crc32c as u8);
+        bad[checksum_pos] = 0x7f;
+        assert!(TableSettings::decode(&bad).is_err());
+    }
+
+    #[test]
+    fn test_bottommost_compression() {
+        let options = TableOptions {
+            compression: CompressionType::Lz4,
+            ..TableOptions::default()
+        };
+        assert_eq!(options.for_bottommost_level().compression, CompressionType::Lz4);
+        let options = TableOptions {
+            bottommost_compression: Some(CompressionType::Zstd),
+            ..options
+        };
+        assert_eq!(options.compression, CompressionType::Lz4);
+        assert_eq!(options.for_bottommost_level().compression, CompressionType::Zstd);
+        assert_eq!(options.for_bottommost_level().settings().compression,
+            CompressionType::Zstd);
+    }
+}
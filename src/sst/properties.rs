@@ -0,0 +1,234 @@
+use std::collections::BTreeMap;
+use crate::coding::{get_varint, put_varint};
+use crate::sst::lsm_error::DataStoreError;
+
+/// Name of the meta block holding the TableProperties of the table.
+pub const PROPERTIES_BLOCK_NAME: &str = "lsm.properties";
+
+/// Prefix of the names of the properties recorded by the table builder.
+/// User collected properties must not use it.
+const RESERVED_PREFIX: &str = "lsm.";
+const NUM_ENTRIES: &str = "lsm.num_entries";
+const NUM_DELETIONS: &str = "lsm.num_deletions";
+const NUM_RANGE_DELETIONS: &str = "lsm.num_range_deletions";
+const SMALLEST_SEQUENCE: &str = "lsm.smallest_sequence";
+const LARGEST_SEQUENCE: &str = "lsm.largest_sequence";
+const RAW_KEY_SIZE: &str = "lsm.raw_key_size";
+const RAW_VALUE_SIZE: &str = "lsm.raw_value_size";
+const NUM_DATA_BLOCKS: &str = "lsm.num_data_blocks";
+const DATA_SIZE: &str = "lsm.data_size";
+const UNCOMPRESSED_DATA_SIZE: &str = "lsm.uncompressed_data_size";
+const SMALLEST_KEY: &str = "lsm.smallest_key";
+const LARGEST_KEY: &str = "lsm.largest_key";
+const CREATION_TIME: &str = "lsm.creation_time";
+const PROPERTY_COLLECTORS: &str = "lsm.property_collectors";
+
+/// Statistics of a table, recorded in its properties block so they can be
+/// read without scanning the table.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TableProperties {
+    pub num_entries: u64,
+    /// Number of deletion markers. Only counted for tables keyed by
+    /// internal keys.
+    pub num_deletions: u64,
+    /// Number of entries of the range deletion block. Not included in
+    /// 'num_entries'.
+    pub num_range_deletions: u64,
+    /// Smallest and largest sequence numbers of the entries and range
+    /// deletions. Only recorded for tables keyed by internal keys.
+    pub smallest_sequence: u64,
+    pub largest_sequence: u64,
+    pub raw_key_size: u64,
+    pub raw_value_size: u64,
+    pub num_data_blocks: u64,
+    /// Size of the data blocks as stored, including their trailers.
+    pub data_size: u64,
+    /// Size of the data blocks before compression.
+    pub uncompressed_data_size: u64,
+    pub smallest_key: Vec<u8>,
+    pub largest_key: Vec<u8>,
+    /// Seconds since the Unix epoch when the table was created.
+    pub creation_time: u64,
+    /// Names of the TablePropertiesCollectors of the table.
+    pub property_collectors: Vec<String>,
+    /// Properties recorded by TablePropertiesCollectors.
+    pub user_collected: BTreeMap<String, Vec<u8>>,
+}
+
+impl TableProperties {
+    /// Ratio of the uncompressed size of the data blocks to their stored
+    /// size. 1.0 for an empty table.
+    pub fn compression_ratio(&self) -> f64 {
+        if self.data_size == 0 {
+            return 1.0;
+        }
+        return self.uncompressed_data_size as f64 / self.data_size as f64;
+    }
+
+    /// Properties as (name, value) entries in name order, to be written to
+    /// the properties block.
+    pub fn encode(&self) -> BTreeMap<String, Vec<u8>> {
+        let mut entries = BTreeMap::new();
+        for (name, value) in [(NUM_ENTRIES, self.num_entries),
+            (NUM_DELETIONS, self.num_deletions),
+            (NUM_RANGE_DELETIONS, self.num_range_deletions),
+            (SMALLEST_SEQUENCE, self.smallest_sequence),
+            (LARGEST_SEQUENCE, self.largest_sequence),
+            (RAW_KEY_SIZE, self.raw_key_size),
+            (RAW_VALUE_SIZE, self.raw_value_size),
+            (NUM_DATA_BLOCKS, self.num_data_blocks),
+            (DATA_SIZE, self.data_size),
+            (UNCOMPRESSED_DATA_SIZE, self.uncompressed_data_size),
+            (CREATION_TIME, self.creation_time)] {
+            let mut encoded = vec!();
+            put_varint(&mut encoded, value);
+            entries.insert(name.to_string(), encoded);
+        }
+        entries.insert(SMALLEST_KEY.to_string(), self.smallest_key.clone());
+        entries.insert(LARGEST_KEY.to_string(), self.largest_key.clone());
+        // Names are length prefixed, so they may hold any character.
+        let mut collectors = vec!();
+        for name in self.property_collectors.iter() {
+            put_varint(&mut collectors, name.len() as u64);
+            collectors.extend_from_slice(name.as_bytes());
+        }
+        entries.insert(PROPERTY_COLLECTORS.to_string(), collectors);
+        for (name, value) in self.user_collected.iter() {
+            entries.insert(name.clone(), value.clone());
+        }
+        return entries;
+    }
+
+    /// Decodes the (name, value) entries of a properties block. Unknown
+    /// reserved names are skipped, so that newer properties can be added.
+    pub fn decode<'a, I>(entries: I) -> Result<TableProperties, DataStoreError>
+        where I: IntoIterator<Item = (&'a [u8], &'a [u8])> {
+        let mut properties = TableProperties::default();
+        for (name, value) in entries {
+            let name = String::from_utf8(name.to_vec()).map_err(|_|
+                bad_properties("property name is not valid UTF-8"))?;
+            let field = match name.as_str() {
+                NUM_ENTRIES => &mut properties.num_entries,
+                NUM_DELETIONS => &mut properties.num_deletions,
+                NUM_RANGE_DELETIONS => &mut properties.num_range_deletions,
+                SMALLEST_SEQUENCE => &mut properties.smallest_sequence,
+                LARGEST_SEQUENCE => &mut properties.largest_sequence,
+                RAW_KEY_SIZE => &mut properties.raw_key_size,
+                RAW_VALUE_SIZE => &mut properties.raw_value_size,
+                NUM_DATA_BLOCKS => &mut properties.num_data_blocks,
+                DATA_SIZE => &mut properties.data_size,
+                UNCOMPRESSED_DATA_SIZE => &mut properties.uncompressed_data_size,
+                CREATION_TIME => &mut properties.creation_time,
+                SMALLEST_KEY => {
+                    properties.smallest_key = value.to_vec();
+                    continue;
+                }
+                LARGEST_KEY => {
+                    properties.largest_key = value.to_vec();
+                    continue;
+                }
+                PROPERTY_COLLECTORS => {
+                    properties.property_collectors = decode_names(value)?;
+                    continue;
+                }
+                _ => {
+                    if !name.starts_with(RESERVED_PREFIX) {
+                        properties.user_collected.insert(name, value.to_vec());
+                    }
+                    continue;
+                }
+            };
+            let mut input = value;
+            *field = get_varint(&mut input).ok_or_else(||
+                bad_properties(&format!("bad value of {}", name)))?;
+        }
+        return Ok(properties);
+    }
+}
+
+fn decode_names(mut input: &[u8]) -> Result<Vec<String>, DataStoreError> {
+    let mut names = vec!();
+    while !input.is_empty() {
+        let length = get_varint(&mut input)
+            .and_then(|length| usize::try_from(length).ok())
+            .filter(|length| *length <= input.len())
+            .ok_or_else(|| bad_properties("bad property collector name"))?;
+        let name = String::from_utf8(input[..length].to_vec()).map_err(|_|
+            bad_properties("property collector name is not valid UTF-8"))?;
+        names.push(name);
+        input = &input[length..];
+    }
+    return Ok(names);
+}
+
+fn bad_properties(reason: &str) -> DataStoreError {
+    DataStoreError::InvalidHeader {
+        expected: "valid table properties".to_string(),
+        found: reason.to_string(),
+    }
+}
+
+/// Records custom properties of a table while it is built. A collector
+/// sees every entry added to the table, in order, and returns the
+/// properties to be recorded once the table is finished.
+pub trait TablePropertiesCollector {
+    /// Called for every entry added to the table.
+    fn add(&mut self, key: &[u8], value: &[u8]);
+
+    /// Returns the properties to be recorded in the table. Names must not
+    /// start with "lsm.".
+    fn finish(&mut self) -> BTreeMap<String, Vec<u8>>;
+
+    /// Name of the collector, recorded in the properties of the tables it
+    /// collected properties of.
+    fn name(&self) -> &str;
+}
+
+/// Creates a TablePropertiesCollector for every table built.
+pub trait TablePropertiesCollectorFactory: Send + Sync {
+    fn create_collector(&self) -> Box<dyn TablePropertiesCollector>;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use crate::sst::properties::TableProperties;
+
+    #[test]
+    fn test_round_trip() {
+        let properties = TableProperties {
+            num_entries: 100,
+            num_deletions: 3,
+            num_range_deletions: 2,
+            smallest_sequence: 10,
+            largest_sequence: 1 << 50,
+            raw_key_size: 1000,
+            raw_value_size: 1 << 40,
+            num_data_blocks: 7,
+            data_size: 2000,
+            uncompressed_data_size: 8000,
+            smallest_key: b"a".to_vec(),
+            largest_key: b"z".to_vec(),
+            creation_time: 1_700_000_000,
+            property_collectors: vec!("app.Collector".to_string(), "app,other".to_string()),
+            user_collected: BTreeMap::from([("app.max".to_string(), b"9".to_vec())]),
+        };
+        assert_eq!(properties.compression_ratio(), 4.0);
+        let encoded = properties.encode();
+        let decoded = TableProperties::decode(
+            encoded.iter().map(|(k, v)| (k.as_bytes(), &v[..]))).unwrap();
+        assert_eq!(decoded, properties);
+    }
+
+    #[test]
+    fn test_unknown_and_malformed_properties() {
+        // Unknown reserved properties are skipped.
+        let decoded = TableProperties::decode([(&b"lsm.future"[..], &b"x"[..])]).unwrap();
+        assert_eq!(decoded, TableProperties::default());
+        assert!(TableProperties::decode([(&b"lsm.num_entries"[..], &[0x80u8][..])])
+            .is_err());
+        assert!(TableProperties::decode([(&b"lsm.property_collectors"[..], &[5, b'a'][..])])
+            .is_err());
+        assert_eq!(TableProperties::default().compression_ratio(), 1.0);
+    }
+}
@@ -0,0 +1,873 @@
+use std::cmp::Ordering;
+use std::sync::{Arc, OnceLock};
+use crate::comparator::{check_comparator_name, BytewiseComparator, Comparator};
+use crate::filesystem::Path;
+use crate::iterator::InternalIterator;
+use crate::memtable::range_tombstone::FragmentedRangeTombstones;
+use crate::sst::block_reader::{BlockIter, BlockReader};
+use crate::sst::filter_block::FilterBlockReader;
+use crate::sst::format::{read_block, BlockHandle, Footer, BLOCK_TRAILER_SIZE,
+    FILTER_BLOCK_NAME_PREFIX, RANGE_DEL_BLOCK_NAME, TABLE_SETTINGS_BLOCK_NAME};
+use crate::sst::lsm_error::DataStoreError;
+use crate::sst::options::{ChecksumType, ReadOptions, TableOptions, TableSettings};
+use crate::sst::properties::{TableProperties, PROPERTIES_BLOCK_NAME};
+
+/// (begin, end) of a range deletion, deleting the keys from 'begin',
+/// inclusive, to 'end', exclusive.
+pub type RangeTombstone = (Vec<u8>, Vec<u8>);
+
+/// Read side of a table written by TableBuilder. Opening a table reads
+/// the footer, the index block, the properties and the settings the table
+/// was built with, verifying their checksums. If the table has a filter built by the
+/// filter policy of the reader, it is read as well, and consulted before
+/// reading data blocks for point lookups. Range deletions are read at open
+/// too. Data blocks are read from the file on demand.
+pub struct Table {
+    path_: Arc<dyn Path>,
+    options_: TableOptions,
+    /// Checksum of the block trailers, as recorded in the footer.
+    checksum_: ChecksumType,
+    settings_: TableSettings,
+    properties_: TableProperties,
+    index_block_: BlockReader,
+    filter_: Option<FilterBlockReader>,
+    /// (begin, end) of the range deletions, in the order of 'begin'.
+    range_deletions_: Vec<RangeTombstone>,
+    /// 'range_deletions_' fragmented by the first lookup which needed them.
+    range_tombstones_: OnceLock<FragmentedRangeTombstones>,
+    file_size_: u64,
+}
+
+impl Table {
+    /// Opens the table at 'path'. Only the comparator and filter policy of
+    /// 'options' are used, the other settings are read from the table.
+    /// Fails with
+    /// DataStoreError::InvalidHeader if the file is not a table or the
+    /// table was built with a different comparator, and with
+    /// DataStoreError::Corruption if the footer points outside of the table.
+    pub fn open(options: TableOptions, path: Arc<dyn Path>)
+        -> Result<Table, DataStoreError> {
+        let file_size = path.get_file_system().file_size(path.as_ref())?;
+        if file_size < Footer::ENCODED_LENGTH as u64 {
+            return Err(DataStoreError::InvalidHeader {
+                expected: format!("table of at least {} bytes", Footer::ENCODED_LENGTH),
+                found: format!("{} bytes", file_size),
+            });
+        }
+        let mut footer = vec![0; Footer::ENCODED_LENGTH];
+        let footer_offset = file_size - Footer::ENCODED_LENGTH as u64;
+        path.get_file_system().seek_read(path.as_ref(), footer_offset, &mut footer)?;
+        let footer = Footer::decode_from(&footer)?;
+        for handle in [&footer.metaindex_handle, &footer.index_handle] {
+            Self::check_footer_handle(path.as_ref(), handle, footer_offset)?;
+        }
+        let metaindex = BlockReader::read_from(path.as_ref(), &footer.metaindex_handle,
+            file_size, footer.checksum, true, Arc::new(BytewiseComparator))?;
+        let settings = Self::read_settings(path.as_ref(), file_size, &footer, &metaindex)?;
+        check_comparator_name(options.comparator.as_ref(), &settings.comparator_name)?;
+        let filter = Self::read_filter(path.as_ref(), file_size, &footer, &metaindex,
+            &options)?;
+        let properties = Self::read_properties(path.as_ref(), file_size, &footer,
+            &metaindex)?;
+        let range_deletions = Self::read_range_deletions(path.as_ref(), file_size, &footer,
+            &metaindex, &options)?;
+        let index_block = BlockReader::read_from(path.as_ref(), &footer.index_handle,
+            file_size, footer.checksum, true, options.comparator.clone())?;
+        return Ok(Table {
+            path_: path,
+            options_: options,
+            checksum_: footer.checksum,
+            settings_: settings,
+            properties_: properties,
+            index_block_: index_block,
+            filter_: filter,
+            range_deletions_: range_deletions,
+            range_tombstones_: OnceLock::new(),
+            file_size_: file_size,
+        });
+    }
+
+    /// Fails with DataStoreError::Corruption unless the block at 'handle',
+    /// along with its trailer, ends before the footer at 'footer_offset'.
+    fn check_footer_handle(path: &dyn Path, handle: &BlockHandle, footer_offset: u64)
+        -> Result<(), DataStoreError> {
+        let end = handle.offset.checked_add(handle.size)
+            .and_then(|end| end.checked_add(BLOCK_TRAILER_SIZE as u64));
+        if end.is_none_or(|end| end > footer_offset) {
+            return Err(DataStoreError::Corruption {
+                file: path.to_str().unwrap_or("").to_string(),
+                offset: footer_offset,
+                reason: format!("bad footer block handle {:?}", handle),
+            });
+        }
+        return Ok(());
+    }
+
+    /// Handle of the meta block named 'name', if any.
+    fn find_meta_block(metaindex: &BlockReader, name: &str)
+        -> Result<Option<BlockHandle>, DataStoreError> {
+        let mut iter = metaindex.iter();
+        iter.seek(name.as_bytes())?;
+        if !iter.valid() || iter.key() != name.as_bytes() {
+            return Ok(None);
+        }
+        return Ok(Some(BlockHandle::decode_from(&mut iter.value())?));
+    }
+
+    fn read_settings(path: &dyn Path, file_size: u64, footer: &Footer,
+        metaindex: &BlockReader) -> Result<TableSettings, DataStoreError> {
+        let handle = Self::find_meta_block(metaindex, TABLE_SETTINGS_BLOCK_NAME)?
+            .ok_or_else(|| DataStoreError::InvalidHeader {
+                expected: format!("{} meta block", TABLE_SETTINGS_BLOCK_NAME),
+                found: "no such meta block".to_string(),
+            })?;
+        let settings_block = BlockReader::read_from(path, &handle, file_size,
+            footer.checksum, true, Arc::new(BytewiseComparator))?;
+        let mut iter = settings_block.iter();
+        iter.seek_to_first()?;
+        if !iter.valid() {
+            return Err(DataStoreError::MalformedBlock {
+                offset: handle.offset as usize,
+                reason: "empty table settings block".to_string(),
+            });
+        }
+        return TableSettings::decode(iter.value());
+    }
+
+    fn read_properties(path: &dyn Path, file_size: u64, footer: &Footer,
+        metaindex: &BlockReader) -> Result<TableProperties, DataStoreError> {
+        let handle = Self::find_meta_block(metaindex, PROPERTIES_BLOCK_NAME)?
+            .ok_or_else(|| DataStoreError::InvalidHeader {
+                expected: format!("{} meta block", PROPERTIES_BLOCK_NAME),
+                found: "no such meta block".to_string(),
+            })?;
+        let properties_block = BlockReader::read_from(path, &handle, file_size,
+            footer.checksum, true, Arc::new(BytewiseComparator))?;
+        let mut entries = vec!();
+        let mut iter = properties_block.iter();
+        iter.seek_to_first()?;
+        while iter.valid() {
+            entries.push((iter.key().to_vec(), iter.value().to_vec()));
+            iter.next()?;
+        }
+        return TableProperties::decode(entries.iter()
+            .map(|(name, value)| (&name[..], &value[..])));
+    }
+
+    fn read_range_deletions(path: &dyn Path, file_size: u64, footer: &Footer,
+        metaindex: &BlockReader, options: &TableOptions)
+        -> Result<Vec<RangeTombstone>, DataStoreError> {
+        let mut range_deletions = vec!();
+        if let Some(handle) = Self::find_meta_block(metaindex, RANGE_DEL_BLOCK_NAME)? {
+            let block = BlockReader::read_from(path, &handle, file_size, footer.checksum,
+                true, options.comparator.clone())?;
+            let mut iter = block.iter();
+            iter.seek_to_first()?;
+            while iter.valid() {
+                range_deletions.push((iter.key().to_vec(), iter.value().to_vec()));
+                iter.next()?;
+            }
+        }
+        return Ok(range_deletions);
+    }
+
+    /// Reads the filter block built by the filter policy of 'options', if
+    /// any. Filters built by other policies are ignored.
+    fn read_filter(path: &dyn Path, file_size: u64, footer: &Footer,
+        metaindex: &BlockReader, options: &TableOptions)
+        -> Result<Option<FilterBlockReader>, DataStoreError> {
+        let policy = match options.filter_policy.as_ref() {
+            Some(policy) => policy,
+            None => return Ok(None),
+        };
+        let name = format!("{}{}", FILTER_BLOCK_NAME_PREFIX, policy.name());
+        return match Self::find_meta_block(metaindex, &name)? {
+            Some(handle) => {
+                let data = read_block(path, &handle, file_size, footer.checksum, true)?;
+                Ok(Some(FilterBlockReader::new(policy.clone(), data)))
+            }
+            None => Ok(None),
+        };
+    }
+
+    /// Statistics of the table and user collected properties.
+    #[inline(always)]
+    pub fn properties(&self) -> &TableProperties {
+        &self.properties_
+    }
+
+    /// Whether the table has a filter usable by the reader.
+    #[inline(always)]
+    pub fn has_filter(&self) -> bool {
+        self.filter_.is_some()
+    }
+
+    /// Settings the table was built with.
+    #[inline(always)]
+    pub fn settings(&self) -> &TableSettings {
+        &self.settings_
+    }
+
+    #[inline(always)]
+    pub fn file_size(&self) -> u64 {
+        self.file_size_
+    }
+
+    /// (begin, end) of the range deletions of the table, in the order of
+    /// 'begin'.
+    #[inline(always)]
+    pub fn range_deletions(&self) -> &[RangeTombstone] {
+        &self.range_deletions_
+    }
+
+    /// Range deletions of the table fragmented for lookups of user keys
+    /// ordered by 'user_comparator', which must be the same on every call.
+    /// Built on first use. Fails with DataStoreError::Corruption if a range
+    /// deletion does not start with an internal key.
+    pub fn range_tombstones(&self, user_comparator: &Arc<dyn Comparator>)
+        -> Result<&FragmentedRangeTombstones, DataStoreError> {
+        if let Some(tombstones) = self.range_tombstones_.get() {
+            return Ok(tombstones);
+        }
+        let tombstones = FragmentedRangeTombstones::new(user_comparator.clone(),
+            self.range_deletions_.iter().map(|(begin, end)| (&begin[..], &end[..])))
+            .map_err(|error| DataStoreError::Corruption {
+                file: self.path_.to_str().unwrap_or("").to_string(),
+                offset: 0,
+                reason: error.to_string(),
+            })?;
+        return Ok(self.range_tombstones_.get_or_init(|| tombstones));
+    }
+
+    /// Returns false if the filter of the table tells that 'key' is not
+    /// in the table. The filter is checked for the data block which would
+    /// hold 'key'.
+    pub fn key_may_match(&self, key: &[u8]) -> Result<bool, DataStoreError> {
+        let filter = match self.filter_.as_ref() {
+            Some(filter) => filter,
+            None => return Ok(true),
+        };
+        let mut index_iter = self.index_block_.iter();
+        index_iter.seek(key)?;
+        if !index_iter.valid() {
+            return Ok(false);
+        }
+        let handle = BlockHandle::decode_from(&mut index_iter.value())?;
+        return Ok(filter.key_may_match(handle.offset, key));
+    }
+
+    /// Returns the value stored against 'key', if any. Reads at most one
+    /// data block, and none if the filter rules the key out.
+    pub fn get(&self, read_options: &ReadOptions, key: &[u8])
+        -> Result<Option<Vec<u8>>, DataStoreError> {
+        let mut index_iter = self.index_block_.iter();
+        index_iter.seek(key)?;
+        if !index_iter.valid() {
+            return Ok(None);
+        }
+        let handle = BlockHandle::decode_from(&mut index_iter.value())?;
+        if let Some(filter) = self.filter_.as_ref() {
+            if !filter.key_may_match(handle.offset, key) {
+                return Ok(None);
+            }
+        }
+        let block = self.read_data_block(read_options, &handle)?;
+        let mut iter = block.iter();
+        iter.seek(key)?;
+        if iter.valid()
+            && self.options_.comparator.compare(iter.key(), key) == Ordering::Equal {
+            return Ok(Some(iter.value().to_vec()));
+        }
+        return Ok(None);
+    }
+
+    /// Iterator over all the entries of the table.
+    pub fn iter(&self, read_options: &ReadOptions) -> TableIterator<'_> {
+        TableIterator {
+            table_: self,
+            read_options_: *read_options,
+            index_iter_: self.index_block_.iter(),
+            data_iter_: None,
+        }
+    }
+
+    fn read_data_block(&self, read_options: &ReadOptions, handle: &BlockHandle)
+        -> Result<BlockReader, DataStoreError> {
+        BlockReader::read_from(self.path_.as_ref(), handle, self.file_size_, self.checksum_,
+            read_options.verify_checksums, self.options_.comparator.clone())
+    }
+}
+
+/// Two level iterator over the entries of a table. The index iterator
+/// points at the handle of the current data block, and the data iterator
+/// at the current entry within that block.
+pub struct TableIterator<'a> {
+    table_: &'a Table,
+    read_options_: ReadOptions,
+    index_iter_: BlockIter,
+    /// Iterator over the data block 'index_iter_' points at. None if
+    /// 'index_iter_' is invalid.
+    data_iter_: Option<BlockIter>,
+}
+
+impl TableIterator<'_> {
+    #[inline(always)]
+    pub fn valid(&self) -> bool {
+        self.data_iter_.as_ref().is_some_and(|iter| iter.valid())
+    }
+
+    pub fn seek_to_first(&mut self) -> Result<(), DataStoreError> {
+        self.index_iter_.seek_to_first()?;
+        self.init_data_block()?;
+        if let Some(iter) = self.data_iter_.as_mut() {
+            iter.seek_to_first()?;
+        }
+        return self.skip_empty_data_blocks();
+    }
+
+    /// Positions at the first entry with key at or after 'target'.
+    pub fn seek(&mut self, target: &[u8]) -> Result<(), DataStoreError> {
+        self.index_iter_.seek(target)?;
+        self.init_data_block()?;
+        if let Some(iter) = self.data_iter_.as_mut() {
+            iter.seek(target)?;
+        }
+        return self.skip_empty_data_blocks();
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<(), DataStoreError> {
+        assert!(self.valid());
+        self.data_iter_.as_mut().unwrap().next()?;
+        return self.skip_empty_data_blocks();
+    }
+
+    #[inline(always)]
+    pub fn key(&self) -> &[u8] {
+        self.data_iter_.as_ref().unwrap().key()
+    }
+
+    #[inline(always)]
+    pub fn value(&self) -> &[u8] {
+        self.data_iter_.as_ref().unwrap().value()
+    }
+
+    /// Points 'data_iter_' at the block 'index_iter_' points at.
+    fn init_data_block(&mut self) -> Result<(), DataStoreError> {
+        if !self.index_iter_.valid() {
+            self.data_iter_ = None;
+            return Ok(());
+        }
+        let handle = BlockHandle::decode_from(&mut self.index_iter_.value())?;
+        self.data_iter_ = Some(self.table_.read_data_block(&self.read_options_,
+            &handle)?.iter());
+        return Ok(());
+    }
+
+    /// Moves to the first entry of the following data blocks while the
+    /// current data block is exhausted.
+    fn skip_empty_data_blocks(&mut self) -> Result<(), DataStoreError> {
+        while self.data_iter_.as_ref().is_some_and(|iter| !iter.valid()) {
+            self.index_iter_.next()?;
+            self.init_data_block()?;
+            if let Some(iter) = self.data_iter_.as_mut() {
+                iter.seek_to_first()?;
+            }
+        }
+        return Ok(());
+    }
+}
+
+impl InternalIterator for TableIterator<'_> {
+    fn valid(&self) -> bool {
+        TableIterator::valid(self)
+    }
+
+    fn seek_to_first(&mut self) -> Result<(), DataStoreError> {
+        TableIterator::seek_to_first(self)
+    }
+
+    fn seek(&mut self, target: &[u8]) -> Result<(), DataStoreError> {
+        TableIterator::seek(self, target)
+    }
+
+    fn next(&mut self) -> Result<(), DataStoreError> {
+        TableIterator::next(self)
+    }
+
+    fn key(&self) -> &[u8] {
+        TableIterator::key(self)
+    }
+
+    fn value(&self) -> &[u8] {
+        TableIterator::value(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+    use crate::comparator::Comparator;
+    use crate::filesystem::{LocalPath, Path};
+    use crate::memtable::internal_key::{InternalKey, InternalKeyComparator, ValueType};
+    use crate::sst::filter::BloomFilterPolicy;
+    use crate::sst::format::{BlockHandle, Footer, BLOCK_TRAILER_SIZE};
+    use crate::sst::lsm_error::DataStoreError;
+    use crate::sst::options::{ChecksumType, CompressionType, ReadOptions, TableOptions};
+    use crate::sst::properties::{TablePropertiesCollector,
+        TablePropertiesCollectorFactory};
+    use crate::sst::table::Table;
+    use crate::sst::table_builder::TableBuilder;
+
+    fn build_table(dir: &TempDir, name: &str, options: &TableOptions,
+        num_entries: usize) -> Arc<dyn Path> {
+        let path: Arc<dyn Path> = Arc::new(
+            LocalPath::from_std_path(dir.path().join(name).as_path()));
+        let mut builder = TableBuilder::new(options.clone(), path.as_ref()).unwrap();
+        for i in 0..num_entries {
+            assert!(builder.add(format!("key{:06}", i * 2).as_bytes(),
+                format!("value{}", i).as_bytes()).is_ok());
+        }
+        assert!(builder.finish().is_ok());
+        return path;
+    }
+
+    fn small_blocks() -> TableOptions {
+        TableOptions {
+            block_size: 512,
+            ..TableOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_get() {
+        let tmp_dir = TempDir::new().unwrap();
+        let options = small_blocks();
+        let path = build_table(&tmp_dir, "test.sst", &options, 1000);
+        let table = Table::open(options.clone(), path).unwrap();
+        assert_eq!(table.settings(), &options.settings());
+        for i in 0..1000 {
+            assert_eq!(table.get(&ReadOptions::default(), format!("key{:06}", i * 2).as_bytes()).unwrap(),
+                Some(format!("value{}", i).into_bytes()));
+            // Keys in between are missing.
+            assert_eq!(table.get(&ReadOptions::default(), format!("key{:06}", i * 2 + 1).as_bytes()).unwrap(),
+                None);
+        }
+        assert_eq!(table.get(&ReadOptions::default(), b"a").unwrap(), None);
+        assert_eq!(table.get(&ReadOptions::default(), b"z").unwrap(), None);
+    }
+
+    #[test]
+    fn test_iterator() {
+        let tmp_dir = TempDir::new().unwrap();
+        let options = small_blocks();
+        let path = build_table(&tmp_dir, "test.sst", &options, 1000);
+        let table = Table::open(options, path).unwrap();
+        let mut iter = table.iter(&ReadOptions::default());
+        assert!(!iter.valid());
+        assert!(iter.seek_to_first().is_ok());
+        for i in 0..1000 {
+            assert!(iter.valid());
+            assert_eq!(iter.key(), format!("key{:06}", i * 2).as_bytes());
+            assert_eq!(iter.value(), format!("value{}", i).as_bytes());
+            assert!(iter.next().is_ok());
+        }
+        assert!(!iter.valid());
+        // Range scan starting in between keys.
+        assert!(iter.seek(b"key000501").is_ok());
+        for i in 251..1000 {
+            assert_eq!(iter.key(), format!("key{:06}", i * 2).as_bytes());
+            assert!(iter.next().is_ok());
+        }
+        assert!(!iter.valid());
+        assert!(iter.seek(b"key999999").is_ok());
+        assert!(!iter.valid());
+    }
+
+    #[test]
+    fn test_empty_table() {
+        let tmp_dir = TempDir::new().unwrap();
+        let options = TableOptions::default();
+        let path = build_table(&tmp_dir, "empty.sst", &options, 0);
+        let table = Table::open(options, path).unwrap();
+        let mut iter = table.iter(&ReadOptions::default());
+        assert!(iter.seek_to_first().is_ok());
+        assert!(!iter.valid());
+        assert_eq!(table.get(&ReadOptions::default(), b"key").unwrap(), None);
+    }
+
+    #[test]
+    fn test_bad_magic() {
+        let tmp_dir = TempDir::new().unwrap();
+        let options = TableOptions::default();
+        let path = build_table(&tmp_dir, "test.sst", &options, 10);
+        let mut data = std::fs::read(path.to_str().unwrap()).unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 0xff;
+        std::fs::write(path.to_str().unwrap(), &data).unwrap();
+        assert!(matches!(Table::open(options.clone(), path),
+            Err(DataStoreError::InvalidHeader { .. })));
+        // File too short to be a table.
+        let short: Arc<dyn Path> = Arc::new(
+            LocalPath::from_std_path(tmp_dir.path().join("short.sst").as_path()));
+        std::fs::write(short.to_str().unwrap(), b"not a table").unwrap();
+        assert!(matches!(Table::open(options, short),
+            Err(DataStoreError::InvalidHeader { .. })));
+    }
+
+    #[test]
+    fn test_comparator_mismatch() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = build_table(&tmp_dir, "test.sst", &TableOptions::default(), 10);
+        let user_comparator = TableOptions::default().comparator;
+        let comparator: Arc<dyn Comparator> =
+            Arc::new(InternalKeyComparator::new(user_comparator));
+        let options = TableOptions {
+            comparator,
+            ..TableOptions::default()
+        };
+        assert!(matches!(Table::open(options, path),
+            Err(DataStoreError::InvalidHeader { .. })));
+    }
+
+    #[test]
+    fn test_checksum_mismatch() {
+        for checksum in [ChecksumType::Crc32c, ChecksumType::XxHash64] {
+            let tmp_dir = TempDir::new().unwrap();
+            let options = TableOptions {
+                checksum,
+                ..small_blocks()
+            };
+            let path = build_table(&tmp_dir, "test.sst", &options, 1000);
+            // Flip a bit in the value of the first entry.
+            let mut data = std::fs::read(path.to_str().unwrap()).unwrap();
+            let pos = data.windows(6).position(|w| w == b"value0").unwrap();
+            data[pos] ^= 0x01;
+            std::fs::write(path.to_str().unwrap(), &data).unwrap();
+            let table = Table::open(options, path.clone()).unwrap();
+            match table.get(&ReadOptions::default(), b"key000000") {
+                Err(DataStoreError::Corruption { file, offset, .. }) => {
+                    assert_eq!(file, path.to_str().unwrap());
+                    assert_eq!(offset, 0);
+                }
+                result => panic!("Corruption is not detected {:?}", result),
+            }
+            let mut iter = table.iter(&ReadOptions::default());
+            assert!(matches!(iter.seek_to_first(),
+                Err(DataStoreError::Corruption { .. })));
+            // Other blocks can still be read.
+            assert!(table.get(&ReadOptions::default(), b"key001998").unwrap().is_some());
+            // Corruption is not detected unless checksums are verified.
+            let read_options = ReadOptions { verify_checksums: false };
+            assert_eq!(table.get(&read_options, b"key000000").unwrap(),
+                Some(b"walue0".to_vec()));
+        }
+    }
+
+    #[test]
+    fn test_corrupted_index() {
+        let tmp_dir = TempDir::new().unwrap();
+        let options = small_blocks();
+        let path = build_table(&tmp_dir, "test.sst", &options, 100);
+        let mut data = std::fs::read(path.to_str().unwrap()).unwrap();
+        // Index block is right before the footer and ends with its trailer
+        // and restart array.
+        let pos = data.len() - Footer::ENCODED_LENGTH - BLOCK_TRAILER_SIZE - 12;
+        data[pos] ^= 0x01;
+        std::fs::write(path.to_str().unwrap(), &data).unwrap();
+        assert!(matches!(Table::open(options, path),
+            Err(DataStoreError::Corruption { .. })));
+    }
+
+    #[test]
+    fn test_corrupted_footer_handles() {
+        let tmp_dir = TempDir::new().unwrap();
+        let options = small_blocks();
+        let path = build_table(&tmp_dir, "test.sst", &options, 100);
+        let data = std::fs::read(path.to_str().unwrap()).unwrap();
+        let footer_offset = data.len() - Footer::ENCODED_LENGTH;
+        let footer = Footer::decode_from(&data[footer_offset..]).unwrap();
+        // Sizes past the end of the file fail before any allocation.
+        for size in [1 << 40, u64::MAX - 1] {
+            let mut corrupted = data.clone();
+            let index_handle = BlockHandle::new(footer.index_handle.offset, size);
+            corrupted[footer_offset..].copy_from_slice(&Footer {
+                index_handle,
+                ..footer
+            }.encode());
+            std::fs::write(path.to_str().unwrap(), &corrupted).unwrap();
+            match Table::open(options.clone(), path.clone()) {
+                Err(DataStoreError::Corruption { file, offset, .. }) => {
+                    assert_eq!(file, path.to_str().unwrap());
+                    assert_eq!(offset, footer_offset as u64);
+                }
+                result => panic!("Corruption is not detected {:?}", result.is_ok()),
+            }
+        }
+        // A metaindex block running into the footer.
+        let mut corrupted = data.clone();
+        let metaindex_handle = BlockHandle::new(footer.metaindex_handle.offset,
+            (footer_offset - BLOCK_TRAILER_SIZE) as u64 - footer.metaindex_handle.offset + 1);
+        corrupted[footer_offset..].copy_from_slice(&Footer {
+            metaindex_handle,
+            ..footer
+        }.encode());
+        std::fs::write(path.to_str().unwrap(), &corrupted).unwrap();
+        assert!(matches!(Table::open(options, path),
+            Err(DataStoreError::Corruption { .. })));
+    }
+
+    fn filter_options(whole_table_filter: bool) -> TableOptions {
+        TableOptions {
+            filter_policy: Some(Arc::new(BloomFilterPolicy::new(10))),
+            whole_table_filter,
+            ..small_blocks()
+        }
+    }
+
+    #[test]
+    fn test_filter() {
+        for whole_table_filter in [false, true] {
+            let tmp_dir = TempDir::new().unwrap();
+            let options = filter_options(whole_table_filter);
+            let path = build_table(&tmp_dir, "test.sst", &options, 1000);
+            let table = Table::open(options, path.clone()).unwrap();
+            assert!(table.has_filter());
+            let read_options = ReadOptions::default();
+            for i in 0..1000 {
+                assert_eq!(table.get(&read_options, format!("key{:06}", i * 2).as_bytes())
+                    .unwrap(), Some(format!("value{}", i).into_bytes()));
+            }
+            // Corrupt every data block. Lookups of missing keys ruled out by
+            // the filter do not read them.
+            let mut data = std::fs::read(path.to_str().unwrap()).unwrap();
+            let mut pos = 0;
+            while let Some(offset) = data[pos..].windows(5).position(|w| w == b"value") {
+                pos += offset;
+                data[pos] = b'w';
+            }
+            std::fs::write(path.to_str().unwrap(), &data).unwrap();
+            let mut filtered = 0;
+            for i in 0..1000 {
+                match table.get(&read_options, format!("key{:06}", i * 2 + 1).as_bytes()) {
+                    Ok(None) => filtered += 1,
+                    Err(DataStoreError::Corruption { .. }) => (),
+                    result => panic!("Unexpected result {:?}", result),
+                }
+            }
+            assert!(filtered > 950, "only {} lookups were filtered", filtered);
+        }
+    }
+
+    #[test]
+    fn test_filter_policy_mismatch() {
+        let tmp_dir = TempDir::new().unwrap();
+        let options = filter_options(false);
+        let path = build_table(&tmp_dir, "test.sst", &options, 100);
+        // Readers without a filter policy ignore the filter.
+        let table = Table::open(small_blocks(), path.clone()).unwrap();
+        assert!(!table.has_filter());
+        assert!(table.get(&ReadOptions::default(), b"key000000").unwrap().is_some());
+        // Tables without a filter are read without one.
+        let path = build_table(&tmp_dir, "nofilter.sst", &small_blocks(), 100);
+        let table = Table::open(options, path).unwrap();
+        assert!(!table.has_filter());
+        assert!(table.get(&ReadOptions::default(), b"key000000").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_compression() {
+        let json_value = |i: usize| format!("{{\"id\": {}, \"name\": \"user\", \
+            \"tags\": [\"a\", \"b\", \"c\"], \"active\": true}}", i);
+        let mut sizes = vec!();
+        for compression in [CompressionType::NoCompression, CompressionType::Snappy,
+            CompressionType::Lz4, CompressionType::Zstd] {
+            let tmp_dir = TempDir::new().unwrap();
+            let options = TableOptions {
+                compression,
+                ..TableOptions::default()
+            };
+            let path: Arc<dyn Path> = Arc::new(
+                LocalPath::from_std_path(tmp_dir.path().join("test.sst").as_path()));
+            let mut builder = TableBuilder::new(options.clone(), path.as_ref()).unwrap();
+            for i in 0..1000 {
+                assert!(builder.add(format!("key{:06}", i).as_bytes(),
+                    json_value(i).as_bytes()).is_ok());
+            }
+            sizes.push(builder.finish().unwrap());
+            let table = Table::open(TableOptions::default(), path).unwrap();
+            assert_eq!(table.settings().compression, compression);
+            let mut iter = table.iter(&ReadOptions::default());
+            assert!(iter.seek_to_first().is_ok());
+            for i in 0..1000 {
+                assert_eq!(iter.key(), format!("key{:06}", i).as_bytes());
+                assert_eq!(iter.value(), json_value(i).as_bytes());
+                assert!(iter.next().is_ok());
+            }
+            assert!(!iter.valid());
+        }
+        for size in &sizes[1..] {
+            assert!(*size < sizes[0] / 2, "{:?}", sizes);
+        }
+    }
+
+    /// Records the smallest and largest event timestamp, stored as the
+    /// first 8 bytes of values.
+    struct TimestampCollector {
+        min_: u64,
+        max_: u64,
+    }
+
+    impl TablePropertiesCollector for TimestampCollector {
+        fn add(&mut self, _key: &[u8], value: &[u8]) {
+            let timestamp = u64::from_le_bytes(value[..8].try_into().unwrap());
+            self.min_ = self.min_.min(timestamp);
+            self.max_ = self.max_.max(timestamp);
+        }
+
+        fn finish(&mut self) -> BTreeMap<String, Vec<u8>> {
+            BTreeMap::from([
+                ("app.min_timestamp".to_string(), self.min_.to_le_bytes().to_vec()),
+                ("app.max_timestamp".to_string(), self.max_.to_le_bytes().to_vec()),
+            ])
+        }
+
+        fn name(&self) -> &str {
+            "TimestampCollector"
+        }
+    }
+
+    struct TimestampCollectorFactory;
+
+    impl TablePropertiesCollectorFactory for TimestampCollectorFactory {
+        fn create_collector(&self) -> Box<dyn TablePropertiesCollector> {
+            Box::new(TimestampCollector { min_: u64::MAX, max_: 0 })
+        }
+    }
+
+    #[test]
+    fn test_properties() {
+        let tmp_dir = TempDir::new().unwrap();
+        let options = TableOptions {
+            compression: CompressionType::Snappy,
+            properties_collectors: vec![Arc::new(TimestampCollectorFactory)],
+            ..small_blocks()
+        };
+        let path: Arc<dyn Path> = Arc::new(
+            LocalPath::from_std_path(tmp_dir.path().join("test.sst").as_path()));
+        let mut builder = TableBuilder::new(options.clone(), path.as_ref()).unwrap();
+        let mut raw_value_size = 0;
+        for i in 0..100u64 {
+            let value = [&(1000 + i * 7).to_le_bytes()[..], &[b'x'; 100]].concat();
+            raw_value_size += value.len() as u64;
+            assert!(builder.add(format!("key{:03}", i).as_bytes(), &value).is_ok());
+        }
+        let file_size = builder.finish().unwrap();
+        let expected = builder.properties().clone();
+        let table = Table::open(options, path).unwrap();
+        let properties = table.properties();
+        assert_eq!(properties, &expected);
+        assert_eq!(properties.num_entries, 100);
+        assert_eq!(properties.num_deletions, 0);
+        assert_eq!(properties.raw_key_size, 600);
+        assert_eq!(properties.raw_value_size, raw_value_size);
+        assert_eq!(properties.smallest_key, b"key000");
+        assert_eq!(properties.largest_key, b"key099");
+        assert!(properties.num_data_blocks > 1);
+        assert!(properties.data_size < file_size);
+        assert!(properties.compression_ratio() > 2.0);
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+        assert!(properties.creation_time <= now && properties.creation_time + 60 > now);
+        assert_eq!(properties.property_collectors, vec!("TimestampCollector".to_string()));
+        assert_eq!(properties.user_collected.get("app.min_timestamp"),
+            Some(&1000u64.to_le_bytes().to_vec()));
+        assert_eq!(properties.user_collected.get("app.max_timestamp"),
+            Some(&(1000 + 99 * 7u64).to_le_bytes().to_vec()));
+    }
+
+    #[test]
+    fn test_deletions_counted_for_internal_keys() {
+        let tmp_dir = TempDir::new().unwrap();
+        let user_comparator = TableOptions::default().comparator;
+        let options = TableOptions {
+            comparator: Arc::new(InternalKeyComparator::new(user_comparator)),
+            internal_keys: true,
+            ..TableOptions::default()
+        };
+        let path: Arc<dyn Path> = Arc::new(
+            LocalPath::from_std_path(tmp_dir.path().join("test.sst").as_path()));
+        let mut builder = TableBuilder::new(options.clone(), path.as_ref()).unwrap();
+        for i in 0..10 {
+            let value_type = if i % 3 == 0 { ValueType::Deletion } else { ValueType::Value };
+            let key = InternalKey::new(format!("key{}", i).as_bytes(), 100, value_type);
+            assert!(builder.add(key.encode(), b"").is_ok());
+        }
+        assert!(builder.finish().is_ok());
+        let table = Table::open(options, path).unwrap();
+        assert_eq!(table.properties().num_entries, 10);
+        assert_eq!(table.properties().num_deletions, 4);
+    }
+
+    #[test]
+    fn test_range_deletions() {
+        let tmp_dir = TempDir::new().unwrap();
+        let user_comparator = TableOptions::default().comparator;
+        let options = TableOptions {
+            comparator: Arc::new(InternalKeyComparator::new(user_comparator.clone())),
+            internal_keys: true,
+            ..TableOptions::default()
+        };
+        let path: Arc<dyn Path> = Arc::new(
+            LocalPath::from_std_path(tmp_dir.path().join("test.sst").as_path()));
+        let mut builder = TableBuilder::new(options.clone(), path.as_ref()).unwrap();
+        for i in 0..10u64 {
+            let key = InternalKey::new(format!("key{}", i).as_bytes(), 100 + i,
+                ValueType::Value);
+            assert!(builder.add(key.encode(), b"v").is_ok());
+        }
+        let deletions = [(InternalKey::new(b"a", 300, ValueType::RangeDeletion), b"key3"),
+            (InternalKey::new(b"key5", 50, ValueType::RangeDeletion), b"key7")];
+        for (begin, end) in deletions.iter() {
+            assert!(builder.add_range_deletion(begin.encode(), *end).is_ok());
+        }
+        assert!(builder.finish().is_ok());
+        let table = Table::open(options, path).unwrap();
+        let properties = table.properties();
+        assert_eq!(properties.num_entries, 10);
+        assert_eq!(properties.num_range_deletions, 2);
+        assert_eq!((properties.smallest_sequence, properties.largest_sequence), (50, 300));
+        let expected: Vec<(Vec<u8>, Vec<u8>)> = deletions.iter()
+            .map(|(begin, end)| (begin.encode().to_vec(), end.to_vec())).collect();
+        assert_eq!(table.range_deletions(), &expected[..]);
+        let tombstones = table.range_tombstones(&user_comparator).unwrap();
+        assert_eq!(tombstones.max_covering_tombstone_sequence(b"key2", 400), Some(300));
+        assert_eq!(tombstones.max_covering_tombstone_sequence(b"key6", 400), Some(50));
+        assert_eq!(tombstones.max_covering_tombstone_sequence(b"key7", 400), None);
+        // Range deletions are not entries.
+        let read_options = ReadOptions::default();
+        let mut iter = table.iter(&read_options);
+        assert!(iter.seek_to_first().is_ok());
+        assert_eq!(iter.key(), InternalKey::new(b"key0", 100, ValueType::Value).encode());
+    }
+
+    #[test]
+    fn test_malformed_range_deletion() {
+        let tmp_dir = TempDir::new().unwrap();
+        let options = TableOptions::default();
+        let path: Arc<dyn Path> = Arc::new(
+            LocalPath::from_std_path(tmp_dir.path().join("test.sst").as_path()));
+        let mut builder = TableBuilder::new(options.clone(), path.as_ref()).unwrap();
+        assert!(builder.add_range_deletion(b"a", b"b").is_ok());
+        assert!(builder.finish().is_ok());
+        let table = Table::open(options.clone(), path.clone()).unwrap();
+        match table.range_tombstones(&options.comparator) {
+            Err(DataStoreError::Corruption { file, .. }) => {
+                assert_eq!(file, path.to_str().unwrap());
+            }
+            result => panic!("Corruption is not detected {:?}", result.is_ok()),
+        }
+    }
+}
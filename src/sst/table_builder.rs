@@ -0,0 +1,450 @@
+use std::cmp::Ordering;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::comparator::BytewiseComparator;
+use crate::filesystem::Path;
+use crate::memtable::internal_key::{parse_internal_key, ValueType};
+use crate::sst::block::Block;
+use crate::sst::compression::compress_block;
+use crate::sst::filter_block::{FilterBlockBuilder, FILTER_BASE_LG,
+    WHOLE_TABLE_FILTER_BASE_LG};
+use crate::sst::format::{block_trailer, BlockHandle, Footer, BLOCK_TRAILER_SIZE,
+    FILTER_BLOCK_NAME_PREFIX, RANGE_DEL_BLOCK_NAME, TABLE_SETTINGS_BLOCK_NAME};
+use crate::sst::lsm_error::DataStoreError;
+use crate::sst::options::{CompressionType, TableOptions};
+use crate::sst::properties::{TableProperties, TablePropertiesCollector,
+    PROPERTIES_BLOCK_NAME};
+
+/// Builds a table from entries added in increasing key order. Entries
+/// are packed into data blocks of about 'block_size' bytes, which are
+/// written to the file as soon as they are full. 'finish' writes the
+/// meta blocks, the metaindex and index blocks and the footer.
+/// Statistics of the table, and properties recorded by the collectors of
+/// 'options', are written to the properties meta block.
+/// Check 'sst::format' for the layout of the table.
+pub struct TableBuilder<'a> {
+    options_: TableOptions,
+    path_: &'a dyn Path,
+    /// Size of the table written so far.
+    offset_: u64,
+    data_block_: Block,
+    index_block_: Block,
+    range_del_block_: Block,
+    /// Builds the filter block, if there is a filter policy.
+    filter_block_: Option<FilterBlockBuilder>,
+    /// Last key added to the table. Shortened to a separator when an index
+    /// entry is added.
+    last_key_: Vec<u8>,
+    properties_: TableProperties,
+    collectors_: Vec<Box<dyn TablePropertiesCollector>>,
+    /// Whether keys are internal keys, whose deletions are counted.
+    internal_keys_: bool,
+    /// Whether the index entry of the last written data block is yet to
+    /// be added. It is added once the first key of the next data block is
+    /// known, so that a short separator can be used as its key.
+    pending_index_entry_: bool,
+    /// Handle of the last written data block.
+    pending_handle_: BlockHandle,
+    finished_: bool,
+}
+
+impl<'a> TableBuilder<'a> {
+    /// Creates the file at 'path' and a builder writing the table to it.
+    /// Fails if the file already exists.
+    pub fn new(options: TableOptions, path: &'a dyn Path)
+        -> Result<TableBuilder<'a>, DataStoreError> {
+        path.get_file_system().create(path)?;
+        let filter_block = options.filter_policy.as_ref().map(|policy| {
+            let base_lg = if options.whole_table_filter {
+                WHOLE_TABLE_FILTER_BASE_LG
+            } else {
+                FILTER_BASE_LG
+            };
+            let mut builder = FilterBlockBuilder::new(policy.clone(), base_lg);
+            builder.start_block(0);
+            builder
+        });
+        let creation_time = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs()).unwrap_or(0);
+        let collectors: Vec<Box<dyn TablePropertiesCollector>> = options
+            .properties_collectors.iter()
+            .map(|factory| factory.create_collector())
+            .collect();
+        return Ok(TableBuilder {
+            internal_keys_: options.internal_keys,
+            properties_: TableProperties {
+                creation_time,
+                property_collectors: collectors.iter()
+                    .map(|collector| collector.name().to_string())
+                    .collect(),
+                ..TableProperties::default()
+            },
+            collectors_: collectors,
+            filter_block_: filter_block,
+            data_block_: Block::with_options(&options),
+            index_block_: Block::with_options(&Self::index_block_options(&options)),
+            range_del_block_: Block::with_options(&Self::index_block_options(&options)),
+            options_: options,
+            path_: path,
+            offset_: 0,
+            last_key_: vec!(),
+            pending_index_entry_: false,
+            pending_handle_: BlockHandle::default(),
+            finished_: false,
+        });
+    }
+
+    /// Index and metaindex blocks are searched with a binary search over
+    /// all their keys, so every entry is a restart point, and they are
+    /// never cut.
+    fn index_block_options(options: &TableOptions) -> TableOptions {
+        TableOptions {
+            block_size: usize::MAX,
+            block_restart_interval: 1,
+            ..options.clone()
+        }
+    }
+
+    /// Adds an entry to the table. 'key' must be after every key added
+    /// so far as per the comparator.
+    pub fn add(&mut self, key: &[u8], value: &[u8]) -> Result<(), DataStoreError> {
+        assert!(!self.finished_, "Table is already finished");
+        debug_assert!(self.properties_.num_entries == 0
+            || self.options_.comparator.compare(&self.last_key_, key) == Ordering::Less,
+            "Keys must be added in increasing order");
+        if self.pending_index_entry_ {
+            debug_assert!(self.data_block_.is_empty());
+            self.options_.comparator.find_shortest_separator(&mut self.last_key_, key);
+            self.add_index_entry()?;
+        }
+        match self.data_block_.add(key, value) {
+            Err(DataStoreError::BlockFull { .. }) => {
+                self.flush()?;
+                self.options_.comparator.find_shortest_separator(&mut self.last_key_, key);
+                self.add_index_entry()?;
+                self.data_block_.add(key, value)?;
+            }
+            result => result?,
+        }
+        if let Some(filter_block) = self.filter_block_.as_mut() {
+            filter_block.add_key(key);
+        }
+        for collector in self.collectors_.iter_mut() {
+            collector.add(key, value);
+        }
+        self.last_key_.clear();
+        self.last_key_.extend_from_slice(key);
+        self.update_properties(key, value);
+        return Ok(());
+    }
+
+    fn update_properties(&mut self, key: &[u8], value: &[u8]) {
+        let properties = &mut self.properties_;
+        if properties.num_entries == 0 {
+            properties.smallest_key = key.to_vec();
+        }
+        properties.largest_key.clear();
+        properties.largest_key.extend_from_slice(key);
+        properties.num_entries += 1;
+        properties.raw_key_size += key.len() as u64;
+        properties.raw_value_size += value.len() as u64;
+        if self.internal_keys_ && parse_internal_key(key)
+            .is_some_and(|parsed| parsed.value_type == ValueType::Deletion) {
+            properties.num_deletions += 1;
+        }
+        self.update_sequences(key);
+    }
+
+    /// Tracks the smallest and largest sequence numbers of internal keys.
+    fn update_sequences(&mut self, key: &[u8]) {
+        if !self.internal_keys_ {
+            return;
+        }
+        if let Some(parsed) = parse_internal_key(key) {
+            let properties = &mut self.properties_;
+            if properties.num_entries + properties.num_range_deletions == 1 {
+                properties.smallest_sequence = parsed.sequence;
+            }
+            properties.smallest_sequence = properties.smallest_sequence.min(parsed.sequence);
+            properties.largest_sequence = properties.largest_sequence.max(parsed.sequence);
+        }
+    }
+
+    /// Adds a deletion of the keys from 'begin', inclusive, to 'end',
+    /// exclusive, to the range deletion block. Range deletions are kept
+    /// apart from the entries and must be added in increasing order of
+    /// 'begin' as per the comparator.
+    pub fn add_range_deletion(&mut self, begin: &[u8], end: &[u8])
+        -> Result<(), DataStoreError> {
+        assert!(!self.finished_, "Table is already finished");
+        self.range_del_block_.add(begin, end)?;
+        self.properties_.num_range_deletions += 1;
+        self.update_sequences(begin);
+        return Ok(());
+    }
+
+    /// Writes the current data block to the file, if it has any entries.
+    /// Following entries go to a new data block.
+    pub fn flush(&mut self) -> Result<(), DataStoreError> {
+        assert!(!self.finished_, "Table is already finished");
+        if self.data_block_.is_empty() {
+            return Ok(());
+        }
+        let mut block = std::mem::replace(&mut self.data_block_,
+            Block::with_options(&self.options_));
+        let offset = self.offset_;
+        self.pending_handle_ = self.write_block(&mut block)?;
+        self.properties_.num_data_blocks += 1;
+        self.properties_.uncompressed_data_size += block.current_pos_ as u64;
+        self.properties_.data_size += self.offset_ - offset;
+        self.pending_index_entry_ = true;
+        if let Some(filter_block) = self.filter_block_.as_mut() {
+            filter_block.start_block(self.offset_);
+        }
+        return Ok(());
+    }
+
+    fn add_index_entry(&mut self) -> Result<(), DataStoreError> {
+        self.index_block_.add(&self.last_key_, &self.pending_handle_.encode())?;
+        self.pending_index_entry_ = false;
+        return Ok(());
+    }
+
+    /// Finishes 'block', compresses it and appends it to the file followed
+    /// by its trailer.
+    fn write_block(&mut self, block: &mut Block) -> Result<BlockHandle, DataStoreError> {
+        let (compression, contents) = compress_block(self.options_.compression,
+            block.finish());
+        return self.write_raw_block(&contents, compression);
+    }
+
+    fn write_raw_block(&mut self, contents: &[u8], compression: CompressionType)
+        -> Result<BlockHandle, DataStoreError> {
+        let handle = BlockHandle::new(self.offset_, contents.len() as u64);
+        let trailer = block_trailer(contents, compression, self.options_.checksum);
+        let filesystem = self.path_.get_file_system();
+        filesystem.append(self.path_, contents)?;
+        filesystem.append(self.path_, &trailer)?;
+        self.offset_ += (contents.len() + BLOCK_TRAILER_SIZE) as u64;
+        return Ok(handle);
+    }
+
+    /// Writes the remaining blocks and the footer. No entries can be added
+    /// afterwards. Returns the size of the table.
+    pub fn finish(&mut self) -> Result<u64, DataStoreError> {
+        self.flush()?;
+        self.finished_ = true;
+        // Meta blocks, whose handles are recorded in the metaindex block.
+        let filter_handle = match self.filter_block_.take() {
+            Some(mut filter_block) => Some(self.write_raw_block(filter_block.finish(),
+                CompressionType::NoCompression)?),
+            None => None,
+        };
+        for collector in self.collectors_.iter_mut() {
+            self.properties_.user_collected.extend(collector.finish());
+        }
+        let mut properties_block = Block::with_options(&TableOptions {
+            block_size: usize::MAX,
+            ..TableOptions::default()
+        });
+        for (name, value) in self.properties_.encode() {
+            properties_block.add(name.as_bytes(), &value)?;
+        }
+        let properties_handle = self.write_block(&mut properties_block)?;
+        let range_del_handle = if self.range_del_block_.is_empty() {
+            None
+        } else {
+            let mut range_del_block = std::mem::take(&mut self.range_del_block_);
+            Some(self.write_block(&mut range_del_block)?)
+        };
+        let mut settings_block = Block::with_options(&TableOptions {
+            block_size: usize::MAX,
+            ..TableOptions::default()
+        });
+        settings_block.add(b"settings", &self.options_.settings().encode())?;
+        let settings_handle = self.write_block(&mut settings_block)?;
+        let mut metaindex_block = Block::with_options(&TableOptions {
+            comparator: Arc::new(BytewiseComparator),
+            ..Self::index_block_options(&self.options_)
+        });
+        // Metaindex keys must be added in order. Filter block name sorts
+        // before the properties, range deletion and settings block names.
+        if let Some(filter_handle) = filter_handle {
+            let policy = self.options_.filter_policy.as_ref().unwrap();
+            let name = format!("{}{}", FILTER_BLOCK_NAME_PREFIX, policy.name());
+            metaindex_block.add(name.as_bytes(), &filter_handle.encode())?;
+        }
+        metaindex_block.add(PROPERTIES_BLOCK_NAME.as_bytes(),
+            &properties_handle.encode())?;
+        if let Some(range_del_handle) = range_del_handle {
+            metaindex_block.add(RANGE_DEL_BLOCK_NAME.as_bytes(),
+                &range_del_handle.encode())?;
+        }
+        metaindex_block.add(TABLE_SETTINGS_BLOCK_NAME.as_bytes(),
+            &settings_handle.encode())?;
+        let metaindex_handle = self.write_block(&mut metaindex_block)?;
+        // Index block.
+        if self.pending_index_entry_ {
+            self.options_.comparator.find_short_successor(&mut self.last_key_);
+            self.add_index_entry()?;
+        }
+        let mut index_block = std::mem::take(&mut self.index_block_);
+        let index_handle = self.write_block(&mut index_block)?;
+        let footer = Footer {
+            checksum: self.options_.checksum,
+            metaindex_handle,
+            index_handle,
+        };
+        let encoded = footer.encode();
+        self.path_.get_file_system().append(self.path_, &encoded)?;
+        self.offset_ += encoded.len() as u64;
+        return Ok(self.offset_);
+    }
+
+    /// Overrides the creation time recorded in the properties, in seconds
+    /// since the Unix epoch. Lets a table rewriting older tables keep the
+    /// age of their entries.
+    pub fn set_creation_time(&mut self, creation_time: u64) {
+        self.properties_.creation_time = creation_time;
+    }
+
+    #[inline(always)]
+    pub fn num_entries(&self) -> u64 {
+        self.properties_.num_entries
+    }
+
+    /// Properties of the table built so far. Complete after 'finish'.
+    #[inline(always)]
+    pub fn properties(&self) -> &TableProperties {
+        &self.properties_
+    }
+
+    /// Size of the table written so far. Equal to the final size of the
+    /// table after 'finish'.
+    #[inline(always)]
+    pub fn file_size(&self) -> u64 {
+        self.offset_
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+    use crate::filesystem::{LocalPath, Path};
+    use crate::sst::block_reader::BlockReader;
+    use crate::sst::format::{BlockHandle, Footer, BLOCK_TRAILER_SIZE,
+        TABLE_SETTINGS_BLOCK_NAME};
+    use crate::sst::options::{TableOptions, TableSettings};
+    use crate::sst::table_builder::TableBuilder;
+    use std::cmp::Ordering;
+
+    fn read_footer(path: &dyn Path, file_size: u64) -> Footer {
+        let mut buf = vec![0; Footer::ENCODED_LENGTH];
+        let offset = file_size - Footer::ENCODED_LENGTH as u64;
+        assert_eq!(path.get_file_system().seek_read(path, offset, &mut buf).unwrap(),
+            buf.len());
+        return Footer::decode_from(&buf).unwrap();
+    }
+
+    fn read_block(path: &dyn Path, handle: BlockHandle,
+        options: &TableOptions) -> BlockReader {
+        let file_size = path.get_file_system().file_size(path).unwrap();
+        BlockReader::read_from(path, &handle, file_size, options.checksum, true,
+            options.comparator.clone()).unwrap()
+    }
+
+    #[test]
+    fn test_build_table() {
+        let tmp_dir = TempDir::new().unwrap();
+        let file_path = tmp_dir.path().join("test.sst");
+        let path = LocalPath::from_std_path(file_path.as_path());
+        let options = TableOptions {
+            block_size: 1024,
+            ..TableOptions::default()
+        };
+        let mut builder = TableBuilder::new(options.clone(), &path).unwrap();
+        let num_entries = 1000;
+        for i in 0..num_entries {
+            let key = format!("key{:06}", i);
+            let value = format!("value{}", i);
+            assert!(builder.add(key.as_bytes(), value.as_bytes()).is_ok());
+        }
+        assert_eq!(builder.num_entries(), num_entries);
+        let file_size = builder.finish().unwrap();
+        assert_eq!(file_size, builder.file_size());
+        assert_eq!(file_size, std::fs::metadata(&file_path).unwrap().len());
+
+        let footer = read_footer(&path, file_size);
+        let index = read_block(&path, footer.index_handle, &options);
+        let mut index_iter = index.iter();
+        assert!(index_iter.seek_to_first().is_ok());
+        let mut num_blocks = 0;
+        let mut expected = 0;
+        let mut expected_offset = 0;
+        while index_iter.valid() {
+            let mut input = index_iter.value();
+            let handle = BlockHandle::decode_from(&mut input).unwrap();
+            // Data blocks are contiguous and respect the block size.
+            assert_eq!(handle.offset, expected_offset);
+            assert!(handle.size <= 1024);
+            expected_offset += handle.size + BLOCK_TRAILER_SIZE as u64;
+            let block = read_block(&path, handle, &options);
+            let mut iter = block.iter();
+            assert!(iter.seek_to_first().is_ok());
+            while iter.valid() {
+                let key = format!("key{:06}", expected);
+                assert_eq!(iter.key(), key.as_bytes());
+                assert_eq!(iter.value(), format!("value{}", expected).as_bytes());
+                // Index key separates the data blocks.
+                assert_ne!(options.comparator.compare(iter.key(), index_iter.key()),
+                    Ordering::Greater);
+                expected += 1;
+                assert!(iter.next().is_ok());
+            }
+            if expected < num_entries {
+                let next_key = format!("key{:06}", expected);
+                assert_eq!(options.comparator.compare(index_iter.key(),
+                    next_key.as_bytes()), Ordering::Less);
+            }
+            num_blocks += 1;
+            assert!(index_iter.next().is_ok());
+        }
+        assert_eq!(expected, num_entries);
+        assert!(num_blocks > 1);
+
+        // Settings are recorded in a meta block.
+        let metaindex = read_block(&path, footer.metaindex_handle, &options);
+        let mut iter = metaindex.iter();
+        assert!(iter.seek(TABLE_SETTINGS_BLOCK_NAME.as_bytes()).is_ok());
+        assert_eq!(iter.key(), TABLE_SETTINGS_BLOCK_NAME.as_bytes());
+        let handle = BlockHandle::decode_from(&mut iter.value()).unwrap();
+        let settings_block = read_block(&path, handle, &options);
+        let mut iter = settings_block.iter();
+        assert!(iter.seek_to_first().is_ok());
+        assert_eq!(TableSettings::decode(iter.value()).unwrap(), options.settings());
+    }
+
+    #[test]
+    fn test_build_empty_table() {
+        let tmp_dir = TempDir::new().unwrap();
+        let file_path = tmp_dir.path().join("empty.sst");
+        let path = LocalPath::from_std_path(file_path.as_path());
+        let options = TableOptions::default();
+        let mut builder = TableBuilder::new(options.clone(), &path).unwrap();
+        let file_size = builder.finish().unwrap();
+        let footer = read_footer(&path, file_size);
+        let index = read_block(&path, footer.index_handle, &options);
+        let mut iter = index.iter();
+        assert!(iter.seek_to_first().is_ok());
+        assert!(!iter.valid());
+    }
+
+    #[test]
+    fn test_existing_file() {
+        let tmp_dir = TempDir::new().unwrap();
+        let file_path = tmp_dir.path().join("test.sst");
+        let path = LocalPath::from_std_path(file_path.as_path());
+        assert!(TableBuilder::new(TableOptions::default(), &path).is_ok());
+        assert!(TableBuilder::new(TableOptions::default(), &path).is_err());
+    }
+}
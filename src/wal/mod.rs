@@ -0,0 +1,67 @@
+// Write Ahead Log Format
+// ----------------------
+// The log is a sequence of 32KB blocks. Every logical record written to
+// the log is split into one or more physical records, called fragments,
+// none of which crosses a block boundary. A fragment is:
+//  checksum : fixed32, masked crc32c of the type and the payload
+//  length   : fixed16 little endian, length of the payload
+//  type     : u8, RecordType
+//  payload  : char[length]
+// A record which fits in the remaining space of the current block is
+// written as a single FULL fragment. Otherwise it is written as a FIRST
+// fragment, zero or more MIDDLE fragments and a LAST fragment. If less
+// than a header remains in a block, the remaining bytes are filled with
+// zeroes and skipped by readers. Same format as LevelDB.
+
+pub mod reader;
+pub mod writer;
+mod tests;
+
+/// Size of the blocks of the log.
+pub const BLOCK_SIZE: usize = 32 * 1024;
+
+/// Size of the header of a fragment: checksum, length and type.
+pub const HEADER_SIZE: usize = 4 + 2 + 1;
+
+/// Type of a fragment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum RecordType {
+    /// Reserved for preallocated files.
+    Zero = 0,
+    Full = 1,
+    First = 2,
+    Middle = 3,
+    Last = 4,
+}
+
+/// How a Reader handles corrupted and truncated records when a log is
+/// replayed after a crash. Same modes as RocksDB.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WalRecoveryMode {
+    /// An incomplete record at the end of the log, left behind by a crash
+    /// in the middle of a write, is dropped. Any other corruption fails.
+    TolerateCorruptedTailRecords,
+    /// Any corruption fails, including an incomplete record at the end of
+    /// the log.
+    AbsoluteConsistency,
+    /// Records are read up to the first corruption, leaving the state as
+    /// of the last consistent point in time.
+    #[default]
+    PointInTimeRecovery,
+    /// Corrupted records are reported and skipped.
+    SkipAnyCorruptedRecords,
+}
+
+impl RecordType {
+    pub fn from_u8(value: u8) -> Option<RecordType> {
+        match value {
+            0 => Some(RecordType::Zero),
+            1 => Some(RecordType::Full),
+            2 => Some(RecordType::First),
+            3 => Some(RecordType::Middle),
+            4 => Some(RecordType::Last),
+            _ => None,
+        }
+    }
+}
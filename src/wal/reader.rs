@@ -0,0 +1,273 @@
+use std::sync::Arc;
+use crate::checksum::{crc32c, crc32c_extend, unmask_crc};
+use crate::filesystem::Path;
+use crate::sst::lsm_error::DataStoreError;
+use crate::wal::{RecordType, WalRecoveryMode, BLOCK_SIZE, HEADER_SIZE};
+
+/// Told about data dropped by a Reader because of corruption.
+pub trait Reporter {
+    /// 'bytes' is the approximate number of bytes dropped.
+    fn corruption(&mut self, bytes: usize, reason: &str);
+}
+
+/// Outcome of reading a fragment.
+enum Fragment {
+    Record(RecordType, Vec<u8>),
+    /// Clean end of the log.
+    Eof,
+    /// Fragment cut short by the end of the log, left behind by a
+    /// writer which died in the middle of a write.
+    TruncatedTail,
+    /// Corrupted fragment. 'bytes' were dropped.
+    BadRecord { bytes: usize, reason: &'static str },
+}
+
+/// Reads records written by 'wal::writer::Writer'. Corrupted data is
+/// reported to the reporter, then handled as set by the WalRecoveryMode.
+/// An incomplete record at the end of the log is dropped without a report,
+/// unless absolute consistency is required.
+pub struct Reader<'a> {
+    path_: Arc<dyn Path>,
+    reporter_: Option<&'a mut dyn Reporter>,
+    verify_checksums_: bool,
+    recovery_mode_: WalRecoveryMode,
+    /// Current block of the log.
+    buffer_: Vec<u8>,
+    /// Offset of the next unread fragment in 'buffer_'.
+    buffer_pos_: usize,
+    /// Offset of the end of 'buffer_' in the log.
+    end_of_buffer_offset_: u64,
+    /// Whether the last block of the log was read.
+    eof_: bool,
+    /// Offset of the first fragment of the last record returned.
+    last_record_offset_: u64,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(path: Arc<dyn Path>, reporter: Option<&'a mut dyn Reporter>,
+        verify_checksums: bool, recovery_mode: WalRecoveryMode) -> Reader<'a> {
+        Reader {
+            path_: path,
+            reporter_: reporter,
+            verify_checksums_: verify_checksums,
+            recovery_mode_: recovery_mode,
+            buffer_: Vec::with_capacity(BLOCK_SIZE),
+            buffer_pos_: 0,
+            end_of_buffer_offset_: 0,
+            eof_: false,
+            last_record_offset_: 0,
+        }
+    }
+
+    /// Returns the next record of the log, or None at the end of the log.
+    /// With point in time recovery, the end of the log is the first
+    /// corruption. Fails if the log cannot be read, or on corruption when
+    /// the recovery mode does not allow it.
+    pub fn read_record(&mut self) -> Result<Option<Vec<u8>>, DataStoreError> {
+        let mut scratch = vec!();
+        let mut in_fragmented_record = false;
+        // Offset of the first fragment of the record being read.
+        let mut record_offset = 0;
+        loop {
+            let (fragment_offset, fragment) = self.read_fragment()?;
+            match fragment {
+                Fragment::Record(RecordType::Full, payload) => {
+                    if in_fragmented_record && self.corruption(record_offset, scratch.len(),
+                        "partial record without end(1)")? {
+                        return Ok(None);
+                    }
+                    self.last_record_offset_ = fragment_offset;
+                    return Ok(Some(payload));
+                }
+                Fragment::Record(RecordType::First, payload) => {
+                    if in_fragmented_record && self.corruption(record_offset, scratch.len(),
+                        "partial record without end(2)")? {
+                        return Ok(None);
+                    }
+                    record_offset = fragment_offset;
+                    scratch = payload;
+                    in_fragmented_record = true;
+                }
+                Fragment::Record(RecordType::Middle, payload) => {
+                    if !in_fragmented_record {
+                        if self.corruption(fragment_offset, payload.len(),
+                            "missing start of fragmented record(1)")? {
+                            return Ok(None);
+                        }
+                    } else {
+                        scratch.extend_from_slice(&payload);
+                    }
+                }
+                Fragment::Record(RecordType::Last, payload) => {
+                    if !in_fragmented_record {
+                        if self.corruption(fragment_offset, payload.len(),
+                            "missing start of fragmented record(2)")? {
+                            return Ok(None);
+                        }
+                    } else {
+                        scratch.extend_from_slice(&payload);
+                        self.last_record_offset_ = record_offset;
+                        return Ok(Some(scratch));
+                    }
+                }
+                Fragment::Record(RecordType::Zero, _) => unreachable!(),
+                Fragment::Eof if !in_fragmented_record => {
+                    return Ok(None);
+                }
+                Fragment::Eof | Fragment::TruncatedTail => {
+                    // The last record was cut short by a crash of the
+                    // writer. Not a corruption, unless the log must be
+                    // absolutely consistent.
+                    if self.recovery_mode_ == WalRecoveryMode::AbsoluteConsistency {
+                        let offset = if in_fragmented_record { record_offset }
+                            else { fragment_offset };
+                        return Err(self.corruption_error(offset,
+                            "truncated record at end of log"));
+                    }
+                    return Ok(None);
+                }
+                Fragment::BadRecord { bytes, reason } => {
+                    if in_fragmented_record {
+                        if self.corruption(record_offset, scratch.len(),
+                            "error in middle of record")? {
+                            return Ok(None);
+                        }
+                        in_fragmented_record = false;
+                        scratch.clear();
+                    }
+                    if self.corruption(fragment_offset, bytes, reason)? {
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Offset in the log of the first fragment of the last record returned
+    /// by 'read_record'.
+    #[inline(always)]
+    pub fn last_record_offset(&self) -> u64 {
+        self.last_record_offset_
+    }
+
+    #[inline(always)]
+    fn fragment_offset(&self) -> u64 {
+        self.end_of_buffer_offset_ - (self.buffer_.len() - self.buffer_pos_) as u64
+    }
+
+    /// Reports 'bytes' dropped at 'offset' because of a corruption. Fails
+    /// if the recovery mode does not tolerate it, or returns whether the
+    /// reading must stop.
+    fn corruption(&mut self, offset: u64, bytes: usize, reason: &str)
+        -> Result<bool, DataStoreError> {
+        if bytes == 0 {
+            return Ok(false);
+        }
+        if let Some(reporter) = self.reporter_.as_mut() {
+            reporter.corruption(bytes, reason);
+        }
+        match self.recovery_mode_ {
+            WalRecoveryMode::SkipAnyCorruptedRecords => return Ok(false),
+            WalRecoveryMode::PointInTimeRecovery => {
+                // Ignore the rest of the log.
+                self.buffer_.clear();
+                self.buffer_pos_ = 0;
+                self.eof_ = true;
+                return Ok(true);
+            }
+            WalRecoveryMode::TolerateCorruptedTailRecords |
+            WalRecoveryMode::AbsoluteConsistency => {
+                return Err(self.corruption_error(offset, reason));
+            }
+        }
+    }
+
+    fn corruption_error(&self, offset: u64, reason: &str) -> DataStoreError {
+        DataStoreError::Corruption {
+            file: self.path_.to_str().unwrap_or("").to_string(),
+            offset,
+            reason: reason.to_string(),
+        }
+    }
+
+    /// Reads the next block of the log into 'buffer_'.
+    fn read_block(&mut self) -> Result<(), DataStoreError> {
+        self.buffer_.resize(BLOCK_SIZE, 0);
+        self.buffer_pos_ = 0;
+        let bytes = self.path_.get_file_system().seek_read(self.path_.as_ref(),
+            self.end_of_buffer_offset_, &mut self.buffer_)?;
+        self.buffer_.truncate(bytes);
+        self.end_of_buffer_offset_ += bytes as u64;
+        if bytes < BLOCK_SIZE {
+            self.eof_ = true;
+        }
+        return Ok(());
+    }
+
+    /// Reads the next fragment, and returns it with its offset in the log.
+    fn read_fragment(&mut self) -> Result<(u64, Fragment), DataStoreError> {
+        loop {
+            // Computed once the block holding the fragment is read.
+            let offset = self.fragment_offset();
+            let remaining = self.buffer_.len() - self.buffer_pos_;
+            if remaining < HEADER_SIZE {
+                if !self.eof_ {
+                    // Skip the zero filled trailer of the block.
+                    self.read_block()?;
+                    continue;
+                }
+                // Truncated header at the end of the log.
+                self.buffer_pos_ = self.buffer_.len();
+                if remaining > 0 {
+                    return Ok((offset, Fragment::TruncatedTail));
+                }
+                return Ok((offset, Fragment::Eof));
+            }
+            let header = &self.buffer_[self.buffer_pos_..self.buffer_pos_ + HEADER_SIZE];
+            let expected_crc = u32::from_le_bytes(header[..4].try_into().unwrap());
+            let length = u16::from_le_bytes(header[4..6].try_into().unwrap()) as usize;
+            let type_byte = header[6];
+            if HEADER_SIZE + length > remaining {
+                self.buffer_pos_ = self.buffer_.len();
+                if !self.eof_ {
+                    return Ok((offset, Fragment::BadRecord {
+                        bytes: remaining,
+                        reason: "bad record length",
+                    }));
+                }
+                // The writer died in the middle of writing the fragment.
+                return Ok((offset, Fragment::TruncatedTail));
+            }
+            if type_byte == RecordType::Zero as u8 && length == 0 {
+                // Skip zero length records of preallocated files without
+                // reporting any drops.
+                self.buffer_pos_ = self.buffer_.len();
+                return Ok((offset, Fragment::BadRecord { bytes: 0, reason: "zero record" }));
+            }
+            let start = self.buffer_pos_ + HEADER_SIZE;
+            let payload = &self.buffer_[start..start + length];
+            if self.verify_checksums_ {
+                let actual_crc = crc32c_extend(crc32c(&[type_byte]), payload);
+                if actual_crc != unmask_crc(expected_crc) {
+                    // Drop the rest of the block, as the length itself may
+                    // be corrupted and the following fragments cannot be
+                    // located reliably.
+                    self.buffer_pos_ = self.buffer_.len();
+                    return Ok((offset, Fragment::BadRecord {
+                        bytes: remaining,
+                        reason: "checksum mismatch",
+                    }));
+                }
+            }
+            self.buffer_pos_ = start + length;
+            let fragment = match RecordType::from_u8(type_byte) {
+                Some(RecordType::Zero) | None => Fragment::BadRecord {
+                    bytes: HEADER_SIZE + length,
+                    reason: "unknown record type",
+                },
+                Some(record_type) => Fragment::Record(record_type, payload.to_vec()),
+            };
+            return Ok((offset, fragment));
+        }
+    }
+}
@@ -0,0 +1,311 @@
+#[cfg(test)]
+mod wal_test {
+    use std::sync::Arc;
+    use tempfile::TempDir;
+    use crate::filesystem::{LocalPath, Path};
+    use crate::sst::lsm_error::DataStoreError;
+    use crate::wal::{WalRecoveryMode, BLOCK_SIZE, HEADER_SIZE};
+    use crate::wal::reader::{Reader, Reporter};
+    use crate::wal::writer::Writer;
+
+    #[derive(Default)]
+    struct TestReporter {
+        dropped_bytes: usize,
+        reasons: Vec<String>,
+    }
+
+    impl Reporter for TestReporter {
+        fn corruption(&mut self, bytes: usize, reason: &str) {
+            self.dropped_bytes += bytes;
+            self.reasons.push(reason.to_string());
+        }
+    }
+
+    fn log_path(dir: &TempDir) -> Arc<dyn Path> {
+        return Arc::new(LocalPath::from_std_path(dir.path().join("000001.log").as_path()));
+    }
+
+    fn write_records(path: &Arc<dyn Path>, records: &[Vec<u8>]) {
+        let mut writer = Writer::new(path.clone()).unwrap();
+        for record in records {
+            writer.add_record(record).unwrap();
+        }
+        writer.sync().unwrap();
+    }
+
+    fn read_records(path: &Arc<dyn Path>, reporter: &mut TestReporter) -> Vec<Vec<u8>> {
+        return recover(path, reporter, WalRecoveryMode::SkipAnyCorruptedRecords).unwrap();
+    }
+
+    fn recover(path: &Arc<dyn Path>, reporter: &mut TestReporter, mode: WalRecoveryMode)
+        -> Result<Vec<Vec<u8>>, DataStoreError> {
+        let mut reader = Reader::new(path.clone(), Some(reporter), true, mode);
+        let mut records = vec!();
+        while let Some(record) = reader.read_record()? {
+            records.push(record);
+        }
+        return Ok(records);
+    }
+
+    fn big_record(fill: u8, size: usize) -> Vec<u8> {
+        return vec![fill; size];
+    }
+
+    fn overwrite(path: &Arc<dyn Path>, f: impl FnOnce(&mut Vec<u8>)) {
+        let std_path = std::path::Path::new(path.as_os_str());
+        let mut contents = std::fs::read(std_path).unwrap();
+        f(&mut contents);
+        std::fs::write(std_path, contents).unwrap();
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = log_path(&tmp_dir);
+        let records = vec!(b"foo".to_vec(), b"bar".to_vec(), vec!(), b"xxxx".to_vec());
+        write_records(&path, &records);
+        let mut reporter = TestReporter::default();
+        assert_eq!(read_records(&path, &mut reporter), records);
+        assert!(reporter.reasons.is_empty());
+    }
+
+    #[test]
+    fn test_fragmentation() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = log_path(&tmp_dir);
+        let records = vec!(b"small".to_vec(), big_record(b'm', 50000),
+            big_record(b'l', 3 * BLOCK_SIZE + 17), b"tail".to_vec());
+        write_records(&path, &records);
+        let mut reporter = TestReporter::default();
+        let mut reader = Reader::new(path.clone(), Some(&mut reporter), true,
+            WalRecoveryMode::default());
+        let mut offsets = vec!();
+        for record in records.iter() {
+            assert_eq!(&reader.read_record().unwrap().unwrap(), record);
+            offsets.push(reader.last_record_offset());
+        }
+        assert!(reader.read_record().unwrap().is_none());
+        let first = (HEADER_SIZE + 5) as u64;
+        assert_eq!(offsets[..2], [0, first]);
+        assert!(reporter.reasons.is_empty());
+    }
+
+    #[test]
+    fn test_marginal_trailer() {
+        // Leave exactly a header, and less than a header, at the end of
+        // the first block.
+        for leftover in [HEADER_SIZE, HEADER_SIZE - 1, 1] {
+            let tmp_dir = TempDir::new().unwrap();
+            let path = log_path(&tmp_dir);
+            let records = vec!(big_record(b'a', BLOCK_SIZE - HEADER_SIZE - leftover),
+                vec!(), b"bar".to_vec());
+            write_records(&path, &records);
+            let mut reporter = TestReporter::default();
+            assert_eq!(read_records(&path, &mut reporter), records, "{}", leftover);
+            assert!(reporter.reasons.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_reopen_and_append() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = log_path(&tmp_dir);
+        let first = big_record(b'x', BLOCK_SIZE - 100);
+        write_records(&path, std::slice::from_ref(&first));
+        let mut writer = Writer::with_existing_log(path.clone()).unwrap();
+        writer.add_record(b"appended").unwrap();
+        writer.add_record(&big_record(b'y', 1000)).unwrap();
+        let mut reporter = TestReporter::default();
+        assert_eq!(read_records(&path, &mut reporter),
+            vec!(first, b"appended".to_vec(), big_record(b'y', 1000)));
+        assert!(reporter.reasons.is_empty());
+    }
+
+    #[test]
+    fn test_checksum_mismatch_skips_block() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = log_path(&tmp_dir);
+        let records = vec!(b"foo".to_vec(), b"bar".to_vec(),
+            big_record(b'z', BLOCK_SIZE), b"baz".to_vec());
+        write_records(&path, &records);
+        // Flip a bit in the payload of the first record.
+        overwrite(&path, |contents| contents[HEADER_SIZE] ^= 0x01);
+        let mut reporter = TestReporter::default();
+        // The rest of the first block is dropped, including the start of
+        // the fragmented record, whose remaining fragments are reported.
+        assert_eq!(read_records(&path, &mut reporter), vec!(b"baz".to_vec()));
+        assert_eq!(reporter.reasons[0], "checksum mismatch");
+        assert_eq!(reporter.reasons[1], "missing start of fragmented record(2)");
+        assert!(reporter.dropped_bytes >= BLOCK_SIZE);
+
+        // Without verification the corrupted payload is returned.
+        let mut reader = Reader::new(path.clone(), None, false,
+            WalRecoveryMode::SkipAnyCorruptedRecords);
+        assert_eq!(reader.read_record().unwrap().unwrap(), b"goo".to_vec());
+    }
+
+    #[test]
+    fn test_bad_length_and_unknown_type() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = log_path(&tmp_dir);
+        write_records(&path, &[b"foo".to_vec(), big_record(b'b', BLOCK_SIZE), b"bar".to_vec()]);
+        // Corrupt the length of the first fragment.
+        overwrite(&path, |contents| contents[5] = 0xff);
+        let mut reporter = TestReporter::default();
+        assert_eq!(read_records(&path, &mut reporter), vec!(b"bar".to_vec()));
+        assert_eq!(reporter.reasons[0], "bad record length");
+
+        let tmp_dir = TempDir::new().unwrap();
+        let path = log_path(&tmp_dir);
+        write_records(&path, &[b"foo".to_vec(), b"bar".to_vec()]);
+        overwrite(&path, |contents| contents[6] = 9);
+        let mut reporter = TestReporter::default();
+        let mut reader = Reader::new(path.clone(), Some(&mut reporter), false,
+            WalRecoveryMode::SkipAnyCorruptedRecords);
+        assert_eq!(reader.read_record().unwrap().unwrap(), b"bar".to_vec());
+        assert!(reader.read_record().unwrap().is_none());
+        assert_eq!(reporter.reasons, vec!("unknown record type"));
+    }
+
+    #[test]
+    fn test_truncated_tail_dropped_silently() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = log_path(&tmp_dir);
+        let records = vec!(b"foo".to_vec(), big_record(b't', 2 * BLOCK_SIZE));
+        write_records(&path, &records);
+        let std_path = std::path::Path::new(path.as_os_str());
+        let size = std::fs::metadata(std_path).unwrap().len() as usize;
+        // Cut the log in the header of the last fragment, in the payload
+        // of the last fragment and in the middle of the record.
+        for cut in [size - 1, 2 * BLOCK_SIZE + 3, BLOCK_SIZE + 100] {
+            let mut contents = std::fs::read(std_path).unwrap();
+            contents.truncate(cut);
+            let truncated: Arc<dyn Path> = Arc::new(LocalPath::from_std_path(
+                tmp_dir.path().join(format!("{}.log", cut)).as_path()));
+            std::fs::write(std::path::Path::new(truncated.as_os_str()), contents).unwrap();
+            let mut reporter = TestReporter::default();
+            assert_eq!(read_records(&truncated, &mut reporter), vec!(b"foo".to_vec()));
+            assert!(reporter.reasons.is_empty(), "{}: {:?}", cut, reporter.reasons);
+        }
+    }
+
+    const MODES: [WalRecoveryMode; 4] = [WalRecoveryMode::TolerateCorruptedTailRecords,
+        WalRecoveryMode::AbsoluteConsistency, WalRecoveryMode::PointInTimeRecovery,
+        WalRecoveryMode::SkipAnyCorruptedRecords];
+
+    fn assert_corruption(result: Result<Vec<Vec<u8>>, DataStoreError>, path: &Arc<dyn Path>,
+        expected_offset: u64) {
+        match result {
+            Err(DataStoreError::Corruption { file, offset, .. }) => {
+                assert_eq!(file, path.to_str().unwrap());
+                assert_eq!(offset, expected_offset);
+            }
+            other => panic!("Corruption is not detected: {:?}", other.map(|r| r.len())),
+        }
+    }
+
+    #[test]
+    fn test_recovery_modes_truncated_tail() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = log_path(&tmp_dir);
+        let records = vec!(b"foo".to_vec(), b"bar".to_vec(), big_record(b't', BLOCK_SIZE));
+        write_records(&path, &records);
+        let last_offset = (2 * (HEADER_SIZE + 3)) as u64;
+        overwrite(&path, |contents| contents.truncate(contents.len() - 10));
+        for mode in MODES {
+            let mut reporter = TestReporter::default();
+            let result = recover(&path, &mut reporter, mode);
+            if mode == WalRecoveryMode::AbsoluteConsistency {
+                assert_corruption(result, &path, last_offset);
+            } else {
+                assert_eq!(result.unwrap(), records[..2], "{:?}", mode);
+            }
+            assert!(reporter.reasons.is_empty());
+        }
+        // A log cut right after a complete record is consistent.
+        overwrite(&path, |contents| contents.truncate(last_offset as usize));
+        for mode in MODES {
+            let mut reporter = TestReporter::default();
+            assert_eq!(recover(&path, &mut reporter, mode).unwrap(), records[..2]);
+        }
+    }
+
+    #[test]
+    fn test_truncated_tail_offset_after_block_trailer() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = log_path(&tmp_dir);
+        // Leaves a trailer of 3 bytes in the first block, so the second
+        // record starts the second block.
+        let records = vec!(big_record(b'a', BLOCK_SIZE - HEADER_SIZE - 3),
+            big_record(b'b', 100));
+        write_records(&path, &records);
+        overwrite(&path, |contents| contents.truncate(contents.len() - 10));
+        let mut reporter = TestReporter::default();
+        assert_corruption(recover(&path, &mut reporter,
+            WalRecoveryMode::AbsoluteConsistency), &path, BLOCK_SIZE as u64);
+    }
+
+    #[test]
+    fn test_recovery_modes_corrupted_last_fragment() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = log_path(&tmp_dir);
+        let records = vec!(b"foo".to_vec(), b"bar".to_vec());
+        write_records(&path, &records);
+        // A complete last fragment with a bad checksum is a corruption, not
+        // a torn write.
+        overwrite(&path, |contents| *contents.last_mut().unwrap() ^= 0x01);
+        for mode in MODES {
+            let mut reporter = TestReporter::default();
+            let result = recover(&path, &mut reporter, mode);
+            match mode {
+                WalRecoveryMode::TolerateCorruptedTailRecords |
+                WalRecoveryMode::AbsoluteConsistency => {
+                    assert_corruption(result, &path, (HEADER_SIZE + 3) as u64);
+                }
+                WalRecoveryMode::PointInTimeRecovery |
+                WalRecoveryMode::SkipAnyCorruptedRecords => {
+                    assert_eq!(result.unwrap(), records[..1], "{:?}", mode);
+                }
+            }
+            assert_eq!(reporter.reasons, vec!("checksum mismatch"), "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn test_recovery_modes_corrupted_record() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = log_path(&tmp_dir);
+        // A record in the first block followed by records in later blocks,
+        // so that the corruption is not at the tail of the log.
+        let records = vec!(b"foo".to_vec(), big_record(b'b', BLOCK_SIZE), b"bar".to_vec(),
+            big_record(b'c', BLOCK_SIZE), b"baz".to_vec());
+        write_records(&path, &records);
+        let std_path = std::path::Path::new(path.as_os_str());
+        let clean = std::fs::read(std_path).unwrap();
+        // Flip a bit in the payload of the fragment holding "bar", in the
+        // second block.
+        let bar_offset = clean.windows(3).position(|w| w == b"bar").unwrap();
+        overwrite(&path, |contents| contents[bar_offset] ^= 0x01);
+        let fragment_offset = (bar_offset - HEADER_SIZE) as u64;
+        for mode in MODES {
+            let mut reporter = TestReporter::default();
+            let result = recover(&path, &mut reporter, mode);
+            match mode {
+                WalRecoveryMode::TolerateCorruptedTailRecords |
+                WalRecoveryMode::AbsoluteConsistency => {
+                    assert_corruption(result, &path, fragment_offset);
+                }
+                WalRecoveryMode::PointInTimeRecovery => {
+                    assert_eq!(result.unwrap(), records[..2]);
+                }
+                WalRecoveryMode::SkipAnyCorruptedRecords => {
+                    // The rest of the second block is dropped, so the
+                    // fragmented record starting in it is lost as well.
+                    assert_eq!(result.unwrap(), vec!(records[0].clone(), records[1].clone(),
+                        records[4].clone()));
+                }
+            }
+            assert_eq!(reporter.reasons[0], "checksum mismatch", "{:?}", mode);
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use std::sync::Arc;
+use crate::checksum::{crc32c, crc32c_extend, mask_crc};
+use crate::filesystem::Path;
+use crate::sst::lsm_error::DataStoreError;
+use crate::wal::{RecordType, BLOCK_SIZE, HEADER_SIZE};
+
+/// Appends records to a log. Check the comment at 'wal' for the format.
+pub struct Writer {
+    path_: Arc<dyn Path>,
+    /// Offset of the end of the log within the current block.
+    block_offset_: usize,
+}
+
+impl Writer {
+    /// Creates the log at 'path'. Fails if the file already exists.
+    pub fn new(path: Arc<dyn Path>) -> Result<Writer, DataStoreError> {
+        path.get_file_system().create(path.as_ref())?;
+        return Ok(Writer {
+            path_: path,
+            block_offset_: 0,
+        });
+    }
+
+    /// Creates a writer appending to the existing log at 'path'.
+    pub fn with_existing_log(path: Arc<dyn Path>) -> Result<Writer, DataStoreError> {
+        let file_size = path.get_file_system().file_size(path.as_ref())?;
+        return Ok(Writer {
+            path_: path,
+            block_offset_: (file_size % BLOCK_SIZE as u64) as usize,
+        });
+    }
+
+    /// Appends 'record' to the log. The record is durable only once
+    /// 'sync' returns.
+    pub fn add_record(&mut self, record: &[u8]) -> Result<(), DataStoreError> {
+        let mut out = Vec::with_capacity(record.len() + HEADER_SIZE);
+        let mut left = record;
+        // Fragment the record if necessary and emit it. Note that if
+        // 'record' is empty, we still want to iterate once to emit a
+        // single zero-length FULL fragment.
+        let mut begin = true;
+        loop {
+            let leftover = BLOCK_SIZE - self.block_offset_;
+            if leftover < HEADER_SIZE {
+                // Switch to a new block, filling the trailer with zeroes.
+                out.resize(out.len() + leftover, 0);
+                self.block_offset_ = 0;
+            }
+            let available = BLOCK_SIZE - self.block_offset_ - HEADER_SIZE;
+            let fragment_length = left.len().min(available);
+            let end = fragment_length == left.len();
+            let record_type = match (begin, end) {
+                (true, true) => RecordType::Full,
+                (true, false) => RecordType::First,
+                (false, true) => RecordType::Last,
+                (false, false) => RecordType::Middle,
+            };
+            self.emit_fragment(&mut out, record_type, &left[..fragment_length]);
+            left = &left[fragment_length..];
+            begin = false;
+            if end {
+                break;
+            }
+        }
+        self.path_.get_file_system().append(self.path_.as_ref(), &out)?;
+        return Ok(());
+    }
+
+    fn emit_fragment(&mut self, out: &mut Vec<u8>, record_type: RecordType,
+        payload: &[u8]) {
+        debug_assert!(self.block_offset_ + HEADER_SIZE + payload.len() <= BLOCK_SIZE);
+        let crc = crc32c_extend(crc32c(&[record_type as u8]), payload);
+        out.extend_from_slice(&mask_crc(crc).to_le_bytes());
+        out.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+        out.push(record_type as u8);
+        out.extend_from_slice(payload);
+        self.block_offset_ += HEADER_SIZE + payload.len();
+    }
+
+    /// Flushes the log to durable storage.
+    pub fn sync(&self) -> Result<(), DataStoreError> {
+        self.path_.get_file_system().sync(self.path_.as_ref())?;
+        return Ok(());
+    }
+
+    #[inline(always)]
+    pub fn path(&self) -> &Arc<dyn Path> {
+        &self.path_
+    }
+}
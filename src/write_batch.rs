@@ -0,0 +1,427 @@
+use crate::coding::{get_varint, put_varint};
+use crate::memtable::MemTable;
+use crate::memtable::internal_key::{SequenceNumber, ValueType};
+use crate::sst::lsm_error::DataStoreError;
+
+// Write Batch Format
+// ------------------
+// A WriteBatch is a header followed by its records:
+//  sequence : fixed64 little endian, sequence number of the first record
+//  count    : fixed32 little endian, number of records
+//  record*  : count records
+// A record is a ValueType byte followed by its arguments, each of which
+// is a leb128 length prefixed string:
+//  Value         : key, value
+//  Deletion      : key
+//  Merge         : key, operand
+//  RangeDeletion : begin key, end key
+// The records get consecutive sequence numbers starting at the sequence
+// number of the batch. The whole batch is written to the WAL as a single
+// record, so it is recovered all or nothing.
+
+/// Size of the header of a WriteBatch: sequence number and count.
+pub const WRITE_BATCH_HEADER_SIZE: usize = 8 + 4;
+
+/// Receives the records of a WriteBatch, in order, from
+/// 'WriteBatch::iterate'.
+pub trait Handler {
+    fn put(&mut self, key: &[u8], value: &[u8]);
+
+    fn delete(&mut self, key: &[u8]);
+
+    /// Deletion of the keys from 'begin', inclusive, to 'end', exclusive.
+    fn delete_range(&mut self, begin: &[u8], end: &[u8]);
+
+    fn merge(&mut self, key: &[u8], operand: &[u8]);
+}
+
+/// Collects updates to be applied atomically. Check the comment above for
+/// the format.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WriteBatch {
+    rep_: Vec<u8>,
+}
+
+impl Default for WriteBatch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WriteBatch {
+    pub fn new() -> WriteBatch {
+        WriteBatch { rep_: vec![0; WRITE_BATCH_HEADER_SIZE] }
+    }
+
+    /// Creates a WriteBatch from its serialized form, as returned by
+    /// 'data'. Fails if 'data' is too short to hold the header. Records
+    /// are validated when the batch is iterated.
+    pub fn from_data(data: Vec<u8>) -> Result<WriteBatch, DataStoreError> {
+        if data.len() < WRITE_BATCH_HEADER_SIZE {
+            return Err(DataStoreError::MalformedWriteBatch("too small".to_string()));
+        }
+        return Ok(WriteBatch { rep_: data });
+    }
+
+    pub fn put(&mut self, key: &[u8], value: &[u8]) {
+        self.add_record(ValueType::Value, &[key, value]);
+    }
+
+    pub fn delete(&mut self, key: &[u8]) {
+        self.add_record(ValueType::Deletion, &[key]);
+    }
+
+    /// Deletes the keys from 'begin', inclusive, to 'end', exclusive.
+    pub fn delete_range(&mut self, begin: &[u8], end: &[u8]) {
+        self.add_record(ValueType::RangeDeletion, &[begin, end]);
+    }
+
+    /// Records 'operand' to be combined with the value of 'key' by the
+    /// merge operator.
+    pub fn merge(&mut self, key: &[u8], operand: &[u8]) {
+        self.add_record(ValueType::Merge, &[key, operand]);
+    }
+
+    fn add_record(&mut self, value_type: ValueType, args: &[&[u8]]) {
+        self.set_count(self.count() + 1);
+        self.rep_.push(value_type as u8);
+        for arg in args {
+            put_varint(&mut self.rep_, arg.len() as u64);
+            self.rep_.extend_from_slice(arg);
+        }
+    }
+
+    /// Appends the records of 'other' to this batch.
+    pub fn append(&mut self, other: &WriteBatch) {
+        self.set_count(self.count() + other.count());
+        self.rep_.extend_from_slice(&other.rep_[WRITE_BATCH_HEADER_SIZE..]);
+    }
+
+    /// Removes all the records.
+    pub fn clear(&mut self) {
+        self.rep_.clear();
+        self.rep_.resize(WRITE_BATCH_HEADER_SIZE, 0);
+    }
+
+    /// Number of records in the batch.
+    #[inline(always)]
+    pub fn count(&self) -> u32 {
+        u32::from_le_bytes(self.rep_[8..WRITE_BATCH_HEADER_SIZE].try_into().unwrap())
+    }
+
+    #[inline(always)]
+    fn set_count(&mut self, count: u32) {
+        self.rep_[8..WRITE_BATCH_HEADER_SIZE].copy_from_slice(&count.to_le_bytes());
+    }
+
+    /// Sequence number of the first record.
+    #[inline(always)]
+    pub fn sequence(&self) -> SequenceNumber {
+        u64::from_le_bytes(self.rep_[..8].try_into().unwrap())
+    }
+
+    #[inline(always)]
+    pub fn set_sequence(&mut self, sequence: SequenceNumber) {
+        self.rep_[..8].copy_from_slice(&sequence.to_le_bytes());
+    }
+
+    /// Serialized form of the batch, as written to the WAL.
+    #[inline(always)]
+    pub fn data(&self) -> &[u8] {
+        &self.rep_
+    }
+
+    #[inline(always)]
+    pub fn approximate_size(&self) -> usize {
+        self.rep_.len()
+    }
+
+    /// Passes the records of the batch, in order, to 'handler'. Fails if
+    /// the batch is malformed, in which case the records before the
+    /// malformed one have already been passed.
+    pub fn iterate(&self, handler: &mut dyn Handler) -> Result<(), DataStoreError> {
+        let mut input = &self.rep_[WRITE_BATCH_HEADER_SIZE..];
+        let mut found = 0;
+        while !input.is_empty() {
+            let tag = input[0];
+            input = &input[1..];
+            match ValueType::from_u8(tag) {
+                Some(ValueType::Value) => {
+                    let (key, value) = (get_arg(&mut input, "Put")?, get_arg(&mut input, "Put")?);
+                    handler.put(key, value);
+                }
+                Some(ValueType::Deletion) => handler.delete(get_arg(&mut input, "Delete")?),
+                Some(ValueType::RangeDeletion) => {
+                    let begin = get_arg(&mut input, "DeleteRange")?;
+                    let end = get_arg(&mut input, "DeleteRange")?;
+                    handler.delete_range(begin, end);
+                }
+                Some(ValueType::Merge) => {
+                    let (key, operand) = (get_arg(&mut input, "Merge")?,
+                        get_arg(&mut input, "Merge")?);
+                    handler.merge(key, operand);
+                }
+                None => {
+                    return Err(DataStoreError::MalformedWriteBatch(
+                        format!("unknown record tag {}", tag)));
+                }
+            }
+            found += 1;
+        }
+        if found != self.count() {
+            return Err(DataStoreError::MalformedWriteBatch(
+                format!("count is {} but found {} records", self.count(), found)));
+        }
+        return Ok(());
+    }
+
+    /// Fails if the batch is malformed or cannot be applied to 'memtable'.
+    pub fn validate(&self, memtable: &MemTable) -> Result<(), DataStoreError> {
+        let mut checker = RecordChecker { has_merge: false };
+        self.iterate(&mut checker)?;
+        if checker.has_merge && memtable.merge_operator().is_none() {
+            return Err(DataStoreError::NotSupported(
+                "Merge requires a merge operator".to_string()));
+        }
+        return Ok(());
+    }
+
+    /// Applies the records of the batch to 'memtable', with consecutive
+    /// sequence numbers starting at the sequence number of the batch. The
+    /// batch is validated first, so either all the records are applied or
+    /// none is. Readers must not be allowed to see the sequence numbers of
+    /// the batch before this returns.
+    pub fn insert_into(&self, memtable: &MemTable) -> Result<(), DataStoreError> {
+        self.validate(memtable)?;
+        let mut inserter = MemTableInserter {
+            memtable,
+            sequence: self.sequence(),
+        };
+        return self.iterate(&mut inserter);
+    }
+}
+
+/// Reads a length prefixed argument of a 'record' from 'input'.
+fn get_arg<'a>(input: &mut &'a [u8], record: &str) -> Result<&'a [u8], DataStoreError> {
+    let malformed = || DataStoreError::MalformedWriteBatch(format!("bad {} record", record));
+    let len = get_varint(input).ok_or_else(malformed)? as usize;
+    if input.len() < len {
+        return Err(malformed());
+    }
+    let (arg, rest) = input.split_at(len);
+    *input = rest;
+    return Ok(arg);
+}
+
+/// Validates the records of a batch before it is applied.
+struct RecordChecker {
+    has_merge: bool,
+}
+
+impl Handler for RecordChecker {
+    fn put(&mut self, _key: &[u8], _value: &[u8]) {}
+
+    fn delete(&mut self, _key: &[u8]) {}
+
+    fn delete_range(&mut self, _begin: &[u8], _end: &[u8]) {}
+
+    fn merge(&mut self, _key: &[u8], _operand: &[u8]) {
+        self.has_merge = true;
+    }
+}
+
+/// Adds the records of a batch to a MemTable.
+struct MemTableInserter<'a> {
+    memtable: &'a MemTable,
+    sequence: SequenceNumber,
+}
+
+impl MemTableInserter<'_> {
+    fn add(&mut self, value_type: ValueType, key: &[u8], value: &[u8]) {
+        self.memtable.add(self.sequence, value_type, key, value);
+        self.sequence += 1;
+    }
+}
+
+impl Handler for MemTableInserter<'_> {
+    fn put(&mut self, key: &[u8], value: &[u8]) {
+        self.add(ValueType::Value, key, value);
+    }
+
+    fn delete(&mut self, key: &[u8]) {
+        self.add(ValueType::Deletion, key, b"");
+    }
+
+    fn delete_range(&mut self, begin: &[u8], end: &[u8]) {
+        self.add(ValueType::RangeDeletion, begin, end);
+    }
+
+    fn merge(&mut self, key: &[u8], operand: &[u8]) {
+        self.add(ValueType::Merge, key, operand);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::comparator::BytewiseComparator;
+    use crate::memtable::{LookupResult, MemTable};
+    use crate::memtable::internal_key::{parse_internal_key, InternalKeyComparator,
+        LookupKey, ValueType};
+    use crate::merge_operator::StringAppendOperator;
+    use crate::sst::lsm_error::DataStoreError;
+    use crate::write_batch::{Handler, WriteBatch, WRITE_BATCH_HEADER_SIZE};
+
+    /// Prints the records of a batch, as LevelDB does in its tests.
+    #[derive(Default)]
+    struct Printer {
+        out: String,
+    }
+
+    impl Handler for Printer {
+        fn put(&mut self, key: &[u8], value: &[u8]) {
+            self.out += &format!("Put({}, {})", String::from_utf8_lossy(key),
+                String::from_utf8_lossy(value));
+        }
+
+        fn delete(&mut self, key: &[u8]) {
+            self.out += &format!("Delete({})", String::from_utf8_lossy(key));
+        }
+
+        fn delete_range(&mut self, begin: &[u8], end: &[u8]) {
+            self.out += &format!("DeleteRange({}, {})", String::from_utf8_lossy(begin),
+                String::from_utf8_lossy(end));
+        }
+
+        fn merge(&mut self, key: &[u8], operand: &[u8]) {
+            self.out += &format!("Merge({}, {})", String::from_utf8_lossy(key),
+                String::from_utf8_lossy(operand));
+        }
+    }
+
+    fn print(batch: &WriteBatch) -> Result<String, DataStoreError> {
+        let mut printer = Printer::default();
+        batch.iterate(&mut printer)?;
+        return Ok(printer.out);
+    }
+
+    fn comparator() -> InternalKeyComparator {
+        InternalKeyComparator::new(Arc::new(BytewiseComparator))
+    }
+
+    #[test]
+    fn test_empty_batch() {
+        let batch = WriteBatch::new();
+        assert_eq!(batch.count(), 0);
+        assert_eq!(batch.approximate_size(), WRITE_BATCH_HEADER_SIZE);
+        assert_eq!(print(&batch).unwrap(), "");
+    }
+
+    #[test]
+    fn test_records_and_header() {
+        let mut batch = WriteBatch::new();
+        batch.put(b"foo", b"bar");
+        batch.delete(b"box");
+        batch.delete_range(b"a", b"c");
+        batch.merge(b"baz", b"boo");
+        batch.put(b"", b"\x00binary");
+        batch.set_sequence(100);
+        assert_eq!(batch.count(), 5);
+        assert_eq!(batch.sequence(), 100);
+        let expected = "Put(foo, bar)Delete(box)DeleteRange(a, c)Merge(baz, boo)Put(, \0binary)";
+        assert_eq!(print(&batch).unwrap(), expected);
+        // The serialized form round trips.
+        let decoded = WriteBatch::from_data(batch.data().to_vec()).unwrap();
+        assert_eq!(decoded.sequence(), 100);
+        assert_eq!(print(&decoded).unwrap(), expected);
+        batch.clear();
+        assert_eq!(batch, WriteBatch::new());
+    }
+
+    #[test]
+    fn test_append() {
+        let mut b1 = WriteBatch::new();
+        let mut b2 = WriteBatch::new();
+        b1.set_sequence(200);
+        b2.set_sequence(300);
+        b1.append(&b2);
+        assert_eq!(print(&b1).unwrap(), "");
+        b2.put(b"a", b"va");
+        b1.append(&b2);
+        assert_eq!(print(&b1).unwrap(), "Put(a, va)");
+        b2.delete(b"foo");
+        b1.append(&b2);
+        assert_eq!(print(&b1).unwrap(), "Put(a, va)Put(a, va)Delete(foo)");
+        assert_eq!(b1.count(), 3);
+        assert_eq!(b1.sequence(), 200);
+    }
+
+    #[test]
+    fn test_malformed_batch() {
+        assert!(matches!(WriteBatch::from_data(vec![0; WRITE_BATCH_HEADER_SIZE - 1]),
+            Err(DataStoreError::MalformedWriteBatch(_))));
+        let mut batch = WriteBatch::new();
+        batch.put(b"foo", b"bar");
+        batch.delete(b"box");
+        // Truncated record.
+        let mut data = batch.data().to_vec();
+        data.pop();
+        let truncated = WriteBatch::from_data(data).unwrap();
+        assert!(print(&truncated).is_err());
+        // Unknown tag.
+        let mut data = batch.data().to_vec();
+        data.push(0x7f);
+        assert!(print(&WriteBatch::from_data(data).unwrap()).is_err());
+        // Wrong count.
+        let mut data = batch.data().to_vec();
+        data[8] = 3;
+        assert!(print(&WriteBatch::from_data(data).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_insert_into_memtable() {
+        let memtable = MemTable::with_merge_operator(comparator(),
+            Arc::new(StringAppendOperator::new(b',')));
+        let mut batch = WriteBatch::new();
+        batch.put(b"a", b"va");
+        batch.put(b"b", b"vb");
+        batch.put(b"c", b"vc");
+        batch.merge(b"a", b"m1");
+        batch.delete(b"b");
+        batch.delete_range(b"b", b"d");
+        batch.merge(b"c", b"m2");
+        batch.set_sequence(10);
+        batch.insert_into(&memtable).unwrap();
+        let get = |key: &[u8], sequence| memtable.get(&LookupKey::new(key, sequence));
+        // Records get consecutive sequence numbers.
+        assert_eq!(get(b"a", 10), LookupResult::Found(b"va".to_vec()));
+        assert_eq!(get(b"a", 13), LookupResult::Found(b"va,m1".to_vec()));
+        assert_eq!(get(b"b", 13), LookupResult::Found(b"vb".to_vec()));
+        assert_eq!(get(b"b", 14), LookupResult::Deleted);
+        assert_eq!(get(b"c", 14), LookupResult::Found(b"vc".to_vec()));
+        assert_eq!(get(b"c", 15), LookupResult::Deleted);
+        // Merge on top of the range deletion.
+        assert_eq!(get(b"c", 16), LookupResult::Found(b"m2".to_vec()));
+        let mut iter = memtable.range_del_iter();
+        iter.seek_to_first();
+        let parsed = parse_internal_key(iter.key()).unwrap();
+        assert_eq!((parsed.user_key, parsed.sequence, parsed.value_type),
+            (&b"b"[..], 15, ValueType::RangeDeletion));
+        assert_eq!(iter.value(), b"d");
+    }
+
+    #[test]
+    fn test_insert_is_all_or_nothing() {
+        let memtable = MemTable::new(comparator());
+        let mut batch = WriteBatch::new();
+        batch.put(b"a", b"va");
+        batch.merge(b"a", b"m1");
+        batch.set_sequence(1);
+        assert!(matches!(batch.insert_into(&memtable),
+            Err(DataStoreError::NotSupported(_))));
+        let mut data = batch.data().to_vec();
+        data.truncate(data.len() - 1);
+        assert!(WriteBatch::from_data(data).unwrap().insert_into(&memtable).is_err());
+        assert_eq!(memtable.get(&LookupKey::new(b"a", 10)), LookupResult::NotFound);
+    }
+}